@@ -0,0 +1,95 @@
+//! A/B files: a variation played straight after its ideal performance, for
+//! comparing the two by ear.
+
+use fon::chan::Ch32;
+use fon::{Audio, Frame};
+use serde::{Deserialize, Serialize};
+use std::io;
+
+use super::wav::BitDepth;
+use super::{at_path, metronome, wav};
+
+/// Seconds between the reference and the variation of an A/B comparison.
+const AB_GAP_SECS: f32 = 1.0;
+
+/// A file to compare a variation with its ideal performance by ear.
+#[derive(Serialize, Deserialize)]
+pub(super) struct AbInfo {
+    filename: String,
+    /// Sample the click between the two starts on.
+    marker_sample: usize,
+    /// Sample `filename` starts on.
+    variation_sample: usize,
+}
+
+// Write `reference` then `variation` into `path`, with `AB_GAP_SECS` of
+// silence between them and a click halfway through the gap to mark the
+// change. Returns the samples the click and the variation start on.
+pub(super) fn generate_ab(
+    reference: &Audio<Ch32, 2>,
+    variation: &Audio<Ch32, 2>,
+    depth: BitDepth,
+    path: &str,
+) -> io::Result<AbInfo> {
+    let sample_rate = reference.sample_rate().get();
+    let mut frames: Vec<Frame<Ch32, 2>> = reference.iter().copied().collect();
+
+    let half_gap = (AB_GAP_SECS / 2.0 * sample_rate as f32).round() as usize;
+    frames.extend(std::iter::repeat_n(Frame::default(), half_gap));
+    let marker_sample = frames.len();
+    let mut second_half = Audio::with_silence(sample_rate, half_gap);
+    let click = metronome::generate_click(60.0 / AB_GAP_SECS, 1, sample_rate);
+    metronome::mix_in(&mut second_half, &click, 0.0);
+    frames.extend(second_half.iter().copied());
+
+    let variation_sample = frames.len();
+    frames.extend(variation.iter().copied());
+
+    let audio = Audio::<Ch32, 2>::with_frames(sample_rate, frames);
+    progress!("Writing {}", path);
+    wav::write(&audio, path, depth).map_err(at_path(path))?;
+    let filename = std::path::Path::new(path).file_name().unwrap_or_default();
+    Ok(AbInfo {
+        filename: filename.to_string_lossy().into_owned(),
+        marker_sample,
+        variation_sample,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_ab_file_is_the_reference_the_gap_then_the_variation() {
+        let tone = |secs: f32, freq: f32| {
+            let frames = (0..(secs * 8000.0) as usize).map(|i| {
+                let v = (i as f32 * freq * std::f32::consts::TAU / 8000.0).sin() * 0.5;
+                Frame::<Ch32, 2>::new(Ch32::new(v), Ch32::new(v))
+            });
+            Audio::<Ch32, 2>::with_frames(8000, frames.collect::<Vec<_>>())
+        };
+        let (reference, variation) = (tone(0.5, 440.0), tone(0.25, 330.0));
+        let path = std::env::temp_dir().join("piano_synth_test_ab.wav");
+        let path = path.to_str().unwrap();
+        let info = generate_ab(&reference, &variation, BitDepth::Int16, path).unwrap();
+
+        let gap = (AB_GAP_SECS * 8000.0) as usize;
+        assert_eq!(info.filename, "piano_synth_test_ab.wav");
+        assert_eq!(info.marker_sample, reference.len() + gap / 2);
+        assert_eq!(info.variation_sample, reference.len() + gap);
+        let audio = wav::read::<Ch32>(path).unwrap();
+        assert_eq!(audio.len(), reference.len() + gap + variation.len());
+
+        // Silence either side of the click, which is all the gap holds
+        let level = |from: usize, to: usize| {
+            use fon::chan::Channel;
+            let frames = audio.iter().skip(from).take(to - from);
+            frames.map(|frame| frame.channels()[0].to_f32().abs()).fold(0.0, f32::max)
+        };
+        assert!(level(reference.len(), info.marker_sample) < 1e-3);
+        assert!(level(info.marker_sample, info.marker_sample + 80) > 0.1);
+        assert!(level(info.marker_sample + gap / 4, info.variation_sample) < 1e-3);
+        assert!(level(info.variation_sample, audio.len()) > 0.4);
+    }
+}
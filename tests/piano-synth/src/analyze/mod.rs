@@ -0,0 +1,264 @@
+//! Measurements taken from rendered audio, for checking accuracy.
+
+use fon::Audio;
+use fon::chan::{Ch16, Channel};
+use fon::pos::{Left, Right};
+use serde::{Deserialize, Serialize};
+
+/// Windows quieter than this RMS are reported as silence (0.0 Hz).
+const SILENCE_RMS: f32 = 1e-3;
+
+/// YIN threshold on the normalized difference function.
+const YIN_THRESHOLD: f32 = 0.1;
+
+/// Floor for [`levels`], so silence still reports a finite level.
+const SILENCE_DBFS: f32 = -120.0;
+
+// Pitch search range in Hz
+const MIN_FREQ: f32 = 30.0;
+const MAX_FREQ: f32 = 4_000.0;
+
+/// Average the stereo channels into mono samples in -1.0..=1.0
+pub(super) fn to_mono(audio: &Audio<Ch16, 2>) -> Vec<f32> {
+    audio
+        .iter()
+        .map(|frame| {
+            let left = i16::from(frame[Left]) as f32;
+            let right = i16::from(frame[Right]) as f32;
+            (left + right) / 2.0 / 32_768.0
+        })
+        .collect()
+}
+
+/// Loudness of a whole buffer, relative to full scale.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub(super) struct Levels {
+    /// Loudest single sample.
+    pub(super) peak_dbfs: f32,
+    /// RMS over every sample of both channels; a full-scale sine is -3 dBFS.
+    pub(super) rms_dbfs: f32,
+}
+
+/// Peak and RMS level of `audio`, neither below [`SILENCE_DBFS`].
+pub(super) fn levels<Chan: Channel>(audio: &Audio<Chan, 2>) -> Levels {
+    let (mut peak, mut sum_sq) = (0.0f32, 0.0f64);
+    for sample in audio.iter().flat_map(|frame| frame.channels().iter().map(|s| s.to_f32())) {
+        peak = peak.max(sample.abs());
+        sum_sq += f64::from(sample) * f64::from(sample);
+    }
+    let rms = (sum_sq / (audio.len() * 2).max(1) as f64).sqrt() as f32;
+    let dbfs = |level: f32| (20.0 * level.log10()).max(SILENCE_DBFS);
+    Levels { peak_dbfs: dbfs(peak), rms_dbfs: dbfs(rms) }
+}
+
+/// Samples of either channel at or past full scale, where the signal was
+/// cut off rather than reproduced.
+pub(super) fn count_clipped_samples(audio: &Audio<Ch16, 2>) -> usize {
+    audio
+        .iter()
+        .flat_map(|frame| frame.channels().iter().map(|&s| i16::from(s)))
+        .filter(|s| s.unsigned_abs() >= i16::MAX as u16)
+        .count()
+}
+
+/// Estimate the fundamental frequency of each consecutive `window`-sample
+/// block, returning 0.0 for silent or unpitched blocks.
+///
+/// Uses the YIN autocorrelation method with parabolic interpolation.
+pub(super) fn detect_pitches(audio: &Audio<Ch16, 2>, window: usize) -> Vec<f32> {
+    let sample_rate = audio.sample_rate().get() as f32;
+    to_mono(audio)
+        .chunks_exact(window)
+        .map(|block| detect_pitch(block, sample_rate))
+        .collect()
+}
+
+fn detect_pitch(block: &[f32], sample_rate: f32) -> f32 {
+    let rms = (block.iter().map(|s| s * s).sum::<f32>() / block.len() as f32).sqrt();
+    if rms < SILENCE_RMS {
+        return 0.0;
+    }
+
+    let min_lag = (sample_rate / MAX_FREQ) as usize;
+    let max_lag = ((sample_rate / MIN_FREQ) as usize).min(block.len() / 2);
+    if min_lag + 2 >= max_lag {
+        return 0.0;
+    }
+
+    // Difference function d(tau) over the first half of the block
+    let span = block.len() - max_lag;
+    let diff = |lag: usize| -> f32 {
+        (0..span)
+            .map(|i| {
+                let d = block[i] - block[i + lag];
+                d * d
+            })
+            .sum()
+    };
+
+    // Cumulative mean normalized difference d'(tau), up to the first dip
+    // under the threshold followed to its local minimum (and one lag past
+    // it, for the parabola below); that dip is the period, so the longer
+    // lags, the bulk of the work, are only needed when there is none
+    let mut cmnd = vec![1.0; max_lag + 1];
+    let mut running = 0.0;
+    let mut dip = None;
+    for lag in 1..=max_lag {
+        let diff = diff(lag);
+        running += diff;
+        cmnd[lag] = if running > 0.0 { diff * lag as f32 / running } else { 1.0 };
+        match dip {
+            Some(found) if lag < max_lag && cmnd[lag] < cmnd[found] => dip = Some(lag),
+            Some(_) => break,
+            None if lag >= min_lag && lag < max_lag && cmnd[lag] < YIN_THRESHOLD => dip = Some(lag),
+            None => {}
+        }
+    }
+
+    // Otherwise the global minimum
+    let lag = dip.unwrap_or_else(|| {
+        (min_lag..max_lag)
+            .min_by(|&a, &b| cmnd[a].total_cmp(&cmnd[b]))
+            .unwrap_or(min_lag)
+    });
+
+    // Refine the lag between samples with a parabola through its neighbours
+    let (a, b, c) = (cmnd[lag - 1], cmnd[lag], cmnd[lag + 1]);
+    let denom = a - 2.0 * b + c;
+    let offset = if denom.abs() > f32::EPSILON { 0.5 * (a - c) / denom } else { 0.0 };
+
+    sample_rate / (lag as f32 + offset)
+}
+
+/// Tuning for [`detect_onsets_with`].
+#[derive(Clone, Copy)]
+pub(super) struct OnsetConfig {
+    /// Minimum rise in frame RMS, as a fraction of the loudest frame's RMS.
+    pub(super) threshold: f32,
+    /// Onsets closer than this (seconds) to the previous one are dropped.
+    pub(super) min_gap: f32,
+    /// Samples between analysis frames.
+    pub(super) hop: usize,
+    /// Samples per analysis frame; longer than `hop` so low notes are smoothed.
+    pub(super) frame: usize,
+}
+
+impl Default for OnsetConfig {
+    fn default() -> Self {
+        Self { threshold: 0.1, min_gap: 0.05, hop: 256, frame: 1024 }
+    }
+}
+
+/// Find note onsets, in seconds, using the default [`OnsetConfig`].
+pub(super) fn detect_onsets(audio: &Audio<Ch16, 2>) -> Vec<f32> {
+    detect_onsets_with(audio, OnsetConfig::default())
+}
+
+/// Find note onsets, in seconds, as peaks in the rise of the energy envelope.
+pub(super) fn detect_onsets_with(audio: &Audio<Ch16, 2>, config: OnsetConfig) -> Vec<f32> {
+    let sample_rate = audio.sample_rate().get() as f32;
+    let samples = to_mono(audio);
+    let rms: Vec<f32> = (0..samples.len())
+        .step_by(config.hop)
+        .map(|start| {
+            let frame = &samples[start..(start + config.frame).min(samples.len())];
+            (frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32).sqrt()
+        })
+        .collect();
+    let peak = rms.iter().cloned().fold(0.0, f32::max);
+    if peak <= 0.0 {
+        return Vec::new();
+    }
+
+    // Positive energy rise per frame, relative to the loudest frame
+    let rise: Vec<f32> = (0..rms.len())
+        .map(|i| {
+            let prev = if i == 0 { 0.0 } else { rms[i - 1] };
+            (rms[i] - prev).max(0.0) / peak
+        })
+        .collect();
+
+    let mut onsets: Vec<f32> = Vec::new();
+    for i in 0..rise.len() {
+        let prev = if i == 0 { 0.0 } else { rise[i - 1] };
+        let next = rise.get(i + 1).copied().unwrap_or(0.0);
+        if rise[i] < config.threshold || rise[i] < prev || rise[i] <= next {
+            continue;
+        }
+        // The rise peaks once the attack is well inside the frame
+        let time = (i * config.hop + (config.frame - config.hop) / 2) as f32 / sample_rate;
+        if onsets.last().is_none_or(|&last| time - last >= config.min_gap) {
+            onsets.push(time);
+        }
+    }
+    onsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fon::Frame;
+    use fon::chan::Ch32;
+    use std::f32::consts::TAU;
+
+    fn tone(freq: f32, secs: f32, sample_rate: u32) -> Audio<Ch16, 2> {
+        let frames = (0..(secs * sample_rate as f32) as usize)
+            .map(|i| {
+                let sample = Ch16::from(0.5 * (TAU * freq * i as f32 / sample_rate as f32).sin());
+                Frame::<Ch16, 2>::new(sample, sample)
+            })
+            .collect::<Vec<_>>();
+        Audio::with_frames(sample_rate, frames)
+    }
+
+    #[test]
+    fn a_pure_tone_is_heard_at_its_pitch() {
+        for freq in [110.0, 261.63, 440.0, 1_760.0] {
+            let pitches = detect_pitches(&tone(freq, 0.5, 48_000), 2048);
+            assert_eq!(pitches.len(), 24_000 / 2048);
+            for heard in pitches {
+                assert!((heard - freq).abs() < freq * 0.002, "{} Hz heard as {}", freq, heard);
+            }
+        }
+    }
+
+    #[test]
+    fn silence_has_no_pitch_and_no_onsets() {
+        let silence = Audio::<Ch16, 2>::with_silence(48_000, 48_000);
+        assert!(detect_pitches(&silence, 2048).iter().all(|&heard| heard == 0.0));
+        assert!(detect_onsets(&silence).is_empty());
+    }
+
+    #[test]
+    fn a_full_scale_sine_peaks_at_zero_and_averages_three_below() {
+        let frames = (0..48_000)
+            .map(|i| {
+                let sample = Ch32::new((TAU * 440.0 * i as f32 / 48_000.0).sin());
+                Frame::<Ch32, 2>::new(sample, sample)
+            })
+            .collect::<Vec<_>>();
+        let full = levels(&Audio::with_frames(48_000, frames));
+        assert!(full.peak_dbfs.abs() < 0.01, "{}", full.peak_dbfs);
+        assert!((full.rms_dbfs + 3.01).abs() < 0.02, "{}", full.rms_dbfs);
+        // Half scale is 6 dB down on both
+        let half = levels(&tone(440.0, 1.0, 48_000));
+        assert!((half.peak_dbfs + 6.02).abs() < 0.01, "{}", half.peak_dbfs);
+        assert!((half.rms_dbfs + 9.03).abs() < 0.02, "{}", half.rms_dbfs);
+    }
+
+    #[test]
+    fn every_sample_driven_past_full_scale_is_counted() {
+        // Ten left samples over the top, five right ones under the bottom,
+        // and one exactly at full scale; the rest well inside
+        let frames = (0..100)
+            .map(|i| {
+                let left = if i < 10 { 1.5 } else if i == 50 { 1.0 } else { 0.5 };
+                let right = if (20..25).contains(&i) { -1.5 } else { -0.5 };
+                Frame::<Ch32, 2>::new(Ch32::new(left), Ch32::new(right))
+            })
+            .collect::<Vec<_>>();
+        let overdriven = Audio::<Ch16, 2>::with_audio(48_000, &Audio::with_frames(48_000, frames));
+        assert_eq!(count_clipped_samples(&overdriven), 16);
+        assert_eq!(count_clipped_samples(&tone(440.0, 0.5, 48_000)), 0);
+    }
+}
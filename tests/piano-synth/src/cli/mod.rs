@@ -0,0 +1,489 @@
+//! Command-line arguments: which songs and variations to generate.
+
+use super::config;
+use super::wav::BitDepth;
+
+pub(super) const USAGE: &str = "\
+Usage: piano-synth [--song NAME]... [--variation NAME]... [--summary FILE]
+                   [--spectrograms] [--csv] [--ab] [--calibration]
+                   [--seed N] [--trim DBFS]
+                   [--sample-rate HZ] [--bit-depth 16|24|float]
+                   [--velocity-curve exponential|linear|square]
+                   [--import FILE]...
+       piano-synth --config FILE [OPTION]...
+       piano-synth --list | --help
+       piano-synth --medley [--song NAME]... [--sample-rate HZ] [--bit-depth D]
+                   [--config FILE]
+       piano-synth --ladder [--song NAME]... [--sample-rate HZ] [--bit-depth D]
+                   [--config FILE]
+       piano-synth --stdout --song NAME --variation NAME [--seed N]
+                   [--sample-rate HZ] [--bit-depth D] [--config FILE]
+
+With no arguments every song and variation is generated, along with
+available_tests.json. --song and --variation may be repeated; the JSON manifest
+is only rewritten when nothing is filtered out. A table of each file's expected
+accuracy is printed at the end, and also written to FILE with --summary.
+--spectrograms draws each file's spectrogram next to it, as NAME.png.
+--csv logs each song's expected melody pitch every 10 ms, with its note name,
+to target_music/SONG_pitch.csv.
+--ab writes each variation right after the ideal performance it is scored
+against, a click marking the change, as NAME_ab.wav.
+--calibration writes an A4 held for a second in each timbre, with nothing else
+in the file, as target_music/calibration_TIMBRE.wav.
+--trim DBFS cuts each file's leading and trailing audio quieter than DBFS
+(e.g. -60), moving the sample positions in the manifest to match.
+--config FILE reads the songs, variations, tempo, sample rate, bit depth,
+velocity curve and output directory to use from a JSON object with the fields
+songs, variations, tempo_bpm, sample_rate, bit_depth, velocity_curve and
+output_dir, any of which may be left out; the other options still apply.
+The output directory holds target_music for --medley and --ladder as well, but
+they play each song at its own tempo, so their config cannot give one.
+--seed N redraws every random choice (wrong notes, jitter, noise and the rest)
+from N instead of 0; the seed is recorded in available_tests.json.
+--import FILE adds the song in FILE, named after it, to the ones to choose
+from: a MIDI file (.mid), one part per track, or one melody in the plain-text
+notation, a note name and value per line (such as \"E5 16\"). Notes that
+overlap within a MIDI track are read as the highest of them.
+--sample-rate HZ renders at HZ frames per second (8000 to 192000) instead of
+48000; the rate is recorded in available_tests.json.
+--bit-depth 16|24|float writes 16-bit (the default) or 24-bit PCM, or 32-bit
+float; the mono copies stay 16-bit.
+--velocity-curve exponential|linear|square sets how each note's velocity maps
+to its amplitude: falling away like a struck string's (the default), equal to
+it, or its square; the curve is recorded in available_tests.json. Full
+velocity sounds the same under each, but softer notes are quieter by default
+than they were before the curve could be chosen: 0.6, as in flat_dynamics,
+plays at about 0.31 of full rather than 0.6. Pass --velocity-curve linear for
+the old levels.
+
+--medley instead plays the chosen songs (all by default) one after another in
+target_music/medley.wav, and writes where each begins to
+target_music/medley.json.
+
+--ladder plays each chosen song five times in target_music/NAME_ladder.wav,
+from 60% of its tempo up to full speed, and writes each pass's tempo and
+starting sample to target_music/ladder.json.
+
+--stdout renders a single file in memory and writes it to stdout as a WAV, for
+piping into aplay or ffmpeg, with nothing written to disk; progress messages
+then go to stderr.";
+
+/// What the program was asked to do.
+pub(super) enum Command {
+    Generate(Selection, Outputs),
+    /// Play the selected songs back to back; no variations are chosen.
+    Medley(Selection),
+    /// Play each selected song at rising tempos; no variations are chosen.
+    Ladder(Selection),
+    /// Generate one song's variation and write the WAV to stdout.
+    Stream(Selection),
+    List,
+    Help,
+}
+
+impl Command {
+    /// The songs and variations chosen, for a command that generates any.
+    pub(super) fn selection(&self) -> Option<&Selection> {
+        match self {
+            Command::Generate(selection, _)
+            | Command::Medley(selection)
+            | Command::Ladder(selection)
+            | Command::Stream(selection) => Some(selection),
+            Command::List | Command::Help => None,
+        }
+    }
+}
+
+/// What to write besides the files themselves and the manifest.
+#[derive(Default)]
+pub(super) struct Outputs {
+    /// Where to write the summary table as well as printing it.
+    pub(super) summary: Option<String>,
+    /// Draw a spectrogram PNG of every file generated.
+    pub(super) spectrograms: bool,
+    /// Write each song's melody pitch over time as a CSV.
+    pub(super) csv: bool,
+    /// Write every variation after its ideal performance, to compare by ear.
+    pub(super) ab: bool,
+    /// Write a single note in each timbre, for checking the analyzers.
+    pub(super) calibration: bool,
+    /// Cut each file's ends quieter than this many dBFS.
+    pub(super) trim_dbfs: Option<f32>,
+}
+
+impl Outputs {
+    // Whether any flag beyond the song and variation choices was given
+    fn any(&self) -> bool {
+        self.summary.is_some()
+            || self.spectrograms
+            || self.csv
+            || self.ab
+            || self.calibration
+            || self.trim_dbfs.is_some()
+    }
+}
+
+/// Songs and variations to generate; an empty list means all of them.
+#[derive(Clone, Default)]
+pub(super) struct Selection {
+    songs: Vec<String>,
+    variations: Vec<String>,
+    seed: u64,
+    tempo_bpm: Option<f32>,
+    sample_rate: Option<u32>,
+    bit_depth: BitDepth,
+    velocity_curve: Option<&'static str>,
+    imports: Vec<String>,
+    output_dir: Option<String>,
+}
+
+impl Selection {
+    pub(super) fn wants_song(&self, song: &str) -> bool {
+        self.songs.is_empty() || self.songs.iter().any(|s| s == song)
+    }
+
+    pub(super) fn wants(&self, variation: &str) -> bool {
+        self.variations.is_empty() || self.variations.iter().any(|v| v == variation)
+    }
+
+    /// What the randomized variations draw from; 0 unless `--seed` is given.
+    pub(super) fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Tempo to play every song at, if `--config` gives one.
+    pub(super) fn tempo_bpm(&self) -> Option<f32> {
+        self.tempo_bpm
+    }
+
+    /// Frames per second to render at; 48 kHz unless `--sample-rate` or
+    /// `--config` gives another.
+    pub(super) fn sample_rate(&self) -> u32 {
+        self.sample_rate.unwrap_or(super::SAMPLE_RATE)
+    }
+
+    /// Sample encoding of the files written; 16-bit unless `--bit-depth` or
+    /// `--config` gives another.
+    pub(super) fn bit_depth(&self) -> BitDepth {
+        self.bit_depth
+    }
+
+    /// Name of the velocity curve to render with, from `VELOCITY_CURVES`;
+    /// the first unless `--velocity-curve` or `--config` gives another.
+    pub(super) fn velocity_curve(&self) -> &'static str {
+        self.velocity_curve.unwrap_or(super::VELOCITY_CURVES[0].0)
+    }
+
+    /// The same choices, but of `variation` alone.
+    pub(super) fn only(&self, variation: &str) -> Selection {
+        Selection { variations: vec![variation.to_string()], ..self.clone() }
+    }
+
+    /// Files given with `--import`, whose songs [`import_title`] names.
+    pub(super) fn imports(&self) -> &[String] {
+        &self.imports
+    }
+
+    /// Where `name` is written: under the output directory `--config` gives,
+    /// or as it is when it gives none. The summary's path is relative to it
+    /// too.
+    pub(super) fn under(&self, name: &str) -> String {
+        match &self.output_dir {
+            Some(dir) => format!("{}/{}", dir, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Whether nothing is filtered out.
+    pub(super) fn is_everything(&self) -> bool {
+        self.songs.is_empty() && self.variations.is_empty()
+    }
+}
+
+// The name in `VELOCITY_CURVES` that `name` spells
+fn velocity_curve(name: &str) -> Result<&'static str, String> {
+    let names: Vec<&'static str> = super::VELOCITY_CURVES.iter().map(|&(known, _)| known).collect();
+    let found = names.iter().find(|&&known| known == name).copied();
+    found.ok_or_else(|| format!("unknown velocity curve '{}', expected {}", name, names.join(", ")))
+}
+
+/// What `--import path` calls the song in `path`: its file name without the
+/// extension.
+pub(super) fn import_title(path: &str) -> String {
+    let stem = std::path::Path::new(path).file_stem().and_then(|stem| stem.to_str());
+    stem.unwrap_or(path).to_string()
+}
+
+// Add `name` to the `chosen` songs or variations if it is one of `known`
+fn choose(kind: &str, known: &[&str], name: &str, chosen: &mut Vec<String>) -> Result<(), String> {
+    if !known.contains(&name) {
+        return Err(format!("unknown {} '{}'", kind, name));
+    }
+    chosen.push(name.to_string());
+    Ok(())
+}
+
+/// Parse the arguments after the program name, checking names against the
+/// known `songs`, with any imported ones, and `variations`.
+pub(super) fn parse(
+    args: &[String],
+    songs: &[&str],
+    variations: &[&str],
+) -> Result<Command, String> {
+    let mut selection = Selection::default();
+    let mut medley = false;
+    let mut ladder = false;
+    let mut stdout = false;
+    let mut seeded = false;
+    let mut outputs = Outputs::default();
+    // Checked once every `--import` is known, since they add songs
+    let mut chosen_songs = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--list" => return Ok(Command::List),
+            "--help" | "-h" => return Ok(Command::Help),
+            "--medley" => medley = true,
+            "--ladder" => ladder = true,
+            "--stdout" => stdout = true,
+            "--summary" => {
+                let path = args.next().ok_or("--summary needs a file name")?;
+                outputs.summary = Some(path.clone());
+            }
+            "--spectrograms" => outputs.spectrograms = true,
+            "--csv" => outputs.csv = true,
+            "--ab" => outputs.ab = true,
+            "--calibration" => outputs.calibration = true,
+            "--trim" => {
+                let value = args.next().ok_or("--trim needs a level in dBFS")?;
+                let level = value.parse().ok().filter(|&db: &f32| db <= 0.0);
+                let level =
+                    level.ok_or_else(|| format!("--trim needs 0 dBFS or below, not '{}'", value))?;
+                outputs.trim_dbfs = Some(level);
+            }
+            "--seed" => {
+                let value = args.next().ok_or("--seed needs a number")?;
+                let number = value.parse();
+                selection.seed =
+                    number.map_err(|_| format!("--seed must be a whole number, not '{}'", value))?;
+                seeded = true;
+            }
+            "--sample-rate" => {
+                let value = args.next().ok_or("--sample-rate needs a rate in Hz")?;
+                let rate = value.parse().ok().filter(|hz| config::SAMPLE_RATES.contains(hz));
+                let rate = rate.ok_or_else(|| {
+                    format!("--sample-rate needs 8000 to 192000 Hz, not '{}'", value)
+                })?;
+                selection.sample_rate = Some(rate);
+            }
+            "--bit-depth" => {
+                let value = args.next().ok_or("--bit-depth needs 16, 24 or float")?;
+                selection.bit_depth = BitDepth::from_name(value)
+                    .ok_or_else(|| format!("--bit-depth needs 16, 24 or float, not '{}'", value))?;
+            }
+            "--import" => {
+                let path = args.next().ok_or("--import needs a file name")?;
+                selection.imports.push(path.clone());
+            }
+            "--velocity-curve" => {
+                let value = args.next().ok_or("--velocity-curve needs a curve name")?;
+                selection.velocity_curve = Some(velocity_curve(value)?);
+            }
+            "--song" => {
+                let value = args.next().ok_or("--song needs a name")?;
+                chosen_songs.push(value.clone());
+            }
+            "--variation" => {
+                let value = args.next().ok_or("--variation needs a name")?;
+                choose("variation", variations, value, &mut selection.variations)?;
+            }
+            "--config" => {
+                let path = args.next().ok_or("--config needs a file name")?;
+                let config = config::read(path).map_err(|err| format!("{}: {}", path, err))?;
+                for song in &config.songs {
+                    chosen_songs.push(song.clone());
+                }
+                for variation in &config.variations {
+                    choose("variation", variations, variation, &mut selection.variations)?;
+                }
+                selection.tempo_bpm = config.tempo_bpm;
+                selection.sample_rate = config.sample_rate.or(selection.sample_rate);
+                selection.bit_depth = config.bit_depth.unwrap_or(selection.bit_depth);
+                if let Some(name) = &config.velocity_curve {
+                    selection.velocity_curve = Some(velocity_curve(name)?);
+                }
+                selection.output_dir = config.output_dir;
+            }
+            _ => return Err(format!("unexpected argument '{}'", arg)),
+        }
+    }
+    let mut known = songs.to_vec();
+    let imported: Vec<String> = selection.imports.iter().map(|path| import_title(path)).collect();
+    for (path, title) in selection.imports.iter().zip(&imported) {
+        if known.contains(&title.as_str()) {
+            return Err(format!("--import {}: there is already a song called '{}'", path, title));
+        }
+        known.push(title);
+    }
+    for song in &chosen_songs {
+        choose("song", &known, song, &mut selection.songs)?;
+    }
+    if stdout {
+        if medley || ladder || outputs.any() {
+            return Err("--stdout only writes the WAV itself, with no other outputs".to_string());
+        }
+        if selection.songs.len() != 1 || selection.variations.len() != 1 {
+            return Err("--stdout needs exactly one --song and one --variation".to_string());
+        }
+        return Ok(Command::Stream(selection));
+    }
+    if !medley && !ladder {
+        return Ok(Command::Generate(selection, outputs));
+    }
+    let mode = if medley { "--medley" } else { "--ladder" };
+    if medley && ladder {
+        return Err("--medley cannot be combined with --ladder".to_string());
+    }
+    if !selection.variations.is_empty() || seeded {
+        return Err(format!("{} cannot be combined with --variation or --seed", mode));
+    }
+    if selection.tempo_bpm.is_some() {
+        return Err(format!("{} plays each song at its own tempo, not the config's", mode));
+    }
+    if outputs.any() {
+        let others = "--summary, --spectrograms, --csv, --ab, --calibration or --trim";
+        return Err(format!("{} cannot be combined with {}", mode, others));
+    }
+    Ok(if medley { Command::Medley(selection) } else { Command::Ladder(selection) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SONGS: &[&str] = &["twinkle", "ode_to_joy"];
+    const VARIATIONS: &[&str] = &["clean", "fast"];
+
+    fn parse_args(args: &str) -> Result<Command, String> {
+        let args: Vec<String> = args.split_whitespace().map(str::to_string).collect();
+        parse(&args, SONGS, VARIATIONS)
+    }
+
+    fn rejects(args: &str, message: &str) {
+        match parse_args(args) {
+            Err(err) => assert!(err.contains(message), "'{}': {}", args, err),
+            Ok(_) => panic!("accepted '{}'", args),
+        }
+    }
+
+    #[test]
+    fn no_arguments_generate_everything() {
+        let Ok(Command::Generate(selection, outputs)) = parse_args("") else {
+            panic!("expected a normal run");
+        };
+        assert!(selection.is_everything() && !outputs.any());
+        assert_eq!(selection.seed(), 0);
+        assert_eq!(selection.sample_rate(), crate::SAMPLE_RATE);
+        assert_eq!(selection.bit_depth(), BitDepth::Int16);
+        assert_eq!(selection.velocity_curve(), "exponential");
+    }
+
+    #[test]
+    fn choices_and_outputs_are_collected() {
+        let args = "--song twinkle --variation fast --variation clean --seed 7 --trim -60 \
+                    --summary table.txt --csv --ab --sample-rate 24000 --bit-depth 24 \
+                    --velocity-curve square";
+        let Ok(Command::Generate(selection, outputs)) = parse_args(args) else {
+            panic!("expected a normal run");
+        };
+        assert!(selection.wants_song("twinkle") && !selection.wants_song("ode_to_joy"));
+        assert!(selection.wants("fast") && selection.wants("clean"));
+        assert!(!selection.is_everything());
+        assert_eq!(selection.seed(), 7);
+        assert_eq!(selection.sample_rate(), 24_000);
+        assert_eq!(selection.bit_depth(), BitDepth::Int24);
+        assert_eq!(selection.velocity_curve(), "square");
+        assert_eq!(outputs.trim_dbfs, Some(-60.0));
+        assert_eq!(outputs.summary.as_deref(), Some("table.txt"));
+        assert!(outputs.csv && outputs.ab && !outputs.spectrograms);
+    }
+
+    #[test]
+    fn modes_are_recognized() {
+        assert!(matches!(parse_args("--list"), Ok(Command::List)));
+        assert!(matches!(parse_args("--song twinkle -h"), Ok(Command::Help)));
+        assert!(matches!(parse_args("--medley --song twinkle"), Ok(Command::Medley(_))));
+        assert!(matches!(parse_args("--ladder --sample-rate 8000"), Ok(Command::Ladder(_))));
+        let stream = "--stdout --song twinkle --variation fast --seed 3 --bit-depth float";
+        assert!(matches!(parse_args(stream), Ok(Command::Stream(_))));
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        rejects("--song waltz", "unknown song 'waltz'");
+        rejects("--variation slow", "unknown variation 'slow'");
+        rejects("--seed", "--seed needs a number");
+        rejects("--seed -1", "whole number");
+        rejects("--trim 3", "0 dBFS or below");
+        rejects("--sample-rate 4000", "8000 to 192000");
+        rejects("--bit-depth 32", "16, 24 or float");
+        rejects("--velocity-curve cubic", "expected exponential, linear, square");
+        rejects("--summary", "--summary needs a file name");
+        rejects("--loud", "unexpected argument '--loud'");
+        rejects("--import", "--import needs a file name");
+        rejects("--import songs/twinkle.mid", "already a song called 'twinkle'");
+    }
+
+    #[test]
+    fn conflicting_modes_are_rejected() {
+        rejects("--medley --ladder", "cannot be combined with --ladder");
+        rejects("--medley --variation fast", "cannot be combined with --variation");
+        rejects("--ladder --seed 2", "cannot be combined with --variation or --seed");
+        rejects("--ladder --csv", "cannot be combined with --summary");
+        rejects("--medley --ab", "cannot be combined with --summary");
+        rejects("--stdout --song twinkle --variation fast --calibration", "only writes the WAV");
+        rejects("--stdout --song twinkle", "exactly one --song and one --variation");
+        rejects("--stdout --song twinkle --variation fast --csv", "only writes the WAV");
+        rejects("--stdout --medley --song twinkle --variation fast", "only writes the WAV");
+    }
+
+    #[test]
+    fn an_imported_song_can_be_chosen() {
+        let Ok(Command::Stream(selection)) =
+            parse_args("--stdout --song waltz --variation fast --import music/waltz.mid")
+        else {
+            panic!("expected a stream");
+        };
+        assert_eq!(selection.imports(), ["music/waltz.mid"]);
+        assert!(selection.wants_song("waltz") && !selection.wants_song("twinkle"));
+        assert_eq!(import_title("notes.txt"), "notes");
+    }
+
+    #[test]
+    fn a_config_plans_every_mode() {
+        let dir = std::env::temp_dir();
+        let (path, timed) = (dir.join("cli_test_config.json"), dir.join("cli_test_timed.json"));
+        let plan = r#"{ "songs": ["twinkle"], "sample_rate": 32000, "output_dir": "out" }"#;
+        std::fs::write(&path, plan).unwrap();
+        std::fs::write(&timed, r#"{ "tempo_bpm": 90 }"#).unwrap();
+        let (path, timed) = (path.to_str().unwrap(), timed.to_str().unwrap());
+        let run = parse_args(&format!("--config {}", path));
+        let medley = parse_args(&format!("--config {} --medley", path));
+        let conflict = parse_args(&format!("--config {} --ladder", timed));
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(timed).unwrap();
+
+        let Ok(Command::Generate(selection, _)) = run else {
+            panic!("expected a normal run");
+        };
+        assert!(selection.wants_song("twinkle") && !selection.wants_song("ode_to_joy"));
+        assert_eq!(selection.sample_rate(), 32_000);
+        assert_eq!(selection.under("target_music"), "out/target_music");
+        let Ok(Command::Medley(selection)) = medley else {
+            panic!("expected a medley");
+        };
+        assert_eq!(selection.under("target_music"), "out/target_music");
+        assert!(conflict.is_err_and(|err| err.contains("at its own tempo")));
+    }
+}
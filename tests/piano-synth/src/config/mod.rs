@@ -0,0 +1,121 @@
+//! Build plans read from a JSON file, for choosing what to generate without
+//! spelling every flag out on the command line.
+//!
+//! A plan such as
+//!
+//! ```json
+//! { "songs": ["twinkle", "ode_to_joy"], "variations": ["fast"],
+//!   "tempo_bpm": 80, "sample_rate": 44100, "bit_depth": "24",
+//!   "velocity_curve": "exponential", "output_dir": "fixtures" }
+//! ```
+//!
+//! may leave out any field; every song and variation is built by default.
+
+use super::wav::BitDepth;
+use serde::{Deserialize, Deserializer, de};
+use std::{fs, io, ops::RangeInclusive};
+
+/// Sample rates a render may be asked for, in Hz.
+pub(super) const SAMPLE_RATES: RangeInclusive<u32> = 8_000..=192_000;
+
+/// What one run should build.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub(super) struct Config {
+    /// Song titles, as `--song` takes them; empty for all of them.
+    pub(super) songs: Vec<String>,
+    /// Variation names, as `--variation` takes them; empty for all of them.
+    pub(super) variations: Vec<String>,
+    /// Tempo (quarter notes per minute) to play every song at, in place of
+    /// the one it is written at.
+    pub(super) tempo_bpm: Option<f32>,
+    /// Frames per second to render at, in place of 48 kHz.
+    pub(super) sample_rate: Option<u32>,
+    /// Sample encoding, as `--bit-depth` takes it: "16", "24" or "float".
+    #[serde(deserialize_with = "bit_depth")]
+    pub(super) bit_depth: Option<BitDepth>,
+    /// How velocity maps to amplitude, as `--velocity-curve` names it.
+    pub(super) velocity_curve: Option<String>,
+    /// Directory to write `target_music/` and the manifest under, created if
+    /// it is missing.
+    pub(super) output_dir: Option<String>,
+}
+
+// A bit depth named as `--bit-depth` takes it
+fn bit_depth<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<BitDepth>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    let depth = BitDepth::from_name(&name);
+    let message = || format!("unknown bit depth '{}', expected \"16\", \"24\" or \"float\"", name);
+    depth.map(Some).ok_or_else(|| de::Error::custom(message()))
+}
+
+/// Read and parse the plan at `path`.
+pub(super) fn read(path: &str) -> io::Result<Config> {
+    parse(&fs::read_to_string(path)?)
+}
+
+// Parse a plan, rejecting a tempo no song could be played at or a sample
+// rate outside `SAMPLE_RATES`
+fn parse(text: &str) -> io::Result<Config> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let config: Config = serde_json::from_str(text).map_err(|err| invalid(err.to_string()))?;
+    if let Some(bpm) = config.tempo_bpm.filter(|&bpm| bpm <= 0.0 || !bpm.is_finite()) {
+        return Err(invalid(format!("a tempo of {} bpm can't be played", bpm)));
+    }
+    if let Some(hz) = config.sample_rate.filter(|hz| !SAMPLE_RATES.contains(hz)) {
+        return Err(invalid(format!("a sample rate of {} Hz is out of range", hz)));
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_field_may_be_left_out() {
+        let config = parse("{}").unwrap();
+        assert!(config.songs.is_empty() && config.variations.is_empty());
+        assert!(config.tempo_bpm.is_none() && config.output_dir.is_none());
+        assert!(config.sample_rate.is_none() && config.bit_depth.is_none());
+
+        let config = parse(r#"{ "songs": ["twinkle"], "tempo_bpm": 80 }"#).unwrap();
+        assert_eq!(config.songs, ["twinkle"]);
+        assert_eq!(config.tempo_bpm, Some(80.0));
+        assert!(config.variations.is_empty() && config.output_dir.is_none());
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let err = parse(r#"{ "song": ["twinkle"] }"#).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("unknown field `song`"), "{}", err);
+    }
+
+    #[test]
+    fn a_tempo_must_be_playable() {
+        for bad in ["0", "-60", "\"fast\""] {
+            let text = format!(r#"{{ "tempo_bpm": {} }}"#, bad);
+            assert!(parse(&text).is_err(), "accepted a tempo of {}", bad);
+        }
+    }
+
+    #[test]
+    fn a_sample_rate_must_be_in_range() {
+        assert_eq!(parse(r#"{ "sample_rate": 24000 }"#).unwrap().sample_rate, Some(24_000));
+        for bad in ["0", "4000", "384000", "-48000", "44100.5"] {
+            let text = format!(r#"{{ "sample_rate": {} }}"#, bad);
+            assert!(parse(&text).is_err(), "accepted a sample rate of {}", bad);
+        }
+    }
+
+    #[test]
+    fn a_bit_depth_is_named_as_the_flag_takes_it() {
+        let config = parse(r#"{ "bit_depth": "float" }"#).unwrap();
+        assert_eq!(config.bit_depth, Some(BitDepth::Float32));
+        for bad in ["\"32\"", "24", "\"Float\""] {
+            let text = format!(r#"{{ "bit_depth": {} }}"#, bad);
+            assert!(parse(&text).is_err(), "accepted a bit depth of {}", bad);
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Comma-separated pitch logs, for plotting the ground truth in a
+//! spreadsheet or notebook without parsing the JSON.
+//!
+//! https://www.rfc-editor.org/rfc/rfc4180
+
+use super::pitch::{NAME_TOLERANCE_CENTS, spell};
+use super::score::merge_tracks;
+use std::{fs, io};
+
+/// Write the frequency `track` should sound at every `interval` seconds, from
+/// 0.0 until the track ends, as `time_s,expected_freq,note_name` rows under
+/// that header. Rests are 0.0 Hz, named "Rest"; black keys are spelled for
+/// `key`.
+pub(super) fn write_pitch_log(
+    track: &[(f32, f32)],
+    interval: f32,
+    key: Option<&str>,
+    path: &str,
+) -> io::Result<()> {
+    if interval.is_nan() || interval <= 0.0 {
+        let message = format!("a pitch log can't be sampled every {} s", interval);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+    let segments = merge_tracks(&[track]);
+    let end = segments.last().map_or(0.0, |&(_, end, _)| end);
+    let rows = (end / interval).ceil() as usize;
+
+    let mut csv = String::from("time_s,expected_freq,note_name\n");
+    let mut segment = 0;
+    for row in 0..rows {
+        // Counted from zero each row, so the times never drift
+        let time = row as f32 * interval;
+        while segment + 1 < segments.len() && time >= segments[segment].1 {
+            segment += 1;
+        }
+        let freq = segments[segment].2.first().copied().unwrap_or(0.0);
+        let name = spell(freq, NAME_TOLERANCE_CENTS, key);
+        csv.push_str(&format!("{:.3},{:.2},{}\n", time, freq, name));
+    }
+    fs::write(path, csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn a_row_is_written_every_interval_until_the_track_ends() {
+        let track = [(440.0, 0.5), (0.0, 0.25), (466.16, 0.25)];
+        let dir = TempDir::new("pitch_log");
+        let path = &dir.path("pitch_log.csv");
+        write_pitch_log(&track, 0.25, Some("F major"), path).unwrap();
+        let rows = fs::read_to_string(path).unwrap();
+        let expected = "time_s,expected_freq,note_name\n\
+                        0.000,440.00,A4\n\
+                        0.250,440.00,A4\n\
+                        0.500,0.00,Rest\n\
+                        0.750,466.16,Bb4\n";
+        assert_eq!(rows, expected);
+    }
+
+    #[test]
+    fn the_interval_must_be_positive() {
+        for interval in [0.0, -0.01, f32::NAN] {
+            let err = write_pitch_log(&[(440.0, 1.0)], interval, None, "unused.csv").unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
@@ -0,0 +1,353 @@
+//! Post-processing effects applied to a rendered buffer.
+
+use super::rng::Rng;
+use fon::{Audio, Frame};
+use fon::chan::Channel;
+
+/// Comb filter delays in samples at 44.1 kHz (Freeverb tunings).
+const COMB_DELAYS: [usize; 4] = [1116, 1188, 1277, 1356];
+
+/// Allpass filter delays in samples at 44.1 kHz.
+const ALLPASS_DELAYS: [usize; 2] = [556, 441];
+const ALLPASS_GAIN: f32 = 0.5;
+
+/// Extra delay on the right channel so the two sides decorrelate.
+const STEREO_SPREAD: usize = 23;
+
+/// Settings for [`reverb`].
+#[derive(Clone, Copy)]
+pub(super) struct Reverb {
+    /// Seconds for the tail to fall by 60 dB.
+    pub(super) decay: f32,
+    /// Wet share of the output, 0.0 (dry) ..= 1.0 (wet only).
+    pub(super) mix: f32,
+}
+
+/// Settings for [`add_noise`].
+#[derive(Clone, Copy)]
+pub(super) struct Noise {
+    /// Signal-to-noise ratio in dB, against the RMS of the whole buffer.
+    pub(super) snr_db: f32,
+    pub(super) seed: u64,
+}
+
+/// Add Schroeder reverb (parallel combs into series allpasses) in place.
+///
+/// `decay` is the RT60 in seconds and `mix` the wet share of the output;
+/// the result is clamped so the wet signal cannot wrap around.
+pub(super) fn reverb<Chan: Channel>(audio: &mut Audio<Chan, 2>, decay: f32, mix: f32) {
+    let sample_rate = audio.sample_rate().get() as f32;
+    let scale = sample_rate / 44_100.0;
+    let mix = mix.clamp(0.0, 1.0);
+
+    for channel in 0..2 {
+        let spread = channel * STEREO_SPREAD;
+        let mut combs: Vec<Delay> = COMB_DELAYS
+            .iter()
+            .map(|&d| {
+                let len = ((d + spread) as f32 * scale) as usize;
+                // Feedback that loses 60 dB over `decay` seconds
+                let gain = 10f32.powf(-3.0 * len as f32 / (decay.max(1e-3) * sample_rate));
+                Delay::new(len, gain)
+            })
+            .collect();
+        let mut allpasses: Vec<Delay> = ALLPASS_DELAYS
+            .iter()
+            .map(|&d| Delay::new(((d + spread) as f32 * scale) as usize, ALLPASS_GAIN))
+            .collect();
+
+        for frame in audio.iter_mut() {
+            let sample = &mut frame.channels_mut()[channel];
+            let dry = sample.to_f32();
+            let mut wet = combs.iter_mut().map(|c| c.comb(dry)).sum::<f32>() / combs.len() as f32;
+            for allpass in &mut allpasses {
+                wet = allpass.allpass(wet);
+            }
+            let out = dry * (1.0 - mix) + wet * mix;
+            *sample = Chan::from(out.clamp(-1.0, 1.0));
+        }
+    }
+}
+
+/// Second-order Butterworth lowpass (RBJ biquad) in place, rolling off
+/// 12 dB per octave above `cutoff_hz`.
+pub(super) fn lowpass<Chan: Channel>(audio: &mut Audio<Chan, 2>, cutoff_hz: f32) {
+    let sample_rate = audio.sample_rate().get() as f32;
+    let w0 = std::f32::consts::TAU * cutoff_hz.clamp(1.0, sample_rate * 0.49) / sample_rate;
+    let alpha = w0.sin() / std::f32::consts::SQRT_2; // Q = 1/sqrt(2)
+    let cos = w0.cos();
+    let a0 = 1.0 + alpha;
+    let b = [(1.0 - cos) / 2.0 / a0, (1.0 - cos) / a0, (1.0 - cos) / 2.0 / a0];
+    let a = [-2.0 * cos / a0, (1.0 - alpha) / a0];
+
+    for channel in 0..2 {
+        // Direct form I history: previous two inputs and outputs
+        let (mut x1, mut x2, mut y1, mut y2) = (0.0, 0.0, 0.0, 0.0);
+        for frame in audio.iter_mut() {
+            let sample = &mut frame.channels_mut()[channel];
+            let x = sample.to_f32();
+            let y = b[0] * x + b[1] * x1 + b[2] * x2 - a[0] * y1 - a[1] * y2;
+            (x2, x1, y2, y1) = (x1, x, y1, y);
+            *sample = Chan::from(y.clamp(-1.0, 1.0));
+        }
+    }
+}
+
+/// Mix in seeded white noise at `snr_db` below the buffer's RMS level, like
+/// the hiss of a room and microphone. Each channel gets its own noise.
+pub(super) fn add_noise<Chan: Channel>(audio: &mut Audio<Chan, 2>, snr_db: f32, seed: u64) {
+    let count = (audio.len() * 2).max(1) as f32;
+    let power = audio
+        .iter()
+        .flat_map(|frame| frame.channels().iter().map(|s| s.to_f32().powi(2)))
+        .sum::<f32>()
+        / count;
+    // Gaussian samples have unit RMS, so this is the noise's RMS too
+    let noise_rms = power.sqrt() / 10f32.powf(snr_db / 20.0);
+
+    let mut rng = Rng::new(seed);
+    for frame in audio.iter_mut() {
+        for sample in frame.channels_mut() {
+            let noisy = sample.to_f32() + noise_rms * rng.gaussian();
+            *sample = Chan::from(noisy.clamp(-1.0, 1.0));
+        }
+    }
+}
+
+/// Scale the whole buffer so its loudest sample has magnitude `peak`,
+/// returning the gain applied (1.0 for silence).
+pub(super) fn normalize<Chan: Channel>(audio: &mut Audio<Chan, 2>, peak: f32) -> f32 {
+    let loudest = audio
+        .iter()
+        .flat_map(|frame| frame.channels().iter().map(|s| s.to_f32().abs()))
+        .fold(0.0, f32::max);
+    if loudest <= 0.0 {
+        return 1.0;
+    }
+    let gain = peak / loudest;
+    amplify(audio, gain);
+    gain
+}
+
+/// Scale the whole buffer by `gain`.
+pub(super) fn amplify<Chan: Channel>(audio: &mut Audio<Chan, 2>, gain: f32) {
+    for frame in audio.iter_mut() {
+        for sample in frame.channels_mut() {
+            *sample = Chan::from(sample.to_f32() * gain);
+        }
+    }
+}
+
+/// Ramp the start of the buffer up from silence over `fade_in_secs` and the
+/// end down to silence over `fade_out_secs`, with raised-cosine curves, so
+/// the file neither starts nor stops with a click.
+pub(super) fn fade<Chan: Channel>(
+    audio: &mut Audio<Chan, 2>,
+    fade_in_secs: f32,
+    fade_out_secs: f32,
+) {
+    let sample_rate = audio.sample_rate().get() as f32;
+    let len = audio.len();
+    let fade_in = ((fade_in_secs * sample_rate) as usize).min(len);
+    let fade_out = ((fade_out_secs * sample_rate) as usize).min(len);
+    // 0.0 at the first point of the ramp, rising to just short of 1.0
+    let ramp = |i: usize, n: usize| {
+        0.5 - 0.5 * (std::f32::consts::PI * i as f32 / n as f32).cos()
+    };
+
+    for (i, frame) in audio.iter_mut().enumerate() {
+        let mut gain = 1.0;
+        if i < fade_in {
+            gain *= ramp(i, fade_in);
+        }
+        if len - i <= fade_out {
+            gain *= ramp(len - 1 - i, fade_out);
+        }
+        if gain < 1.0 {
+            for sample in frame.channels_mut() {
+                *sample = Chan::from(sample.to_f32() * gain);
+            }
+        }
+    }
+}
+
+/// Cut the frames quieter than `threshold_dbfs` in every channel from both
+/// ends, returning what is left with how many frames came off the start and
+/// the end. A buffer that never reaches the threshold is cut to nothing, all
+/// of it counted as coming off the start.
+pub(super) fn trim_silence<Chan: Channel>(
+    audio: &Audio<Chan, 2>,
+    threshold_dbfs: f32,
+) -> (Audio<Chan, 2>, usize, usize) {
+    let threshold = 10f32.powf(threshold_dbfs / 20.0);
+    let frames = audio.as_slice();
+    let loud =
+        |frame: &Frame<Chan, 2>| frame.channels().iter().any(|s| s.to_f32().abs() >= threshold);
+    let start = frames.iter().position(loud).unwrap_or(frames.len());
+    let end = frames.iter().rposition(loud).map_or(start, |last| last + 1);
+    let trimmed = Audio::with_frames(audio.sample_rate().get(), frames[start..end].to_vec());
+    (trimmed, start, frames.len() - end)
+}
+
+// Circular delay line shared by the comb and allpass filters
+struct Delay {
+    buf: Vec<f32>,
+    pos: usize,
+    gain: f32,
+}
+
+impl Delay {
+    fn new(len: usize, gain: f32) -> Self {
+        Self { buf: vec![0.0; len.max(1)], pos: 0, gain }
+    }
+
+    // Feedback comb: y[n] = x[n] + g * y[n - D]
+    fn comb(&mut self, input: f32) -> f32 {
+        let delayed = self.buf[self.pos];
+        let out = input + self.gain * delayed;
+        self.buf[self.pos] = out;
+        self.pos = (self.pos + 1) % self.buf.len();
+        delayed
+    }
+
+    // Schroeder allpass: y[n] = -g * x[n] + w[n - D], w[n] = x[n] + g * w[n - D]
+    fn allpass(&mut self, input: f32) -> f32 {
+        let delayed = self.buf[self.pos];
+        let w = input + self.gain * delayed;
+        self.buf[self.pos] = w;
+        self.pos = (self.pos + 1) % self.buf.len();
+        delayed - self.gain * w
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fon::chan::Ch32;
+
+    // `lead` silent frames, `loud` frames at half scale, then `trail` silent
+    fn padded(lead: usize, loud: usize, trail: usize) -> Audio<Ch32, 2> {
+        let level = |i: usize| if (lead..lead + loud).contains(&i) { 0.5 } else { 0.0 };
+        let frames = (0..lead + loud + trail)
+            .map(|i| Frame::<Ch32, 2>::new(Ch32::new(level(i)), Ch32::new(-level(i))))
+            .collect::<Vec<_>>();
+        Audio::with_frames(48_000, frames)
+    }
+
+    // A second of a `hz` sine at half scale in both channels, at 48 kHz
+    fn sine(hz: f32) -> Audio<Ch32, 2> {
+        let frames = (0..48_000)
+            .map(|i| {
+                let phase = std::f32::consts::TAU * hz * i as f32 / 48_000.0;
+                let sample = Ch32::new(0.5 * phase.sin());
+                Frame::<Ch32, 2>::new(sample, sample)
+            })
+            .collect::<Vec<_>>();
+        Audio::with_frames(48_000, frames)
+    }
+
+    // RMS of the left channel over the second half, once a filter has settled
+    fn settled_rms(audio: &Audio<Ch32, 2>) -> f32 {
+        let half = audio.len() / 2;
+        let sum: f32 = audio.iter().skip(half).map(|f| f.channels()[0].to_f32().powi(2)).sum();
+        (sum / (audio.len() - half) as f32).sqrt()
+    }
+
+    #[test]
+    fn trimming_removes_exactly_the_silent_ends() {
+        let (trimmed, start, end) = trim_silence(&padded(300, 1_000, 700), -60.0);
+        assert_eq!((start, end), (300, 700));
+        assert_eq!(trimmed.len(), 1_000);
+        assert!(trimmed.iter().all(|frame| frame.channels()[0].to_f32() == 0.5));
+    }
+
+    #[test]
+    fn trimming_silence_leaves_nothing() {
+        let (trimmed, start, end) = trim_silence(&padded(0, 0, 500), -60.0);
+        assert_eq!((trimmed.len(), start, end), (0, 500, 0));
+    }
+
+    #[test]
+    fn normalizing_sets_the_peak_and_returns_the_gain() {
+        let mut audio = padded(10, 100, 10);
+        let gain = normalize(&mut audio, 0.9);
+        assert!((gain - 1.8).abs() < 1e-6);
+        let peak = audio.iter().map(|frame| frame.channels()[1].to_f32().abs()).fold(0.0, f32::max);
+        assert!((peak - 0.9).abs() < 1e-6);
+        // Silence has no peak to scale to
+        let mut silence = padded(0, 0, 100);
+        assert_eq!(normalize(&mut silence, 0.9), 1.0);
+        assert!(silence.iter().all(|frame| frame.channels()[0].to_f32() == 0.0));
+    }
+
+    #[test]
+    fn fading_ramps_both_ends_and_leaves_the_middle() {
+        // 10 ms each way at 48 kHz: 480 frames
+        let mut audio = padded(0, 4_800, 0);
+        fade(&mut audio, 0.01, 0.01);
+        let level: Vec<f32> = audio.iter().map(|frame| frame.channels()[0].to_f32()).collect();
+        assert_eq!(level[0], 0.0);
+        assert_eq!(level[4_799], 0.0);
+        assert!((level[240] - 0.25).abs() < 1e-6, "halfway up: {}", level[240]);
+        assert!(level[..480].windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(level[4_320..].windows(2).all(|pair| pair[0] > pair[1]));
+        assert!(level[480..4_320].iter().all(|&x| x == 0.5));
+    }
+
+    #[test]
+    fn reverb_turns_an_impulse_into_a_decaying_tail() {
+        let mut frames = vec![Frame::<Ch32, 2>::default(); 16_000];
+        frames[0] = Frame::<Ch32, 2>::new(Ch32::new(1.0), Ch32::new(1.0));
+        let mut audio = Audio::with_frames(8_000, frames);
+        reverb(&mut audio, 1.0, 0.5);
+
+        // RMS of the left channel between two times in seconds
+        let rms = |from: f32, to: f32| {
+            let (start, len) = ((from * 8_000.0) as usize, ((to - from) * 8_000.0) as usize);
+            let sum: f32 = audio.iter().skip(start).take(len)
+                .map(|frame| frame.channels()[0].to_f32().powi(2))
+                .sum();
+            (sum / len as f32).sqrt()
+        };
+        let (early, late, later) = (rms(0.1, 0.3), rms(0.5, 0.7), rms(1.2, 1.4));
+        assert!(later > 0.0, "the tail died out");
+        assert!(early > late && late > later, "{} {} {}", early, late, later);
+        // A one-second decay loses about 24 dB over 0.4 s
+        let db = 20.0 * (early / late).log10();
+        assert!((12.0..36.0).contains(&db), "{} dB", db);
+    }
+
+    #[test]
+    fn lowpass_cuts_above_the_cutoff_and_passes_below_it() {
+        // Loss in dB of a sine at `hz` through a 1 kHz lowpass
+        let loss = |hz: f32| {
+            let mut audio = sine(hz);
+            let before = settled_rms(&audio);
+            lowpass(&mut audio, 1_000.0);
+            20.0 * (before / settled_rms(&audio)).log10()
+        };
+        // Two octaves and a bit above: 10 log10(1 + 5^4), about 28 dB
+        let high = loss(5_000.0);
+        assert!((high - 28.0).abs() < 2.0, "{} dB", high);
+        let low = loss(100.0);
+        assert!(low.abs() < 0.1, "{} dB", low);
+    }
+
+    #[test]
+    fn noise_is_added_at_the_requested_snr() {
+        let clean = sine(440.0);
+        for snr_db in [0.0, 10.0, 20.0, 40.0] {
+            let mut noisy = sine(440.0);
+            add_noise(&mut noisy, snr_db, 7);
+            let (mut signal, mut noise) = (0.0f32, 0.0f32);
+            for (before, after) in clean.iter().zip(noisy.iter()) {
+                for (s, n) in before.channels().iter().zip(after.channels()) {
+                    signal += s.to_f32().powi(2);
+                    noise += (n.to_f32() - s.to_f32()).powi(2);
+                }
+            }
+            let measured = 10.0 * (signal / noise).log10();
+            assert!((measured - snr_db).abs() < 1.0, "{} dB for {} dB", measured, snr_db);
+        }
+    }
+}
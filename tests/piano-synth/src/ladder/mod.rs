@@ -0,0 +1,126 @@
+//! Tempo ladders: a song played over and over in one file, a little faster
+//! each time, for working a piece up to speed.
+
+use fon::chan::Ch32;
+use fon::{Audio, Frame};
+use serde::Serialize;
+use std::io;
+
+use super::cli::Selection;
+use super::wav::BitDepth;
+use super::{Song, Tempo, at_path, render_at_tempo, wav, write_json};
+
+/// Passes of a tempo ladder, the share of the song's own tempo the first is
+/// played at (the last is at full tempo), and seconds between passes.
+const LADDER_STEPS: usize = 5;
+const LADDER_START_FRACTION: f32 = 0.6;
+const LADDER_GAP_SECS: f32 = 2.0;
+
+/// A song played over and over in one file, a little faster each time.
+#[derive(Serialize)]
+struct LadderInfo {
+    title: String,
+    filename: String,
+    gap_secs: f32,
+    passes: Vec<LadderPass>,
+}
+
+#[derive(Serialize)]
+struct LadderPass {
+    bpm: f32,
+    /// Sample the pass's first note starts on.
+    offset_samples: usize,
+}
+
+// Render `song` `steps` times into `path`, at tempos rising evenly from
+// `start_bpm` to `end_bpm`, with `LADDER_GAP_SECS` of silence between passes
+fn generate_tempo_ladder(
+    song: &Song,
+    start_bpm: f32,
+    end_bpm: f32,
+    steps: usize,
+    sample_rate: u32,
+    depth: BitDepth,
+    path: &str,
+) -> io::Result<LadderInfo> {
+    let tracks: Vec<&[(f32, f32)]> = song.tracks.iter().map(Vec::as_slice).collect();
+    let gap = Frame::<Ch32, 2>::default();
+    let gap_len = (LADDER_GAP_SECS * sample_rate as f32).round() as usize;
+    let mut frames = Vec::new();
+    let mut passes = Vec::new();
+
+    for step in 0..steps {
+        if step > 0 {
+            frames.extend(std::iter::repeat_n(gap, gap_len));
+        }
+        // A single pass is played at the starting tempo
+        let progress = if steps > 1 { step as f32 / (steps - 1) as f32 } else { 0.0 };
+        let bpm = start_bpm + (end_bpm - start_bpm) * progress;
+        let part = render_at_tempo(&tracks, Tempo { bpm }, sample_rate)?;
+        passes.push(LadderPass { bpm, offset_samples: frames.len() });
+        frames.extend(part.iter().copied());
+    }
+
+    let audio = Audio::<Ch32, 2>::with_frames(sample_rate, frames);
+    progress!("Writing {}", path);
+    wav::write(&audio, path, depth).map_err(at_path(path))?;
+
+    let filename = std::path::Path::new(path).file_name().unwrap_or_default();
+    Ok(LadderInfo {
+        title: song.title.clone(),
+        filename: filename.to_string_lossy().into_owned(),
+        gap_secs: LADDER_GAP_SECS,
+        passes,
+    })
+}
+
+// A tempo ladder from `LADDER_START_FRACTION` of each selected song's own
+// tempo up to full speed in `dir`, and the tempo of every pass
+pub(super) fn write_ladders(songs: &[Song], selection: &Selection, dir: &str) -> io::Result<()> {
+    std::fs::create_dir_all(dir).map_err(at_path(dir))?;
+    let mut ladders = Vec::new();
+    for song in songs.iter().filter(|song| selection.wants_song(&song.title)) {
+        let path = format!("{}/{}_ladder.wav", dir, song.title);
+        let (start_bpm, end_bpm) = (song.default_bpm * LADDER_START_FRACTION, song.default_bpm);
+        let (rate, depth) = (selection.sample_rate(), selection.bit_depth());
+        let (start, steps) = (start_bpm, LADDER_STEPS);
+        ladders.push(generate_tempo_ladder(song, start, end_bpm, steps, rate, depth, &path)?);
+    }
+    let json_path = format!("{}/ladder.json", dir);
+    write_json(&json_path, &ladders)?;
+    progress!("Wrote {}", json_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SAMPLE_RATE;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn each_pass_of_a_ladder_is_shorter_than_the_last() {
+        let song = Song::all().into_iter().find(|song| song.title == "twinkle").unwrap();
+        let rate = SAMPLE_RATE / 6;
+        let dir = TempDir::new("ladder");
+        let path = &dir.path("twinkle_ladder.wav");
+        let (start, end) = (song.default_bpm * LADDER_START_FRACTION, song.default_bpm);
+        let depth = BitDepth::default();
+        let info = generate_tempo_ladder(&song, start, end, LADDER_STEPS, rate, depth, path);
+        let info = info.unwrap();
+        let written = wav::read::<Ch32>(path).unwrap();
+
+        // Each pass ends where the gap before the next begins
+        let gap = (LADDER_GAP_SECS * rate as f32).round() as usize;
+        let mut ends: Vec<usize> =
+            info.passes.iter().skip(1).map(|pass| pass.offset_samples - gap).collect();
+        ends.push(written.len());
+        let mut start = 0;
+        let mut last = usize::MAX;
+        for (i, end) in ends.into_iter().enumerate() {
+            let length = end - start;
+            assert!(length < last, "pass {} lasts {} samples, the one before {}", i, length, last);
+            (start, last) = (end + gap, length);
+        }
+    }
+}
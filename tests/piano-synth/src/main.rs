@@ -1,42 +1,185 @@
 use fon::chan::{Ch16, Ch32};
 use fon::{Audio, Frame};
-use twang::Synth;
-use twang::osc::Sine;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use serde::{Deserialize, Serialize};
+use twang::osc::{Pulse, Sawtooth, Sine};
+
+/// Set while a render is streamed to stdout, which then carries nothing else.
+static STREAMING: AtomicBool = AtomicBool::new(false);
+
+/// Print a progress line: to stdout normally, or to stderr while stdout
+/// carries a streamed WAV.
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if $crate::STREAMING.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*);
+        } else {
+            println!($($arg)*);
+        }
+    };
+}
 
+// After `progress!`, which the generators use
+mod ab;
+mod analyze;
+mod cli;
+mod config;
+mod csv;
+mod fx;
+mod ladder;
+mod manifest;
+mod medley;
+mod metronome;
+mod midi;
+mod musicxml;
+mod notation;
+mod pitch;
+mod rng;
+mod score;
+mod spectrogram;
+mod validate;
+mod variations;
 mod wav;
 
+use ab::{AbInfo, generate_ab};
+use analyze::Levels;
+use cli::{Command, Outputs, Selection};
+use fx::{Noise, Reverb};
+use manifest::Manifest;
+use pitch::{NAME_TOLERANCE_CENTS, Pitch, freq_to_name};
+use rng::Rng;
+use validate::{
+    Measure, SongError, join_measures, validate_measures, validate_song, validate_tracks,
+};
+use variations::{VARIATIONS, generate_variations, render_variation};
+use wav::BitDepth;
+
 /// First ten harmonic volumes of a piano sample.
 const HARMONICS: [f32; 10] = [
     0.700, 0.243, 0.229, 0.095, 0.139, 0.087, 0.288, 0.199, 0.124, 0.090,
 ];
 
-// Frequencies
-const E2: f32 = 82.41;
-const A2: f32 = 110.00;
-const C3: f32 = 130.81;
-const E3: f32 = 164.81;
-const G3: f32 = 196.00;
-const G_SHARP_3: f32 = 207.65;
-const A3: f32 = 220.00;
-const C4: f32 = 261.63;
-const D4: f32 = 293.66;
-const E4: f32 = 329.63;
-const F4: f32 = 349.23;
-const G4: f32 = 392.00;
-const G_SHARP_4: f32 = 415.30;
-const A4: f32 = 440.00;
-const B4: f32 = 493.88;
-const C5: f32 = 523.25;
-const D5: f32 = 587.33;
-const D_SHARP_5: f32 = 622.25;
-const E5: f32 = 659.25;
+/// Sample rate, in Hz, of the generated test files.
+const SAMPLE_RATE: u32 = 48_000;
+
+/// Peak level (-1 dBFS) polyphonic mixes are normalized to.
+const POLYPHONIC_PEAK: f32 = 0.891;
+
+/// A test file quieter than this overall (RMS) has lost its notes.
+const SILENT_RMS_DBFS: f32 = -60.0;
+
+/// Ramps at either end of every render, so files loop without clicking.
+const FADE_IN_SECS: f32 = 0.005;
+const FADE_OUT_SECS: f32 = 0.05;
+
+/// Rate twang's oscillators assume when advancing their phase.
+const OSC_RATE: f32 = 48_000.0;
+
+// Pitches
+const E2: Pitch = Pitch::E(2);
+const A2: Pitch = Pitch::A(2);
+const C3: Pitch = Pitch::C(3);
+const D3: Pitch = Pitch::D(3);
+const E3: Pitch = Pitch::E(3);
+const G3: Pitch = Pitch::G(3);
+const G_SHARP_3: Pitch = Pitch::GSharp(3);
+const A3: Pitch = Pitch::A(3);
+const B3: Pitch = Pitch::B(3);
+const C4: Pitch = Pitch::C(4);
+const D4: Pitch = Pitch::D(4);
+const E4: Pitch = Pitch::E(4);
+const F4: Pitch = Pitch::F(4);
+const F_SHARP_4: Pitch = Pitch::FSharp(4);
+const G4: Pitch = Pitch::G(4);
+const G_SHARP_4: Pitch = Pitch::GSharp(4);
+const A4: Pitch = Pitch::A(4);
+const B4: Pitch = Pitch::B(4);
+const C5: Pitch = Pitch::C(5);
+const D5: Pitch = Pitch::D(5);
+const D_SHARP_5: Pitch = Pitch::DSharp(5);
+const E5: Pitch = Pitch::E(5);
+const F5: Pitch = Pitch::F(5);
+const F_SHARP_5: Pitch = Pitch::FSharp(5);
+const G5: Pitch = Pitch::G(5);
+
+/// Tempo in quarter-note beats per minute.
+#[derive(Clone, Copy)]
+struct Tempo {
+    bpm: f32,
+}
+
+impl Tempo {
+    // Multiplier turning durations written at `REFERENCE_TEMPO` into this tempo
+    fn speed_mult(&self) -> f32 {
+        REFERENCE_TEMPO.bpm / self.bpm
+    }
+}
+
+/// Stereo position of the metronome track, away from the piano.
+const CLICK_PAN: f32 = 0.8;
+
+/// Window and step, in samples, of the `--spectrograms` pictures: about
+/// 23 Hz per row and 11 ms per column at 48 kHz.
+const SPECTROGRAM_FFT_SIZE: usize = 2048;
+const SPECTROGRAM_HOP: usize = 512;
+
+/// Seconds between the rows of a `--csv` pitch log.
+const PITCH_LOG_INTERVAL: f32 = 0.01;
+
+/// The tempo the song tables are written at (an eighth note lasts 0.44 s).
+const REFERENCE_TEMPO: Tempo = Tempo { bpm: 60.0 / 0.88 };
+
+/// Note values as fractions of a quarter-note beat.
+#[derive(Clone, Copy)]
+enum Beat {
+    Sixteenth,
+    /// One of three eighths in the time of a quarter.
+    EighthTriplet,
+    Eighth,
+    DottedEighth,
+    Quarter,
+    DottedQuarter,
+    Half,
+    DottedHalf,
+}
+
+impl Beat {
+    const fn beats(self) -> f32 {
+        match self {
+            Beat::Sixteenth => 0.25,
+            Beat::EighthTriplet => 1.0 / 3.0,
+            Beat::Eighth => 0.5,
+            Beat::DottedEighth => 0.75,
+            Beat::Quarter => 1.0,
+            Beat::DottedQuarter => 1.5,
+            Beat::Half => 2.0,
+            Beat::DottedHalf => 3.0,
+        }
+    }
+
+    const fn duration_secs(&self, tempo: &Tempo) -> f32 {
+        self.beats() * 60.0 / tempo.bpm
+    }
+}
 
 // Note duration in seconds
-const S: f32 = 0.22; // Sixteenth note
-const E: f32 = 0.44; // Eighth note
+const S: f32 = Beat::Sixteenth.duration_secs(&REFERENCE_TEMPO);
+const E: f32 = Beat::Eighth.duration_secs(&REFERENCE_TEMPO);
+const DOTTED_E: f32 = Beat::DottedEighth.duration_secs(&REFERENCE_TEMPO);
+const Q: f32 = Beat::Quarter.duration_secs(&REFERENCE_TEMPO);
+const DOTTED_Q: f32 = Beat::DottedQuarter.duration_secs(&REFERENCE_TEMPO);
+const H: f32 = Beat::Half.duration_secs(&REFERENCE_TEMPO);
+const DOTTED_H: f32 = Beat::DottedHalf.duration_secs(&REFERENCE_TEMPO);
+const TRIPLET_E: f32 = Beat::EighthTriplet.duration_secs(&REFERENCE_TEMPO);
+
+// One note of a triplet: three in the time of two `base` notes
+const fn triplet(base: f32) -> f32 {
+    base * 2.0 / 3.0
+}
 
 // Fur Elise Main Theme
-const FUR_ELISE: &[(f32, f32)] = &[
+const FUR_ELISE: &[(Pitch, f32)] = &[
     // Phrase 1
     (E5, S), (D_SHARP_5, S), (E5, S), (D_SHARP_5, S), (E5, S), (B4, S), (D5, S), (C5, S), (A4, E),
     (C4, S), (E4, S), (A4, S), (B4, E),
@@ -48,853 +191,3053 @@ const FUR_ELISE: &[(f32, f32)] = &[
     (E4, S), (C5, S), (B4, S), (A4, E),
 ];
 
-const ODE_TO_JOY: &[(f32, f32)] = &[
+const ODE_TO_JOY: &[(Pitch, f32)] = &[
     (E4, E), (E4, E), (F4, E), (G4, E), (G4, E), (F4, E), (E4, E), (D4, E),
-    (C4, E), (C4, E), (D4, E), (E4, E), (E4, 0.66), (D4, 0.22), (D4, 0.88),
+    (C4, E), (C4, E), (D4, E), (E4, E), (E4, DOTTED_E), (D4, S), (D4, Q),
 ];
 
-const ODE_TO_JOY_HARMONY: &[(f32, f32)] = &[
+const ODE_TO_JOY_HARMONY: &[(Pitch, f32)] = &[
     (C3, E * 4.0), (G3, E * 4.0), (C3, E * 4.0), (G3, E * 4.0),
 ];
 
-const FUR_ELISE_HARMONY: &[(f32, f32)] = &[
+const FUR_ELISE_HARMONY: &[(Pitch, f32)] = &[
     // Intro
-    (0.0, 1.76),
+    (Pitch::Rest, 2.0*Q),
     // Am Arpeggio
-    (A2, S), (E3, S), (A3, 3.0*S),// (0.0, 0.44),
+    (A2, S), (E3, S), (A3, 3.0*S),// (Pitch::Rest, 0.44),
     // E Major Arpeggio
-    (E2, S), (E3, S), (G_SHARP_3, 3.0*S),// (0.0, 0.44),
+    (E2, S), (E3, S), (G_SHARP_3, 3.0*S),// (Pitch::Rest, 0.44),
     // Am Arpeggio (Turnaround)
     (A2, S), (E3, S), (A3, 3.0*S),
 
     // Repeat Intro
-    (0.0, 1.76-3.0*S),
+    (Pitch::Rest, 2.0*Q-2.0*S),
     // Am Arpeggio
-    (A2, S), (E3, S), (A3, 3.0*S),// (0.0, 0.44),
+    (A2, S), (E3, S), (A3, 3.0*S),// (Pitch::Rest, 0.44),
     // Ending phrase
     (E2, S), (E3, S), (G_SHARP_3, 3.0*S), (A2, E),
 ];
 
-// Single voice state
-struct Voice {
-    // 10 harmonics oscillators
-    sines: [Sine; 10],
-    // State to track song position
-    sample_counter: usize,
-    current_note_idx: usize,
-    song: Vec<(f32, f32)>,
-    speed_mult: f32,
+// Twinkle, Twinkle, Little Star (first two lines)
+const TWINKLE: &[(Pitch, f32)] = &[
+    (C4, E), (C4, E), (G4, E), (G4, E), (A4, E), (A4, E), (G4, Q),
+    (F4, E), (F4, E), (E4, E), (E4, E), (D4, E), (D4, E), (C4, Q),
+    (G4, E), (G4, E), (F4, E), (F4, E), (E4, E), (E4, E), (D4, Q),
+    (G4, E), (G4, E), (F4, E), (F4, E), (E4, E), (E4, E), (D4, Q),
+];
+
+// Greensleeves (first strain, 6/8): long-short lilt and a raised leading note
+const GREENSLEEVES: &[(Pitch, f32)] = &[
+    (A4, E),
+    (C5, Q), (D5, E), (E5, DOTTED_E), (F5, S), (E5, E),
+    (D5, Q), (B4, E), (G4, DOTTED_E), (A4, S), (B4, E),
+    (C5, Q), (A4, E), (A4, DOTTED_E), (G_SHARP_4, S), (A4, E),
+    (B4, Q), (G_SHARP_4, E), (E4, Q), (A4, E),
+    (C5, Q), (D5, E), (E5, DOTTED_E), (F5, S), (E5, E),
+    (D5, Q), (B4, E), (G4, DOTTED_E), (A4, S), (B4, E),
+    (C5, DOTTED_E), (B4, S), (A4, E), (G_SHARP_4, DOTTED_E), (F_SHARP_4, S), (G_SHARP_4, E),
+    (A4, DOTTED_Q), (A4, DOTTED_Q),
+];
+
+// Minuet in G, BWV Anh. 114 (first eight bars, 3/4), one bar per line
+const MINUET_IN_G: &[Measure] = &[
+    &[(D5, Q), (G4, E), (A4, E), (B4, E), (C5, E)],
+    &[(D5, Q), (G4, Q), (G4, Q)],
+    &[(E5, Q), (C5, E), (D5, E), (E5, E), (F_SHARP_5, E)],
+    &[(G5, Q), (G4, Q), (G4, Q)],
+    &[(C5, Q), (D5, E), (C5, E), (B4, E), (A4, E)],
+    &[(B4, Q), (C5, E), (B4, E), (A4, E), (G4, E)],
+    &[(F_SHARP_4, Q), (G4, E), (A4, E), (B4, E), (G4, E)],
+    &[(A4, DOTTED_H)],
+];
+
+// Left hand of the minuet: a bass note on each downbeat
+const MINUET_IN_G_BASS: &[Measure] = &[
+    &[(G3, H), (A3, Q)],
+    &[(B3, DOTTED_H)],
+    &[(C4, DOTTED_H)],
+    &[(B3, DOTTED_H)],
+    &[(A3, DOTTED_H)],
+    &[(G3, DOTTED_H)],
+    &[(D4, H), (B3, Q)],
+    &[(D4, Q), (D3, H)],
+];
+
+/// How a note connects to the one after it.
+#[derive(Clone, Copy, Default, PartialEq)]
+enum Articulation {
+    /// Held for the whole slot, and the next note carries on the strings'
+    /// phase instead of restarting it, coming in as this one fades out.
+    Legato,
+    /// Held for the whole slot.
+    #[default]
+    Normal,
+    /// Released early, leaving the rest of the slot silent.
+    Staccato,
+    /// Let go after this share of the slot, as a note cut short by mistake.
+    Clipped(f32),
 }
 
-impl Voice {
-    fn new(song: Vec<(f32, f32)>, speed_mult: f32) -> Self {
-        Self {
-            sines: Default::default(),
-            sample_counter: 0,
-            current_note_idx: usize::MAX,
-            song,
-            speed_mult,
+impl Articulation {
+    // Share of the note's slot that actually sounds
+    fn sounding_fraction(self) -> f32 {
+        match self {
+            Articulation::Legato | Articulation::Normal => 1.0,
+            Articulation::Staccato => 0.4,
+            Articulation::Clipped(share) => share.clamp(0.0, 1.0),
         }
     }
+}
 
-    fn step(&mut self) -> f32 {
-        let sample_rate = 48_000.0f32;
-        let mut time_cursor = 0.0f32;
-        let mut active_freq = 0.0;
-        let mut note_elapsed = 0.0;
-        let mut found_note = false;
-        let mut note_idx = 0;
-
-        // Determine which note to play
-        for (i, (freq, dur_raw)) in self.song.iter().enumerate() {
-            let dur = dur_raw * self.speed_mult;
+/// Periodic wobble of a voice's pitch, as a singer or string player adds.
+#[derive(Clone, Copy, Default)]
+struct Vibrato {
+    /// Wobbles per second.
+    rate: f32,
+    /// Furthest the pitch strays either side of the note, in cents; 0.0 for
+    /// none.
+    depth_cents: f32,
+}
 
-            // Use round to ensure contiguous sample ranges without floating point gaps
-            let start_sample = (time_cursor * sample_rate).round() as usize;
-            let end_sample = ((time_cursor + dur) * sample_rate).round() as usize;
-
-            if self.sample_counter >= start_sample && self.sample_counter < end_sample {
-                active_freq = *freq;
-                // Calculate elapsed time based on sample difference to avoid jitter
-                note_elapsed = (self.sample_counter - start_sample) as f32 / sample_rate;
-                found_note = true;
-                note_idx = i;
-                break;
-            }
-            time_cursor += dur;
+impl Vibrato {
+    // Factor on the note's frequency `elapsed` seconds after it starts
+    fn ratio(self, elapsed: f32) -> f32 {
+        if self.depth_cents == 0.0 {
+            return 1.0;
         }
+        let cents = self.depth_cents * (std::f32::consts::TAU * self.rate * elapsed).sin();
+        2f32.powf(cents / 1200.0)
+    }
+}
 
-        // Increment sample counter for next call
-        self.sample_counter += 1;
+/// Periodic wobble of a voice's loudness.
+#[derive(Clone, Copy, Default)]
+struct Tremolo {
+    /// Wobbles per second.
+    rate: f32,
+    /// Share of the level taken off at the bottom of each wobble, 0.0 (none)
+    /// ..= 1.0 (down to silence).
+    depth: f32,
+}
 
-        if !found_note {
-            return 0.0;
+impl Tremolo {
+    // Gain `elapsed` seconds after the note starts, full at the strike
+    fn gain(self, elapsed: f32) -> f32 {
+        if self.depth == 0.0 {
+            return 1.0;
         }
+        let dip = 0.5 - 0.5 * (std::f32::consts::TAU * self.rate * elapsed).cos();
+        1.0 - self.depth.clamp(0.0, 1.0) * dip
+    }
+}
 
-        // Reset oscillators if new note (to reset phase for attack)
-        if note_idx != self.current_note_idx {
-            self.current_note_idx = note_idx;
-            for s in &mut self.sines {
-                *s = Sine::default();
-            }
-        }
+/// Extra weight on the first beat of every measure, as a player leans on
+/// the downbeat, which also marks the meter for an analyzer.
+#[derive(Clone, Copy)]
+struct Accent {
+    beats_per_measure: u32,
+    /// Seconds per beat at `REFERENCE_TEMPO`.
+    beat_secs: f32,
+    /// Seconds before the first barline, for a song opening on an upbeat.
+    pickup: f32,
+    /// Velocity multiplier on a note that starts on a barline.
+    gain: f32,
+}
 
-        // If freq is 0 (missed note), return silence
-        if active_freq <= 0.0 {
-            return 0.0;
-        }
+impl Accent {
+    // Accent with `gain` on the downbeats of `song`'s written meter
+    fn downbeats_of(song: &Song, gain: f32) -> Self {
+        let (beats_per_measure, beat_type) = song.time_signature;
+        let beat_secs = Q * 4.0 / beat_type.max(1) as f32;
+        Self { beats_per_measure, beat_secs, pickup: song.pickup, gain }
+    }
 
-        // Calculate sample by mixing harmonics
-        let mut mixed = 0.0;
+    // Velocity multiplier for each of notes lasting `durations` (seconds at
+    // the reference tempo) played one after another
+    fn multipliers(&self, durations: impl Iterator<Item = f32>) -> Vec<f32> {
+        let measure = self.beat_secs * self.beats_per_measure.max(1) as f32;
+        let mut elapsed = 0.0;
+        durations
+            .map(|dur| {
+                let measures = (elapsed - self.pickup) / measure;
+                elapsed += dur;
+                let on_barline = (measures - measures.round()).abs() < 1e-3;
+                if on_barline { self.gain } else { 1.0 }
+            })
+            .collect()
+    }
+}
 
-        for (i, sine) in self.sines.iter_mut().enumerate() {
-            let h_freq = active_freq * (i as f32 + 1.0);
-            let sample = sine.step(h_freq);
-            // Convert Ch32 to f32
-            let s_f32: f32 = sample.into();
-            mixed += s_f32 * HARMONICS[i];
-        }
+/// Random starting phases for the partials of each note, since no two real
+/// strikes set the strings moving in quite the same way.
+#[derive(Clone, Copy)]
+struct PhaseScatter {
+    /// Largest offset, as a share of a cycle: 0.0 (none) ..= 1.0 (any phase).
+    spread: f32,
+    seed: u64,
+}
 
-        // Piano Envelope (percussive)
-        let attack_time = 0.01;
-        let envelope = if note_elapsed < attack_time {
-            note_elapsed / attack_time
-        } else {
-             let decay_rate = 3.0;
-             (-decay_rate * (note_elapsed - attack_time)).exp()
-        };
+/// Levels of the melody against the other voices, and of the whole mix, for
+/// [`Processors::set_balance`].
+#[derive(Clone, Copy)]
+struct Balance {
+    master: f32,
+    melody: f32,
+    /// Shared by every voice after the melody.
+    harmony: f32,
+}
 
-        mixed * envelope * 0.25 // Scale down volume
-    }
+/// How the pitches of a chord are spread out in time, as a pianist rolls a
+/// chord instead of striking it.
+#[derive(Clone, Copy, Default)]
+struct Roll {
+    /// Seconds from the first pitch struck to the last, 0.0 for all at once.
+    spread: f32,
+    /// Highest pitch first instead of lowest.
+    downward: bool,
 }
 
-// State of the synthesizer.
-struct Processors {
-    voices: Vec<Voice>,
+/// One entry of a song track.
+#[derive(Clone)]
+struct Note {
+    /// The note, or the root of a chord.
+    pitch: Pitch,
+    /// Further pitches struck together with `pitch`, empty for a single note.
+    chord: Vec<Pitch>,
+    /// Seconds, before any speed multiplier.
+    duration: f32,
+    /// Loudness, 0.0..=1.0.
+    velocity: f32,
+    articulation: Articulation,
+    /// Seconds spent sliding in from the previous note's pitch (portamento),
+    /// 0.0 to jump straight to this one.
+    glide: f32,
+    /// Spread of a chord's pitches; ignored for a single note.
+    roll: Roll,
+    /// Seconds this note takes to speak, in place of the envelope's attack;
+    /// `None` for the voice's usual touch.
+    attack: Option<f32>,
 }
 
-impl Processors {
-    fn new(tracks: Vec<Vec<(f32, f32)>>, speed_mult: f32) -> Self {
+impl From<(Pitch, f32)> for Note {
+    fn from((pitch, duration): (Pitch, f32)) -> Self {
         Self {
-            voices: tracks.into_iter().map(|s| Voice::new(s, speed_mult)).collect(),
+            pitch,
+            chord: Vec::new(),
+            duration,
+            velocity: 1.0,
+            articulation: Articulation::Normal,
+            glide: 0.0,
+            roll: Roll::default(),
+            attack: None,
         }
     }
+}
 
-    // Synthesis logic
-    fn step(&mut self, frame: Frame<Ch32, 2>) -> Frame<Ch32, 2> {
-        let mut mixed = 0.0;
-        for voice in &mut self.voices {
-            mixed += voice.step();
-        }
+/// Lets `(freq, duration)` tracks, as `Song` stores them, be played as they are.
+impl From<(f32, f32)> for Note {
+    fn from((freq, duration): (f32, f32)) -> Self {
+        Self::from((Pitch::from(freq), duration))
+    }
+}
 
-        // Pan center
-        frame.pan(Ch32::new(mixed), 0.0)
+/// A chord: every frequency struck at once, the first taken as the root.
+/// An empty chord is a rest.
+impl From<(&[f32], f32)> for Note {
+    fn from((freqs, duration): (&[f32], f32)) -> Self {
+        let root = freqs.first().copied().unwrap_or(0.0);
+        let chord = freqs.iter().skip(1).map(|&f| Pitch::from(f)).collect();
+        Self { chord, ..Self::from((root, duration)) }
     }
 }
 
-fn freq_to_name(freq: f32) -> String {
-    if freq < 1.0 {
-        return "Rest".to_string();
+impl From<(f32, f32, f32)> for Note {
+    fn from((freq, duration, velocity): (f32, f32, f32)) -> Self {
+        Self { velocity, ..Self::from((Pitch::from(freq), duration)) }
     }
-    let epsilon = 0.1;
-    if (freq - E2).abs() < epsilon { return "E2".to_string(); }
-    if (freq - A2).abs() < epsilon { return "A2".to_string(); }
-    if (freq - C3).abs() < epsilon { return "C3".to_string(); }
-    if (freq - E3).abs() < epsilon { return "E3".to_string(); }
-    if (freq - G3).abs() < epsilon { return "G3".to_string(); }
-    if (freq - G_SHARP_3).abs() < epsilon { return "G#3".to_string(); }
-    if (freq - A3).abs() < epsilon { return "A3".to_string(); }
-    if (freq - C4).abs() < epsilon { return "C4".to_string(); }
-    if (freq - D4).abs() < epsilon { return "D4".to_string(); }
-    if (freq - E4).abs() < epsilon { return "E4".to_string(); }
-    if (freq - F4).abs() < epsilon { return "F4".to_string(); }
-    if (freq - G4).abs() < epsilon { return "G4".to_string(); }
-    if (freq - G_SHARP_4).abs() < epsilon { return "G#4".to_string(); }
-    if (freq - A4).abs() < epsilon { return "A4".to_string(); }
-    if (freq - B4).abs() < epsilon { return "B4".to_string(); }
-    if (freq - C5).abs() < epsilon { return "C5".to_string(); }
-    if (freq - D5).abs() < epsilon { return "D5".to_string(); }
-    if (freq - D_SHARP_5).abs() < epsilon { return "D#5".to_string(); }
-    if (freq - E5).abs() < epsilon { return "E5".to_string(); }
+}
 
-    format!("{:.2} Hz", freq)
+/// Amplitude envelope applied to every note of a voice.
+#[derive(Clone, Copy)]
+struct Envelope {
+    /// Linear ramp from silence to full level, in seconds.
+    attack: f32,
+    /// Exponential decay rate (per second) from full level towards `sustain`,
+    /// for a note at `DECAY_REFERENCE_HZ`.
+    decay: f32,
+    /// Factor on `decay` for each octave above `DECAY_REFERENCE_HZ` (and
+    /// divisor for each below), so low strings ring on; 1.0 for the same
+    /// decay at every pitch.
+    decay_per_octave: f32,
+    /// Level held for the rest of the note, 0.0..=1.0.
+    sustain: f32,
+    /// Linear fade to silence after the note ends, in seconds.
+    release: f32,
 }
 
-struct NoteInfo {
-    name: String,
-    freq: f32,
-    duration: f32,
+impl Envelope {
+    /// Percussive piano: a fast attack, then decay to silence, quicker the
+    /// higher the note, with no release.
+    fn piano_default() -> Self {
+        Self { attack: 0.01, decay: 3.0, decay_per_octave: 1.5, sustain: 0.0, release: 0.0 }
+    }
+
+    // This envelope with the decay rate of a note at `freq`
+    fn for_pitch(self, freq: f32) -> Self {
+        let octaves = (freq.max(1.0) / DECAY_REFERENCE_HZ).log2();
+        Self { decay: self.decay * self.decay_per_octave.powf(octaves), ..self }
+    }
+
+    // Level `elapsed` seconds after the note was struck
+    fn level(&self, elapsed: f32) -> f32 {
+        if elapsed < self.attack {
+            elapsed / self.attack
+        } else {
+            let decayed = (-self.decay * (elapsed - self.attack)).exp();
+            self.sustain + (1.0 - self.sustain) * decayed
+        }
+    }
 }
 
-struct VariationInfo {
-    filename: String,
-    ideal_filename: String,
-    tempo_accuracy: f32,
-    pitch_accuracy: f32,
-    notes: Vec<Vec<NoteInfo>>,
+/// Pitch whose notes decay at exactly `Envelope::decay` (middle C).
+const DECAY_REFERENCE_HZ: f32 = 261.63;
+
+/// Shape of the oscillator playing each partial.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Waveform {
+    /// A pure tone, so the profile alone sets the timbre.
+    #[default]
+    Sine,
+    /// Every harmonic at 1/n, like an analog synth lead. Not band-limited,
+    /// so the harmonics of high notes fold back.
+    Sawtooth,
+    /// Odd harmonics at 1/n, hollow like a clarinet; folds back like the
+    /// sawtooth.
+    Square,
+}
+
+/// One partial's oscillator, of the voice's [`Waveform`].
+#[derive(Clone, Copy)]
+enum Oscillator {
+    Sine(Sine),
+    Sawtooth(Sawtooth),
+    Square(Pulse),
 }
 
-fn generate(filename: &str, tracks: Vec<&[(f32, f32)]>, speed_mult: f32) {
-    // Calculate total duration (max of all tracks)
-    let total_duration: f32 = tracks.iter()
-        .map(|track| track.iter().map(|(_, d)| d * speed_mult).sum::<f32>())
-        .fold(0.0, f32::max);
+impl Oscillator {
+    fn new(waveform: Waveform) -> Self {
+        match waveform {
+            Waveform::Sine => Oscillator::Sine(Sine::default()),
+            Waveform::Sawtooth => Oscillator::Sawtooth(Sawtooth::default()),
+            Waveform::Square => Oscillator::Square(Pulse::default()),
+        }
+    }
+
+    // Next sample at `hz` on twang's 48 kHz clock
+    fn step(&mut self, hz: f32) -> f32 {
+        match self {
+            Oscillator::Sine(osc) => osc.step(hz).into(),
+            Oscillator::Sawtooth(osc) => osc.step(hz).into(),
+            // A duty of 0.0 is an even square
+            Oscillator::Square(osc) => osc.step(hz, Ch32::new(0.0)).into(),
+        }
+    }
 
-    let sample_rate = 48_000;
+    // Move the phase on by `cycles`, a share of one cycle
+    fn shift(&mut self, cycles: f32) {
+        match self {
+            Oscillator::Sine(osc) => osc.shift(Ch32::new(cycles)),
+            Oscillator::Sawtooth(osc) => osc.shift(Ch32::new(cycles)),
+            Oscillator::Square(osc) => osc.shift(Ch32::new(cycles)),
+        }
+    }
+}
 
-    // Initialize audio (buffer size based on song length + 1 second tail)
-    let buffer_len = (sample_rate as f32 * (total_duration + 1.0)) as usize;
-    let mut audio = Audio::<Ch16, 2>::with_silence(sample_rate, buffer_len);
+/// Relative volumes of a voice's harmonics, i.e. its timbre. A voice plays
+/// as many partials as there are amplitudes.
+#[derive(Clone)]
+struct HarmonicProfile {
+    amplitudes: Vec<f32>,
+    /// Inharmonicity coefficient B: partial n sounds at
+    /// `n * sqrt(1 + B * n^2)` times the fundamental, as on a stiff string.
+    inharmonicity: f32,
+    waveform: Waveform,
+}
 
-    // Create audio processors
-    let proc = Processors::new(tracks.iter().map(|&s| s.to_vec()).collect(), speed_mult);
+impl HarmonicProfile {
+    /// The reference timbre; other profiles are scaled to its loudness.
+    fn piano() -> Self {
+        Self {
+            amplitudes: HARMONICS.to_vec(),
+            inharmonicity: PIANO_INHARMONICITY,
+            waveform: Waveform::Sine,
+        }
+    }
 
-    // Build synthesis algorithm
-    let mut synth = Synth::new(proc, |proc, frame: Frame<_, 2>| proc.step(frame));
+    /// Drawbar organ: strong odd harmonics, weak even ones.
+    fn organ() -> Self {
+        Self::normalized(&[1.0, 0.1, 0.6, 0.05, 0.4, 0.03, 0.25, 0.02, 0.15, 0.01])
+    }
 
-    // Synthesize
-    synth.stream(audio.sink());
+    /// Flute: nearly a pure tone with a little breath in the low harmonics.
+    fn flute() -> Self {
+        Self::normalized(&[1.0, 0.35, 0.12, 0.05, 0.02, 0.01])
+    }
 
-    // Write to file
-    println!("Writing {}", filename);
-    wav::write(audio, format!("target_music/{}", filename).as_str()).expect("Failed to write WAV file");
-}
+    /// Synth lead: a single sawtooth, its harmonics all from the waveform.
+    fn synth_lead() -> Self {
+        Self { waveform: Waveform::Sawtooth, ..Self::normalized(&[1.0]) }
+    }
 
-fn generate_variations(base_name: &str, tracks: Vec<&[(f32, f32)]>) -> Vec<VariationInfo> {
-    let mut variations = Vec::new();
+    /// Synth bass: a single square wave.
+    fn synth_bass() -> Self {
+        Self { waveform: Waveform::Square, ..Self::normalized(&[1.0]) }
+    }
 
-    let get_notes = |tracks: &[&[(f32, f32)]], speed_mult: f32| -> Vec<Vec<NoteInfo>> {
-        tracks
-            .iter()
-            .map(|track| {
-                track
-                    .iter()
-                    .map(|(freq, dur)| NoteInfo {
-                        name: freq_to_name(*freq),
-                        freq: *freq,
-                        duration: *dur * speed_mult,
-                    })
-                    .collect()
+    // Scale to the same total power as the piano profile
+    fn normalized(amplitudes: &[f32]) -> Self {
+        let power = |a: &[f32]| a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let scale = power(&HARMONICS) / power(amplitudes);
+        Self {
+            amplitudes: amplitudes.iter().map(|a| a * scale).collect(),
+            inharmonicity: 0.0,
+            waveform: Waveform::Sine,
+        }
+    }
+
+    // Frequency of each partial as a multiple of the fundamental
+    fn partial_ratios(&self) -> Vec<f32> {
+        (1..=self.amplitudes.len())
+            .map(|n| {
+                let n = n as f32;
+                n * (1.0 + self.inharmonicity * n * n).sqrt()
             })
             .collect()
-    };
-
-    let count_playable_notes = |tracks: &[&[(f32, f32)]]| -> usize {
-        tracks
-            .iter()
-            .map(|t| t.iter().filter(|(f, _)| *f > 0.0).count())
-            .sum()
-    };
+    }
+}
 
-    let total_playable_notes = count_playable_notes(&tracks);
-
-    // 1. Original
-    let original_filename = format!("{}.wav", base_name);
-    generate(&original_filename, tracks.clone(), 1.0);
-    variations.push(VariationInfo {
-        filename: original_filename.clone(),
-        ideal_filename: original_filename.clone(),
-        tempo_accuracy: 1.0,
-        pitch_accuracy: 1.0,
-        notes: get_notes(&tracks, 1.0),
-    });
+/// A timbre's name and the profile it is made with.
+type Timbre = (&'static str, fn() -> HarmonicProfile);
 
-    // 2. Fast (1.15x speed)
-    let speed_fast = 1.0 / 1.15;
-    let filename = format!("{}_fast.wav", base_name);
-    generate(&filename, tracks.clone(), speed_fast);
-    variations.push(VariationInfo {
-        filename,
-        ideal_filename: original_filename.clone(),
-        tempo_accuracy: 0.85,
-        pitch_accuracy: 1.0,
-        notes: get_notes(&tracks, speed_fast),
-    });
+/// Every timbre, by the name `--calibration` writes it under.
+const TIMBRES: [Timbre; 5] = [
+    ("piano", HarmonicProfile::piano),
+    ("organ", HarmonicProfile::organ),
+    ("flute", HarmonicProfile::flute),
+    ("synth_lead", HarmonicProfile::synth_lead),
+    ("synth_bass", HarmonicProfile::synth_bass),
+];
 
-    // 3. Slow (0.9x speed)
-    let speed_slow = 1.0 / 0.90;
-    let filename = format!("{}_slow.wav", base_name);
-    generate(&filename, tracks.clone(), speed_slow);
-    variations.push(VariationInfo {
-        filename,
-        ideal_filename: original_filename.clone(),
-        tempo_accuracy: 0.90,
-        pitch_accuracy: 1.0,
-        notes: get_notes(&tracks, speed_slow),
-    });
+/// The note `--calibration` plays in each timbre: pitch and seconds.
+const CALIBRATION_NOTE: (Pitch, f32) = (A4, 1.0);
 
-    // 4. Missed Notes (Melody only)
-    if !tracks.is_empty() {
-        let mut melody = tracks[0].to_vec();
-        let mut missed_count = 0;
+/// Inharmonicity of a mid-range piano string; organ pipes and flutes are
+/// harmonic.
+const PIANO_INHARMONICITY: f32 = 0.0004;
 
-        if melody.len() > 12 {
-            if let Some(note) = melody.get_mut(4) {
-                if note.0 > 0.0 {
-                    note.0 = 0.0;
-                    missed_count += 1;
-                }
-            }
-            if let Some(note) = melody.get_mut(11) {
-                if note.0 > 0.0 {
-                    note.0 = 0.0;
-                    missed_count += 1;
-                }
-            }
-        }
+/// How much duller soft notes are: partial `n` is scaled by
+/// `velocity^(VELOCITY_BRIGHTNESS * (n - 1))`, so a full-velocity note keeps
+/// its profile and the fundamental is never touched.
+const VELOCITY_BRIGHTNESS: f32 = 0.15;
 
-        let mut missed_tracks = vec![melody.as_slice()];
-        missed_tracks.extend_from_slice(&tracks[1..]);
+// Gain on each of `partials` partials of a note struck at `velocity`
+fn velocity_tilt(velocity: f32, partials: usize) -> Vec<f32> {
+    let velocity = velocity.clamp(1e-3, 1.0);
+    (0..partials).map(|i| velocity.powf(VELOCITY_BRIGHTNESS * i as f32)).collect()
+}
 
-        let filename = format!("{}_missed_melody.wav", base_name);
-        generate(&filename, missed_tracks.clone(), 1.0);
+/// How a note's velocity sets its amplitude.
+#[derive(Clone, Copy, Default)]
+enum VelocityCurve {
+    /// Amplitude equal to velocity.
+    Linear,
+    /// `(e^(k * velocity) - 1) / (e^k - 1)` for `k` of
+    /// `VELOCITY_CURVE_STEEPNESS`: soft notes fall away faster than
+    /// linearly, like a struck string, while full velocity stays at 1.0.
+    #[default]
+    Exponential,
+    /// Any mapping from velocity to amplitude.
+    Custom(fn(f32) -> f32),
+}
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes - missed_count) as f32
-                / total_playable_notes as f32,
-            notes: get_notes(&missed_tracks, 1.0),
-        });
-    }
+/// Steepness of `VelocityCurve::Exponential`; half velocity comes out about
+/// 13 dB below full.
+const VELOCITY_CURVE_STEEPNESS: f32 = 2.5;
 
-    // 5. Missed Notes (Harmony only)
-    if tracks.len() >= 2 {
-        let mut harmony = tracks[1].to_vec();
-        let mut missed_count = 0;
+/// The curves `--velocity-curve` can choose, by name; the first is the
+/// default.
+const VELOCITY_CURVES: [(&str, VelocityCurve); 3] = [
+    ("exponential", VelocityCurve::Exponential),
+    ("linear", VelocityCurve::Linear),
+    ("square", VelocityCurve::Custom(|velocity| velocity * velocity)),
+];
 
-        // Try to remove a couple of notes
-        let indices_to_remove = [1, 3, 5];
-        for &i in &indices_to_remove {
-            if i < harmony.len() {
-                if harmony[i].0 > 0.0 {
-                    harmony[i].0 = 0.0;
-                    missed_count += 1;
-                }
+impl VelocityCurve {
+    fn amplitude(self, velocity: f32) -> f32 {
+        match self {
+            VelocityCurve::Linear => velocity,
+            VelocityCurve::Exponential => {
+                let k = VELOCITY_CURVE_STEEPNESS;
+                (k * velocity).exp_m1() / k.exp_m1()
             }
+            VelocityCurve::Custom(curve) => curve(velocity),
         }
+    }
 
-        let mut missed_tracks = vec![tracks[0]];
-        let harmony_slice = harmony.as_slice();
-        missed_tracks.push(harmony_slice);
-        if tracks.len() > 2 {
-            missed_tracks.extend_from_slice(&tracks[2..]);
-        }
+    /// The curve in `VELOCITY_CURVES` called `name`.
+    fn named(name: &str) -> Option<Self> {
+        VELOCITY_CURVES.iter().find(|&&(known, _)| known == name).map(|&(_, curve)| curve)
+    }
+}
 
-        let filename = format!("{}_missed_harmony.wav", base_name);
-        generate(&filename, missed_tracks.clone(), 1.0);
+/// Sustain pedal control track: (pedal down, duration in seconds).
+type SustainTrack = Vec<(bool, f32)>;
+
+/// Pitch bend control track applied to every voice: (semitones, duration in
+/// seconds). Unlike a glide it follows the clock, not the notes.
+type BendTrack = Vec<(f32, f32)>;
+
+/// Stereo positions (-1.0 left ..= 1.0 right) of the melody and the other
+/// voices when a song has more than one track.
+const MELODY_PAN: f32 = 0.2;
+const HARMONY_PAN: f32 = -0.2;
+
+/// Level of each voice before the voices are summed, leaving headroom for
+/// several partials and chord notes to add up, and of the summed mix.
+const VOICE_GAIN: f32 = 0.25;
+const MASTER_GAIN: f32 = 1.0;
+
+/// The written balance: every voice at the same level.
+const EVEN_BALANCE: Balance =
+    Balance { master: MASTER_GAIN, melody: VOICE_GAIN, harmony: VOICE_GAIN };
+
+/// Seconds a slurred note takes to hand over to the next pitch, fading out
+/// while the new one comes in, so the join doesn't click.
+const LEGATO_CROSSFADE_SECS: f32 = 0.005;
+
+/// Decay rate multiplier for notes struck while the sustain pedal is down.
+const SUSTAIN_DECAY_FACTOR: f32 = 0.2;
+
+// Sustain pedal for Fur Elise, lifted briefly on each harmony change
+const FUR_ELISE_PEDAL: &[(bool, f32)] = &[
+    (false, 2.0*Q),
+    (true, 5.0*S - 0.05), (false, 0.05),
+    (true, 5.0*S - 0.05), (false, 0.05),
+    (true, 5.0*S - 0.05), (false, 0.05),
+    (false, 2.0*Q-3.0*S),
+    (true, 5.0*S - 0.05), (false, 0.05),
+    (true, 5.0*S + E),
+];
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes - missed_count) as f32
-                / total_playable_notes as f32,
-            notes: get_notes(&missed_tracks, 1.0),
-        });
-    }
+/// Seconds a measure of `(beats, beat_type)` lasts at `REFERENCE_TEMPO`.
+fn measure_secs((beats, beat_type): (u32, u32)) -> f32 {
+    beats as f32 * Q * 4.0 / beat_type.max(1) as f32
+}
 
-    // 6. Missed Notes (Both)
-    if tracks.len() >= 2 {
-        let mut melody = tracks[0].to_vec();
-        let mut harmony = tracks[1].to_vec();
-        let mut missed_count = 0;
+/// A piece to generate variations of, with its identity and performance marks.
+struct Song {
+    /// Identifies the song in file names, on the command line and in the JSON.
+    title: String,
+    /// Melody first, then any accompaniment, written at `REFERENCE_TEMPO`.
+    tracks: Vec<Vec<(f32, f32)>>,
+    /// Tempo the song is meant to be played at.
+    default_bpm: f32,
+    key: Option<String>,
+    /// Beats per bar and the note value of a beat, as written, e.g. (3, 8).
+    time_signature: (u32, u32),
+    /// Seconds before the first barline, for a song that opens on an upbeat.
+    pickup: f32,
+    /// Semitones to shift by for the transposed variation, if it has one.
+    transpose_semitones: Option<i32>,
+    /// Sustain pedal marks for the pedal variation, if it has one.
+    pedal: Option<SustainTrack>,
+    /// Each track's bars, for a song written bar by bar, so they can be
+    /// checked against the time signature; empty otherwise.
+    measures: Vec<&'static [Measure<'static>]>,
+}
 
-        // Melody misses
-        if melody.len() > 12 {
-            if let Some(note) = melody.get_mut(4) {
-                if note.0 > 0.0 { note.0 = 0.0; missed_count += 1; }
-            }
+impl Song {
+    // `tracks` as written, by pitch or by frequency, are stored in Hz
+    fn new<P: Copy + Into<Pitch>>(title: &str, tracks: &[&[(P, f32)]], key: &str) -> Self {
+        let in_hz = |track: &[(P, f32)]| -> Vec<(f32, f32)> {
+            track.iter().map(|&(pitch, duration)| (pitch.into().to_freq(), duration)).collect()
+        };
+        Self {
+            title: title.to_string(),
+            tracks: tracks.iter().map(|t| in_hz(t)).collect(),
+            default_bpm: REFERENCE_TEMPO.bpm,
+            key: Some(key.to_string()),
+            time_signature: (4, 4),
+            pickup: 0.0,
+            transpose_semitones: None,
+            pedal: None,
+            measures: Vec::new(),
         }
+    }
 
-        // Harmony misses
-        if harmony.len() > 1 {
-            if harmony[1].0 > 0.0 { harmony[1].0 = 0.0; missed_count += 1; }
+    // A song written bar by bar, in `time_signature` after an upbeat of
+    // `pickup` seconds; empty bars are filled with rests
+    fn from_measures(
+        title: &str,
+        measures: &[&'static [Measure<'static>]],
+        key: &str,
+        time_signature: (u32, u32),
+        pickup: f32,
+    ) -> Self {
+        let measure_secs = measure_secs(time_signature);
+        let tracks: Vec<Vec<(Pitch, f32)>> =
+            measures.iter().map(|bars| join_measures(bars, measure_secs, pickup)).collect();
+        let tracks: Vec<&[(Pitch, f32)]> = tracks.iter().map(Vec::as_slice).collect();
+        Self {
+            time_signature,
+            pickup,
+            measures: measures.to_vec(),
+            ..Self::new(title, &tracks, key)
         }
+    }
 
-        let mut missed_tracks = vec![melody.as_slice(), harmony.as_slice()];
-        if tracks.len() > 2 {
-            missed_tracks.extend_from_slice(&tracks[2..]);
+    fn fur_elise() -> Self {
+        Self {
+            transpose_semitones: Some(5),
+            pedal: Some(FUR_ELISE_PEDAL.to_vec()),
+            time_signature: (3, 8),
+            pickup: 2.0 * S,
+            ..Self::new("fur_elise", &[FUR_ELISE], "A minor")
         }
-
-        let filename = format!("{}_missed_both.wav", base_name);
-        generate(&filename, missed_tracks.clone(), 1.0);
-
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes - missed_count) as f32
-                / total_playable_notes as f32,
-            notes: get_notes(&missed_tracks, 1.0),
-        });
     }
 
-    // 7. Incorrect Notes (Melody)
-    if !tracks.is_empty() {
-        let mut melody = tracks[0].to_vec();
-        let mut incorrect_count = 0;
+    fn ode_to_joy() -> Self {
+        Self::new("ode_to_joy", &[ODE_TO_JOY], "C major")
+    }
 
-        if melody.len() > 10 {
-            if let Some(note) = melody.get_mut(5) {
-                if note.0 > 0.0 {
-                    note.0 += 20.0; // Detune
-                    incorrect_count += 1;
-                }
-            }
-            if let Some(note) = melody.get_mut(10) {
-                if note.0 > 0.0 {
-                    note.0 -= 20.0; // Detune
-                    incorrect_count += 1;
-                }
-            }
+    fn fur_elise_harmony() -> Self {
+        Self {
+            time_signature: (3, 8),
+            pickup: 2.0 * S,
+            ..Self::new("fur_elise_harmony", &[FUR_ELISE, FUR_ELISE_HARMONY], "A minor")
         }
+    }
 
-        let mut mod_tracks = vec![melody.as_slice()];
-        mod_tracks.extend_from_slice(&tracks[1..]);
+    fn ode_to_joy_harmony() -> Self {
+        Self::new("ode_to_joy_harmony", &[ODE_TO_JOY, ODE_TO_JOY_HARMONY], "C major")
+    }
 
-        let filename = format!("{}_incorrect_melody.wav", base_name);
-        generate(&filename, mod_tracks.clone(), 1.0);
+    fn twinkle() -> Self {
+        Self::new("twinkle", &[TWINKLE], "C major")
+    }
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes - incorrect_count) as f32
-                / total_playable_notes as f32,
-            notes: get_notes(&mod_tracks, 1.0),
-        });
+    fn greensleeves() -> Self {
+        Self {
+            time_signature: (6, 8),
+            pickup: E,
+            ..Self::new("greensleeves", &[GREENSLEEVES], "A minor")
+        }
     }
 
-    // 8. Incorrect Notes (Harmony)
-    if tracks.len() >= 2 {
-        let mut harmony = tracks[1].to_vec();
-        let mut incorrect_count = 0;
+    fn minuet_in_g() -> Self {
+        let measures = [MINUET_IN_G, MINUET_IN_G_BASS];
+        Self::from_measures("minuet_in_g", &measures, "G major", (3, 4), 0.0)
+    }
 
-        // Modify a couple of notes
-        let indices = [0, 2];
-        for &i in &indices {
-             if i < harmony.len() {
-                if harmony[i].0 > 0.0 {
-                    harmony[i].0 += 30.0;
-                    incorrect_count += 1;
-                }
-             }
+    /// The song in the file at `path`, for `--import`: a MIDI file (`.mid` or
+    /// `.midi`), one part per track with the melody first, or a melody in the
+    /// plain-text notation. It is played at the tempo it is written at.
+    fn import(path: &str) -> io::Result<Self> {
+        let is_midi = std::path::Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("mid") || ext.eq_ignore_ascii_case("midi"));
+        let mut tracks = if is_midi {
+            midi::read(path)?
+        } else {
+            let text = std::fs::read_to_string(path)?;
+            let melody = notation::parse(&text)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))?;
+            vec![melody]
+        };
+        // A MIDI file's tempo track has no notes of its own
+        tracks.retain(|track| track.iter().any(|&(freq, _)| freq > 0.0));
+        if tracks.is_empty() {
+            let message = format!("{}: no notes to play", path);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
         }
-
-        let mut mod_tracks = vec![tracks[0]];
-        let harmony_slice = harmony.as_slice();
-        mod_tracks.push(harmony_slice);
-        if tracks.len() > 2 {
-            mod_tracks.extend_from_slice(&tracks[2..]);
+        // Parts that stop early rest until the longest ends
+        let length = |track: &[(f32, f32)]| track.iter().map(|&(_, dur)| dur).sum::<f32>();
+        let longest = tracks.iter().map(|track| length(track)).fold(0.0, f32::max);
+        for track in &mut tracks {
+            let rest = longest - length(track);
+            if rest > 0.0 {
+                track.push((0.0, rest));
+            }
         }
+        let tracks: Vec<&[(f32, f32)]> = tracks.iter().map(Vec::as_slice).collect();
+        Ok(Self { key: None, ..Self::new(&cli::import_title(path), &tracks, "") })
+    }
 
-        let filename = format!("{}_incorrect_harmony.wav", base_name);
-        generate(&filename, mod_tracks.clone(), 1.0);
+    /// Every song, in generation order.
+    fn all() -> Vec<Self> {
+        vec![
+            Self::fur_elise(),
+            Self::ode_to_joy(),
+            Self::fur_elise_harmony(),
+            Self::ode_to_joy_harmony(),
+            Self::twinkle(),
+            Self::greensleeves(),
+            Self::minuet_in_g(),
+        ]
+    }
+}
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes - incorrect_count) as f32
-                / total_playable_notes as f32,
-            notes: get_notes(&mod_tracks, 1.0),
-        });
+/// `(start_sample, end_sample, freq)` of each note's slot, exactly as `Voice`
+/// plays it: slots are contiguous and `end_sample` is exclusive.
+fn note_timings(song: &[Note], speed_mult: f32, sample_rate: u32) -> Vec<(usize, usize, f32)> {
+    let rate = sample_rate as f32;
+    let mut time_cursor = 0.0f32;
+    song.iter()
+        .map(|note| {
+            let dur = note.duration * speed_mult;
+            // Use round to ensure contiguous sample ranges without floating point gaps
+            let start_sample = (time_cursor * rate).round() as usize;
+            let end_sample = ((time_cursor + dur) * rate).round() as usize;
+            time_cursor += dur;
+            (start_sample, end_sample, note.pitch.to_freq())
+        })
+        .collect()
+}
+
+// Start offset of each of `freqs` when rolled, or empty if struck together
+fn roll_delays(freqs: &[f32], roll: Roll) -> Vec<f32> {
+    if freqs.len() < 2 || roll.spread <= 0.0 {
+        return Vec::new();
+    }
+    let mut order: Vec<usize> = (0..freqs.len()).collect();
+    order.sort_by(|&a, &b| freqs[a].total_cmp(&freqs[b]));
+    if roll.downward {
+        order.reverse();
     }
+    let step = roll.spread / (freqs.len() - 1) as f32;
+    let mut delays = vec![0.0; freqs.len()];
+    for (rank, &bank) in order.iter().enumerate() {
+        delays[bank] = step * rank as f32;
+    }
+    delays
+}
 
-    // 9. Incorrect Notes (Both)
-    if tracks.len() >= 2 {
-        let mut melody = tracks[0].to_vec();
-        let mut harmony = tracks[1].to_vec();
-        let mut incorrect_count = 0;
+// Single voice state
+struct Voice {
+    // An oscillator per partial of `harmonics` for each pitch of the current
+    // note or chord
+    oscillators: Vec<Vec<Oscillator>>,
+    // Frequencies of the current note or chord, root first
+    freqs: Vec<f32>,
+    // Seconds each of `freqs` starts late in a rolled chord, empty if they
+    // are struck together
+    delays: Vec<f32>,
+    // State to track song position
+    sample_counter: usize,
+    current_note_idx: usize,
+    song: Vec<Note>,
+    // [start, end) sample of each note's slot, the sample it stops sounding,
+    // and the first note whose slot is not yet over
+    bounds: Vec<(usize, usize, usize)>,
+    cursor: usize,
+    sample_rate: u32,
+    envelope: Envelope,
+    harmonics: HarmonicProfile,
+    // `harmonics.partial_ratios()`, worked out once
+    partials: Vec<f32>,
+    // `velocity_tilt` of the current note
+    tilt: Vec<f32>,
+    // Stereo position, -1.0 (left) ..= 1.0 (right)
+    pan: f32,
+    // Level the voice joins the mix at
+    gain: f32,
+    // Last sounding root frequency, kept so its release can ring into a rest
+    last_freq: f32,
+    last_level: f32,
+    release_samples: usize,
+    // Whether the current note was struck with the sustain pedal down
+    sustained: bool,
+    // The current note's own attack time, if it has one
+    attack: Option<f32>,
+    // Pitch the current note glides in from, 0.0 if it starts on its own
+    glide_from: f32,
+    vibrato: Vibrato,
+    tremolo: Tremolo,
+    // Largest phase offset for each new note's partials, as a share of a
+    // cycle, and the generator drawing them; `None` starts every one at zero
+    phase_scatter: Option<(f32, Rng)>,
+    // Velocity multiplier for each note of `song`, empty if none is accented
+    accents: Vec<f32>,
+    velocity_curve: VelocityCurve,
+    // A second line of notes for the same hand, such as a bass note held
+    // while the melody moves above it; it keeps its own envelope
+    under: Option<Box<Voice>>,
+    // Seconds a slurred note fades out over under the next one, and the
+    // note doing so
+    crossfade: f32,
+    outgoing: Option<Outgoing>,
+}
 
-        if melody.len() > 8 {
-            if melody[8].0 > 0.0 { melody[8].0 += 20.0; incorrect_count += 1; }
-        }
-        if harmony.len() > 2 {
-             if harmony[2].0 > 0.0 { harmony[2].0 += 20.0; incorrect_count += 1; }
-        }
+/// A slurred note still fading out after the next pitch has begun.
+struct Outgoing {
+    oscillators: Vec<Vec<Oscillator>>,
+    freqs: Vec<f32>,
+    tilt: Vec<f32>,
+    // Root it was sounding at and its level when the next note began
+    root: f32,
+    level: f32,
+    elapsed: usize,
+    length: usize,
+}
 
-        let mut mod_tracks = vec![melody.as_slice(), harmony.as_slice()];
-        if tracks.len() > 2 {
-            mod_tracks.extend_from_slice(&tracks[2..]);
-        }
+impl Voice {
+    // `song` is `(Pitch, f32)` pairs as a song is written, or `Note`s
+    // carrying each note's velocity, articulation and chord as well
+    fn new<N: Into<Note>>(
+        song: Vec<N>,
+        speed_mult: f32,
+        sample_rate: u32,
+        envelope: Envelope,
+        harmonics: HarmonicProfile,
+        pan: f32,
+    ) -> Self {
+        let song: Vec<Note> = song.into_iter().map(Into::into).collect();
+        let bounds = note_timings(&song, speed_mult, sample_rate)
+            .into_iter()
+            .zip(&song)
+            .map(|((start_sample, end_sample, _), note)| {
+                let fraction = note.articulation.sounding_fraction();
+                let sounding = (end_sample - start_sample) as f32 * fraction;
+                (start_sample, end_sample, start_sample + sounding.round() as usize)
+            })
+            .collect();
 
-        let filename = format!("{}_incorrect_both.wav", base_name);
-        generate(&filename, mod_tracks.clone(), 1.0);
+        Self {
+            oscillators: Vec::new(),
+            freqs: Vec::new(),
+            delays: Vec::new(),
+            sample_counter: 0,
+            current_note_idx: usize::MAX,
+            song,
+            bounds,
+            cursor: 0,
+            sample_rate,
+            envelope,
+            partials: harmonics.partial_ratios(),
+            tilt: vec![1.0; harmonics.amplitudes.len()],
+            harmonics,
+            pan,
+            gain: VOICE_GAIN,
+            last_freq: 0.0,
+            last_level: 0.0,
+            release_samples: 0,
+            sustained: false,
+            attack: None,
+            glide_from: 0.0,
+            vibrato: Vibrato::default(),
+            tremolo: Tremolo::default(),
+            phase_scatter: None,
+            accents: Vec::new(),
+            velocity_curve: VelocityCurve::default(),
+            under: None,
+            crossfade: LEGATO_CROSSFADE_SECS,
+            outgoing: None,
+        }
+    }
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes - incorrect_count) as f32
-                / total_playable_notes as f32,
-            notes: get_notes(&mod_tracks, 1.0),
-        });
+    // Play `under` as a second line beneath the voice's own notes, each
+    // note with its own envelope, sharing the voice's timbre and stereo
+    // position
+    fn hold_under(&mut self, under: Vec<Note>, speed_mult: f32) {
+        let (rate, harmonics) = (self.sample_rate, self.harmonics.clone());
+        let line = Voice::new(under, speed_mult, rate, self.envelope, harmonics, self.pan);
+        self.under = Some(Box::new(line));
     }
 
-    // 10. Harmony Out of Sync (Slightly Late)
-    if tracks.len() >= 2 {
-        let mut harmony = vec![(0.0, 0.15)]; // 150ms delay
-        harmony.extend_from_slice(tracks[1]);
-        
-        let mut mod_tracks = vec![tracks[0]];
-        let harmony_slice = harmony.as_slice();
-        mod_tracks.push(harmony_slice);
-        if tracks.len() > 2 {
-            mod_tracks.extend_from_slice(&tracks[2..]);
+    // Start each note's partials at phases drawn from `seed`; a second line
+    // draws its own
+    fn scatter_phases(&mut self, spread: f32, seed: u64) {
+        self.phase_scatter = Some((spread.clamp(0.0, 1.0), Rng::new(seed)));
+        if let Some(line) = &mut self.under {
+            line.scatter_phases(spread, !seed);
         }
+    }
 
-        let filename = format!("{}_sync_slight_lag.wav", base_name);
-        generate(&filename, mod_tracks.clone(), 1.0);
-
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: 1.0,
-            notes: get_notes(&mod_tracks, 1.0),
-        });
+    // Turn velocities into amplitudes along `curve` in both lines
+    fn set_velocity_curve(&mut self, curve: VelocityCurve) {
+        self.velocity_curve = curve;
+        if let Some(line) = &mut self.under {
+            line.set_velocity_curve(curve);
+        }
     }
 
-    // 11. Harmony Out of Sync (Very Late)
-    if tracks.len() >= 2 {
-        let mut harmony = vec![(0.0, 0.35)]; // 350ms delay
-        harmony.extend_from_slice(tracks[1]);
-        
-        let mut mod_tracks = vec![tracks[0]];
-        let harmony_slice = harmony.as_slice();
-        mod_tracks.push(harmony_slice);
-        if tracks.len() > 2 {
-            mod_tracks.extend_from_slice(&tracks[2..]);
+    // Fade slurred notes of both lines into the next over `secs`
+    fn set_crossfade(&mut self, secs: f32) {
+        self.crossfade = secs.max(0.0);
+        if let Some(line) = &mut self.under {
+            line.set_crossfade(secs);
         }
+    }
 
-        let filename = format!("{}_sync_major_lag.wav", base_name);
-        generate(&filename, mod_tracks.clone(), 1.0);
+    // Accent the notes of both lines that start on a downbeat
+    fn accent_downbeats(&mut self, accent: Accent) {
+        self.accents = accent.multipliers(self.song.iter().map(|note| note.duration));
+        if let Some(line) = &mut self.under {
+            line.accent_downbeats(accent);
+        }
+    }
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: 1.0,
-            notes: get_notes(&mod_tracks, 1.0),
-        });
+    // `bend` is the factor the control track puts on every pitch
+    fn step(&mut self, pedal_down: bool, bend: f32) -> f32 {
+        let under = self.under.as_mut().map_or(0.0, |line| line.step(pedal_down, bend));
+        self.step_line(pedal_down, bend) + under
     }
 
-    // 12. Extra Notes (Melody - Simultaneous/Fat Finger)
-    if !tracks.is_empty() {
-        let melody = tracks[0];
-        let mut extra_track = Vec::new();
-        let mut extra_count = 0;
-        let indices = [2, 9, 14];
-
-        for (i, &(freq, dur)) in melody.iter().enumerate() {
-            if indices.contains(&i) && freq > 0.0 {
-                // Add a "fat finger" note (semitone up) for a short duration at the start
-                let fat_freq = freq * 1.05946; 
-                let fat_dur = 0.1;
-                if dur > fat_dur {
-                    extra_track.push((fat_freq, fat_dur));
-                    extra_track.push((0.0, dur - fat_dur));
-                } else {
-                    extra_track.push((fat_freq, dur));
-                }
-                extra_count += 1;
-            } else {
-                extra_track.push((0.0, dur));
-            }
+    // Next sample of the voice's own line of notes
+    fn step_line(&mut self, pedal_down: bool, bend: f32) -> f32 {
+        let sample_rate = self.sample_rate as f32;
+        let mut active_freq = 0.0;
+        let mut velocity = 1.0;
+        let mut amplitude = 1.0;
+        let mut note_elapsed = 0.0;
+        let mut glide = 0.0;
+        let mut found_note = false;
+
+        // Move past notes that have ended; samples only ever move forward
+        while self.bounds.get(self.cursor).is_some_and(|&(_, end, _)| self.sample_counter >= end) {
+            self.cursor += 1;
         }
+        let note_idx = self.cursor;
 
-        let mut mixed_tracks = tracks.to_vec();
-        let extra_slice = extra_track.as_slice();
-        mixed_tracks.push(extra_slice);
+        // Determine which note to play
+        if let Some(&(start_sample, _, sounding_end)) = self.bounds.get(self.cursor)
+            && self.sample_counter >= start_sample
+            && self.sample_counter < sounding_end
+        {
+            let note = &self.song[self.cursor];
+            active_freq = note.pitch.to_freq();
+            let accent = self.accents.get(self.cursor).copied().unwrap_or(1.0);
+            velocity = note.velocity * accent;
+            amplitude = self.velocity_curve.amplitude(note.velocity) * accent;
+            glide = note.glide;
+            // Calculate elapsed time based on sample difference to avoid jitter
+            note_elapsed = (self.sample_counter - start_sample) as f32 / sample_rate;
+            found_note = true;
+        }
 
-        let filename = format!("{}_extra_melody_simul.wav", base_name);
-        generate(&filename, mixed_tracks.clone(), 1.0);
+        // Increment sample counter for next call
+        self.sample_counter += 1;
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes as f32 - extra_count as f32)
-                / total_playable_notes as f32,
-            notes: get_notes(&mixed_tracks, 1.0),
-        });
-    }
+        // If freq is 0 (missed note), a staccato note has been let go, or the
+        // song is over, let the last note release
+        if !found_note || active_freq <= 0.0 {
+            return self.release_step(sample_rate);
+        }
 
-    // 13. Extra Notes (Melody - Before/Anticipation)
-    if !tracks.is_empty() {
-        let melody = tracks[0];
-        let mut extra_track = Vec::new();
-        let mut extra_count = 0;
-        // Insert extra note before index 6 and 12.
-        // This means modifying the segment corresponding to 5 and 11.
-        let indices_before = [6, 12];
-
-        for (i, &(freq, dur)) in melody.iter().enumerate() {
-            // Check if NEXT note is a target
-            if i + 1 < melody.len() && indices_before.contains(&(i + 1)) {
-                let next_freq = melody[i+1].0;
-                if next_freq > 0.0 {
-                    let extra_dur = 0.15;
-                    let extra_freq = next_freq * 0.94387; // Semitone down
-                    
-                    if dur > extra_dur {
-                        extra_track.push((0.0, dur - extra_dur));
-                        extra_track.push((extra_freq, extra_dur));
-                    } else {
-                        // Duration too short to anticipate, just push silence
-                        extra_track.push((0.0, dur));
-                    }
-                    extra_count += 1;
-                } else {
-                    extra_track.push((0.0, dur));
+        // Reset oscillators if new note (to reset phase for attack),
+        // unless the pedal is down and the strings keep ringing or the
+        // previous note was slurred or glides into this one
+        if note_idx != self.current_note_idx {
+            let slurred = self
+                .song
+                .get(self.current_note_idx)
+                .is_some_and(|prev| prev.articulation == Articulation::Legato);
+            // Only slide from a note that was still sounding
+            let still_sounding = self.release_samples == 0;
+            self.glide_from = if glide > 0.0 && still_sounding { self.last_freq } else { 0.0 };
+            self.current_note_idx = note_idx;
+            self.sustained = pedal_down;
+            let restrike = !pedal_down && !slurred && self.glide_from <= 0.0;
+            // Keep the slurred note ringing briefly rather than cutting it
+            // off where the new one starts from silence
+            let fade_length = (self.crossfade * sample_rate).round() as usize;
+            if slurred && still_sounding && self.glide_from <= 0.0 && fade_length > 0 {
+                self.outgoing = Some(Outgoing {
+                    oscillators: self.oscillators.clone(),
+                    freqs: self.freqs.clone(),
+                    tilt: self.tilt.clone(),
+                    root: self.last_freq,
+                    level: self.last_level,
+                    elapsed: 0,
+                    length: fade_length,
+                });
+            }
+            if restrike {
+                for bank in &mut self.oscillators {
+                    bank.fill(Oscillator::new(self.harmonics.waveform));
                 }
-            } else {
-                extra_track.push((0.0, dur));
             }
+            let note = &self.song[note_idx];
+            self.freqs.clear();
+            self.freqs.push(active_freq);
+            self.freqs.extend(note.chord.iter().map(|p| p.to_freq()));
+            let bank = vec![Oscillator::new(self.harmonics.waveform); self.partials.len()];
+            self.oscillators.resize(self.freqs.len(), bank);
+            if let Some((spread, rng)) = &mut self.phase_scatter
+                && restrike
+            {
+                for osc in self.oscillators.iter_mut().flatten() {
+                    osc.shift(*spread * rng.next_f32());
+                }
+            }
+            self.delays = roll_delays(&self.freqs, note.roll);
+            self.tilt = velocity_tilt(velocity, self.partials.len());
+            self.attack = note.attack;
         }
 
-        let mut mixed_tracks = tracks.to_vec();
-        let extra_slice = extra_track.as_slice();
-        mixed_tracks.push(extra_slice);
+        // Slide exponentially, so the pitch moves at a steady rate in cents
+        if self.glide_from > 0.0 && note_elapsed < glide {
+            let progress = note_elapsed / glide;
+            active_freq = self.glide_from * (active_freq / self.glide_from).powf(progress);
+        }
+        active_freq *= self.vibrato.ratio(note_elapsed) * bend;
 
-        let filename = format!("{}_extra_melody_before.wav", base_name);
-        generate(&filename, mixed_tracks.clone(), 1.0);
+        let envelope = self.level(note_elapsed);
+        let tremolo = self.tremolo.gain(note_elapsed);
+        self.last_freq = active_freq;
+        self.last_level = amplitude * envelope * tremolo;
+        self.release_samples = 0;
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes as f32 - extra_count as f32)
-                / total_playable_notes as f32,
-            notes: get_notes(&mixed_tracks, 1.0),
-        });
+        let outgoing = self.outgoing_step();
+        let sample = if self.delays.is_empty() {
+            self.mix_chord(active_freq, None) * amplitude * envelope * tremolo * self.gain
+        } else {
+            self.mix_chord(active_freq, Some(note_elapsed)) * amplitude * tremolo * self.gain
+        };
+        sample + outgoing
     }
 
-    // 14. Extra Notes (Melody - After/Ghost)
-    if !tracks.is_empty() {
-        let melody = tracks[0];
-        let mut extra_track = Vec::new();
-        let mut extra_count = 0;
-        let indices = [4, 10];
-
-        for (i, &(freq, dur)) in melody.iter().enumerate() {
-            if indices.contains(&i) && freq > 0.0 {
-                let extra_dur = 0.08;
-                let delay = 0.08;
-                let extra_freq = freq * 0.94387; // Semitone down
-                
-                if dur > (delay + extra_dur) {
-                    extra_track.push((0.0, delay));
-                    extra_track.push((extra_freq, extra_dur));
-                    extra_track.push((0.0, dur - delay - extra_dur));
-                    extra_count += 1;
-                } else {
-                    extra_track.push((0.0, dur));
-                }
-            } else {
-                extra_track.push((0.0, dur));
-            }
+    // Next sample of the slurred note fading out, if one still is
+    fn outgoing_step(&mut self) -> f32 {
+        let Some(mut outgoing) = self.outgoing.take() else {
+            return 0.0;
+        };
+        let fade = 1.0 - outgoing.elapsed as f32 / outgoing.length as f32;
+        // Play its saved oscillators as if they were the voice's own
+        std::mem::swap(&mut self.oscillators, &mut outgoing.oscillators);
+        std::mem::swap(&mut self.freqs, &mut outgoing.freqs);
+        std::mem::swap(&mut self.tilt, &mut outgoing.tilt);
+        let sample = self.mix_chord(outgoing.root, None);
+        std::mem::swap(&mut self.oscillators, &mut outgoing.oscillators);
+        std::mem::swap(&mut self.freqs, &mut outgoing.freqs);
+        std::mem::swap(&mut self.tilt, &mut outgoing.tilt);
+
+        outgoing.elapsed += 1;
+        let level = outgoing.level * fade;
+        if outgoing.elapsed < outgoing.length {
+            self.outgoing = Some(outgoing);
         }
+        sample * level * self.gain
+    }
 
-        let mut mixed_tracks = tracks.to_vec();
-        let extra_slice = extra_track.as_slice();
-        mixed_tracks.push(extra_slice);
+    // Envelope level `elapsed` seconds into the current note
+    fn level(&self, elapsed: f32) -> f32 {
+        let root = self.freqs.first().copied().unwrap_or(DECAY_REFERENCE_HZ);
+        let mut envelope = self.envelope.for_pitch(root);
+        if let Some(attack) = self.attack {
+            envelope.attack = attack;
+        }
+        if self.sustained {
+            envelope.decay *= SUSTAIN_DECAY_FACTOR;
+        }
+        envelope.level(elapsed)
+    }
 
-        let filename = format!("{}_extra_melody_after.wav", base_name);
-        generate(&filename, mixed_tracks.clone(), 1.0);
+    // Fade out the last sounding note over the envelope's release time
+    fn release_step(&mut self, sample_rate: f32) -> f32 {
+        let elapsed = self.release_samples as f32 / sample_rate;
+        self.release_samples += 1;
+        if self.last_freq <= 0.0 || elapsed >= self.envelope.release {
+            return 0.0;
+        }
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes as f32 - extra_count as f32)
-                / total_playable_notes as f32,
-            notes: get_notes(&mixed_tracks, 1.0),
-        });
+        let level = self.last_level * (1.0 - elapsed / self.envelope.release);
+        self.mix_chord(self.last_freq, None) * level * self.gain
     }
 
-    // 15. Extra Notes (Harmony - Simultaneous)
-    if tracks.len() >= 2 {
-        let harmony = tracks[1];
-        let mut extra_track = Vec::new();
-        let mut extra_count = 0;
-        let indices = [1, 3];
-
-        for (i, &(freq, dur)) in harmony.iter().enumerate() {
-            if indices.contains(&i) && freq > 0.0 {
-                let fat_freq = freq * 1.05946; 
-                let fat_dur = 0.15;
-                if dur > fat_dur {
-                    extra_track.push((fat_freq, fat_dur));
-                    extra_track.push((0.0, dur - fat_dur));
-                } else {
-                    extra_track.push((fat_freq, dur));
+    // Mix every pitch of the current note, moved so the root sounds at `root`.
+    // Chords are scaled for equal power, so a full chord is no louder than
+    // a single note. Given the time into a rolled chord, each pitch gets its
+    // own envelope from when it is struck; otherwise the caller applies one.
+    fn mix_chord(&mut self, root: f32, rolled_elapsed: Option<f32>) -> f32 {
+        let ratio = if root == self.freqs[0] { 1.0 } else { root / self.freqs[0] };
+        let mut mixed = 0.0;
+        for bank in 0..self.freqs.len() {
+            let sample = self.mix_harmonics(bank, self.freqs[bank] * ratio);
+            mixed += match rolled_elapsed {
+                Some(elapsed) => {
+                    let since = elapsed - self.delays[bank];
+                    if since < 0.0 { 0.0 } else { sample * self.level(since) }
                 }
-                extra_count += 1;
-            } else {
-                extra_track.push((0.0, dur));
+                None => sample,
+            };
+        }
+        mixed / (self.freqs.len() as f32).sqrt()
+    }
+
+    // Calculate sample by mixing harmonics
+    fn mix_harmonics(&mut self, bank: usize, freq: f32) -> f32 {
+        let mut mixed = 0.0;
+        // Scale so each oscillator step covers one of our samples
+        let rate_scale = OSC_RATE / self.sample_rate as f32;
+        let nyquist = self.sample_rate as f32 / 2.0;
+
+        for (i, osc) in self.oscillators[bank].iter_mut().enumerate() {
+            // A partial past Nyquist would fold back down as a false pitch
+            if freq * self.partials[i] >= nyquist {
+                continue;
             }
+            let h_freq = freq * self.partials[i] * rate_scale;
+            mixed += osc.step(h_freq) * self.harmonics.amplitudes[i] * self.tilt[i];
         }
 
-        let mut mixed_tracks = tracks.to_vec();
-        let extra_slice = extra_track.as_slice();
-        mixed_tracks.push(extra_slice);
+        mixed
+    }
 
-        let filename = format!("{}_extra_harmony_simul.wav", base_name);
-        generate(&filename, mixed_tracks.clone(), 1.0);
+    // Constant-power (left, right) gains for the voice's pan position
+    fn pan_gains(&self) -> (f32, f32) {
+        let angle = (self.pan.clamp(-1.0, 1.0) + 1.0) * std::f32::consts::FRAC_PI_4;
+        (angle.cos(), angle.sin())
+    }
+}
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes as f32 - extra_count as f32)
-                / total_playable_notes as f32,
-            notes: get_notes(&mixed_tracks, 1.0),
-        });
+/// A control track as it plays: each value with the sample it ends on, and
+/// a cursor on the one in effect, which only ever moves forward.
+#[derive(Default)]
+struct Control<T> {
+    bounds: Vec<(T, usize)>,
+    cursor: usize,
+}
+
+impl<T: Copy> Control<T> {
+    fn new(track: &[(T, f32)], speed_mult: f32, sample_rate: u32) -> Self {
+        let sample_rate = sample_rate as f32;
+        let mut time_cursor = 0.0f32;
+        let bounds = track
+            .iter()
+            .map(|&(value, dur_raw)| {
+                let dur = dur_raw * speed_mult;
+                let end_sample = ((time_cursor + dur) * sample_rate).round() as usize;
+                time_cursor += dur;
+                (value, end_sample)
+            })
+            .collect();
+        Self { bounds, cursor: 0 }
     }
 
-    // 16. Extra Notes (Harmony - Before)
-    if tracks.len() >= 2 {
-        let harmony = tracks[1];
-        let mut extra_track = Vec::new();
-        let mut extra_count = 0;
-        let indices_before = [2]; 
-
-        for (i, &(freq, dur)) in harmony.iter().enumerate() {
-             if i + 1 < harmony.len() && indices_before.contains(&(i + 1)) {
-                let next_freq = harmony[i+1].0;
-                if next_freq > 0.0 {
-                    let extra_dur = 0.2;
-                    let extra_freq = next_freq * 0.94387; 
-                    
-                    if dur > extra_dur {
-                        extra_track.push((0.0, dur - extra_dur));
-                        extra_track.push((extra_freq, extra_dur));
-                    } else {
-                        extra_track.push((0.0, dur));
-                    }
-                    extra_count += 1;
-                } else {
-                    extra_track.push((0.0, dur));
-                }
-            } else {
-                extra_track.push((0.0, dur));
-            }
+    // Value at `sample`, `None` once the track is over; `sample` must not go
+    // back on the last one asked for
+    fn at(&mut self, sample: usize) -> Option<T> {
+        while self.bounds.get(self.cursor).is_some_and(|&(_, end)| sample >= end) {
+            self.cursor += 1;
         }
+        self.bounds.get(self.cursor).map(|&(value, _)| value)
+    }
+}
 
-        let mut mixed_tracks = tracks.to_vec();
-        let extra_slice = extra_track.as_slice();
-        mixed_tracks.push(extra_slice);
+// State of the synthesizer.
+struct Processors {
+    voices: Vec<Voice>,
+    sustain: Control<bool>,
+    bend: Control<f32>,
+    // Scale on the summed voices
+    master_gain: f32,
+    speed_mult: f32,
+    sample_rate: u32,
+    sample_counter: usize,
+}
 
-        let filename = format!("{}_extra_harmony_before.wav", base_name);
-        generate(&filename, mixed_tracks.clone(), 1.0);
+impl Processors {
+    // `profiles` gives each track's timbre; tracks past its end use the piano
+    fn new(
+        tracks: Vec<Vec<Note>>,
+        profiles: &[HarmonicProfile],
+        speed_mult: f32,
+        sample_rate: u32,
+    ) -> Self {
+        let num_tracks = tracks.len();
+        Self {
+            voices: tracks
+                .into_iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    let pan = match (num_tracks, i) {
+                        (1, _) => 0.0,
+                        (_, 0) => MELODY_PAN,
+                        _ => HARMONY_PAN,
+                    };
+                    let profile = profiles.get(i).cloned().unwrap_or_else(HarmonicProfile::piano);
+                    let envelope = Envelope::piano_default();
+                    Voice::new(s, speed_mult, sample_rate, envelope, profile, pan)
+                })
+                .collect(),
+            sustain: Control::default(),
+            bend: Control::default(),
+            master_gain: MASTER_GAIN,
+            speed_mult,
+            sample_rate,
+            sample_counter: 0,
+        }
+    }
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes as f32 - extra_count as f32)
-                / total_playable_notes as f32,
-            notes: get_notes(&mixed_tracks, 1.0),
-        });
+    // As `new`, with the sustain pedal held down where `sustain` says
+    fn with_sustain(
+        tracks: Vec<Vec<Note>>,
+        profiles: &[HarmonicProfile],
+        sustain: &[(bool, f32)],
+        speed_mult: f32,
+        sample_rate: u32,
+    ) -> Self {
+        let mut proc = Self::new(tracks, profiles, speed_mult, sample_rate);
+        proc.sustain = Control::new(sustain, speed_mult, sample_rate);
+        proc
     }
 
-    // 17. Extra Notes (Harmony - After)
-    if tracks.len() >= 2 {
-        let harmony = tracks[1];
-        let mut extra_track = Vec::new();
-        let mut extra_count = 0;
-        let indices = [0, 2];
-
-        for (i, &(freq, dur)) in harmony.iter().enumerate() {
-            if indices.contains(&i) && freq > 0.0 {
-                let extra_dur = 0.08;
-                let delay = 0.08;
-                let extra_freq = freq * 0.94387; 
-                
-                if dur > (delay + extra_dur) {
-                    extra_track.push((0.0, delay));
-                    extra_track.push((extra_freq, extra_dur));
-                    extra_track.push((0.0, dur - delay - extra_dur));
-                    extra_count += 1;
-                } else {
-                    extra_track.push((0.0, dur));
-                }
-            } else {
-                extra_track.push((0.0, dur));
+    // Give each voice its own phase offsets, all drawn from `scatter.seed`
+    fn scatter_phases(&mut self, scatter: PhaseScatter) {
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            voice.scatter_phases(scatter.spread, scatter.seed.wrapping_add(i as u64));
+        }
+    }
+
+    // Set the melody's level, every other voice's, and the whole mix's; a
+    // second line in a voice follows it
+    fn set_balance(&mut self, balance: Balance) {
+        self.master_gain = balance.master;
+        for (i, voice) in self.voices.iter_mut().enumerate() {
+            let gain = if i == 0 { balance.melody } else { balance.harmony };
+            voice.gain = gain;
+            if let Some(line) = &mut voice.under {
+                line.gain = gain;
             }
         }
+    }
 
-        let mut mixed_tracks = tracks.to_vec();
-        let extra_slice = extra_track.as_slice();
-        mixed_tracks.push(extra_slice);
+    // Turn every voice's velocities into amplitudes along `curve`
+    fn set_velocity_curve(&mut self, curve: VelocityCurve) {
+        for voice in &mut self.voices {
+            voice.set_velocity_curve(curve);
+        }
+    }
 
-        let filename = format!("{}_extra_harmony_after.wav", base_name);
-        generate(&filename, mixed_tracks.clone(), 1.0);
+    // Fade every voice's slurred notes into the next over `secs`
+    fn set_crossfade(&mut self, secs: f32) {
+        for voice in &mut self.voices {
+            voice.set_crossfade(secs);
+        }
+    }
 
-        variations.push(VariationInfo {
-            filename,
-            ideal_filename: original_filename.clone(),
-            tempo_accuracy: 1.0,
-            pitch_accuracy: (total_playable_notes as f32 - extra_count as f32)
-                / total_playable_notes as f32,
-            notes: get_notes(&mixed_tracks, 1.0),
-        });
+    // Accent every voice's downbeats
+    fn accent_downbeats(&mut self, accent: Accent) {
+        for voice in &mut self.voices {
+            voice.accent_downbeats(accent);
+        }
+    }
+
+    // Longest any voice's last note takes to release after the song ends
+    fn release_secs(&self) -> f32 {
+        self.voices.iter().map(|voice| voice.envelope.release).fold(0.0, f32::max)
+    }
+
+    // Whether the sustain pedal is held at the current sample
+    fn pedal_down(&mut self) -> bool {
+        self.sustain.at(self.sample_counter).unwrap_or(false)
     }
 
-    variations
+    // Factor on every voice's pitch at the current sample
+    fn bend_ratio(&mut self) -> f32 {
+        self.bend.at(self.sample_counter).map_or(1.0, |semitones| 2f32.powf(semitones / 12.0))
+    }
+
+    // Synthesis logic
+    fn step(&mut self) -> Frame<Ch32, 2> {
+        let pedal_down = self.pedal_down();
+        let bend = self.bend_ratio();
+        self.sample_counter += 1;
+
+        let (mut left, mut right) = (0.0, 0.0);
+        for voice in &mut self.voices {
+            let sample = voice.step(pedal_down, bend);
+            let (left_gain, right_gain) = voice.pan_gains();
+            left += sample * left_gain;
+            right += sample * right_gain;
+        }
+
+        let (left, right) = (left * self.master_gain, right * self.master_gain);
+        Frame::<Ch32, 2>::new(Ch32::new(left), Ch32::new(right))
+    }
 }
 
-fn main() {
-    let mut all_variations = Vec::new();
+#[derive(Serialize, Deserialize)]
+struct NoteInfo {
+    /// Nearest note, with how many cents off it when out of tune, e.g.
+    /// "A4 (+12c)".
+    name: String,
+    #[serde(rename = "frequency")]
+    freq: f32,
+    /// Other frequencies of a chord rooted on `freq`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    chord: Vec<f32>,
+    duration: f32,
+    velocity: f32,
+}
 
-    // Fur Elise
-    all_variations.extend(generate_variations("fur_elise", vec![FUR_ELISE]));
+#[derive(Default, Serialize, Deserialize)]
+struct VariationInfo {
+    /// [`Song::title`] of the piece played.
+    title: String,
+    default_bpm: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    /// Key found from the written notes; a melody alone may come out as a
+    /// closely related key instead of `key`.
+    estimated_key: String,
+    filename: String,
+    ideal_filename: String,
+    #[serde(rename = "expected_tempo_accuracy")]
+    tempo_accuracy: f32,
+    #[serde(rename = "expected_pitch_accuracy")]
+    pitch_accuracy: f32,
+    /// What `score::tempo_accuracy` and `score::pitch_accuracy` make of the
+    /// file as written, against the ground truth of `ideal_filename`: the
+    /// onsets `analyze` detects, and the pitches it hears in the melody's
+    /// notes. A check on the expected scores above, which are set by hand.
+    measured_tempo_accuracy: f32,
+    measured_pitch_accuracy: f32,
+    notes: Vec<Vec<NoteInfo>>,
+    /// Notes played that are not in the score, as opposed to wrong pitches.
+    expected_extra_notes: usize,
+    /// Melody (track 0) score indices that were struck twice in a row.
+    stutter_positions: Vec<usize>,
+    /// Per track, `(start_sample, end_sample, frequency)` of every note as
+    /// rendered, rests included.
+    onsets_samples: Vec<Vec<(usize, usize, f32)>>,
+    /// Every track and chord tone of `notes` merged into `(start_secs,
+    /// end_secs, frequencies)` segments: what should be sounding when.
+    timeline: Vec<(f32, f32, Vec<f32>)>,
+    /// Intended velocity of each melody note, when the variation shapes it;
+    /// amplitude follows it along the manifest's `velocity_curve`.
+    dynamics: Vec<f32>,
+    /// Each track rendered alone, in track order, for songs with several.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    stems: Vec<String>,
+    /// Notes held too long or let go too soon, for the variations that have
+    /// them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    duration_errors: Vec<DurationError>,
+    /// Silence the player stopped for, if the variation has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pause: Option<PauseInfo>,
+    /// Frames `--trim` cut from the start and end of `filename`; the sample
+    /// positions and times above already allow for them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    trimmed_samples: Option<(usize, usize)>,
+    /// Peak and RMS level of `filename` as written.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    levels: Option<Levels>,
+    /// Other stereo files rendered with `filename`, which `--trim` cuts to
+    /// the same span as it: the melody-only and accompaniment copies.
+    #[serde(skip)]
+    copies: Vec<String>,
+    /// Likewise for its mono copy.
+    #[serde(skip)]
+    mono_copy: Option<String>,
+    /// `ideal_filename` then `filename`, with `--ab`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ab: Option<AbInfo>,
+}
 
-    // Ode to Joy
-    all_variations.extend(generate_variations("ode_to_joy", vec![ODE_TO_JOY]));
+/// A note that sounds longer or shorter than written, though it starts on
+/// time.
+#[derive(Serialize, Deserialize)]
+struct DurationError {
+    /// Melody (track 0) score index of the note.
+    note: usize,
+    /// Seconds it sounds past (positive) or short of its written length.
+    change_secs: f32,
+}
 
-    // Fur Elise (Polyphonic)
-    all_variations.extend(generate_variations("fur_elise_harmony", vec![FUR_ELISE, FUR_ELISE_HARMONY]));
+/// Where a performance stops for a moment and then carries on.
+#[derive(Serialize, Deserialize)]
+struct PauseInfo {
+    /// Melody (track 0) score index of the first note after the pause.
+    before_note: usize,
+    /// Sample the pause starts on, and how many it lasts.
+    start_sample: usize,
+    length_samples: usize,
+}
 
-    // Ode to Joy (Polyphonic)
-    all_variations.extend(generate_variations("ode_to_joy_harmony", vec![ODE_TO_JOY, ODE_TO_JOY_HARMONY]));
+// Resolve song tables of any note format into `Note`s
+fn to_notes<N: Clone + Into<Note>>(tracks: &[&[N]]) -> Vec<Vec<Note>> {
+    tracks
+        .iter()
+        .map(|track| track.iter().cloned().map(Into::into).collect())
+        .collect()
+}
 
-    // Generate JSON
-    let json_items: Vec<String> = all_variations
+// `to_notes`, refusing any track `validate_song` finds fault with
+fn checked_notes<N: Clone + Into<Note>>(tracks: &[&[N]]) -> io::Result<Vec<Vec<Note>>> {
+    let tracks = to_notes(tracks);
+    let mut errors = Vec::new();
+    for (i, track) in tracks.iter().enumerate() {
+        let song: Vec<(f32, f32)> = track.iter().map(|n| (n.pitch.to_freq(), n.duration)).collect();
+        if let Err(found) = validate_song(&song) {
+            errors.extend(found.into_iter().map(|e| (i, e)));
+        }
+    }
+    if errors.is_empty() { Ok(tracks) } else { Err(invalid_song(&errors)) }
+}
+
+fn invalid_song(errors: &[(usize, SongError)]) -> io::Error {
+    let lines: Vec<String> =
+        errors.iter().map(|(track, e)| format!("track {}: {}", track, e)).collect();
+    io::Error::new(io::ErrorKind::InvalidData, lines.join("; "))
+}
+
+fn get_notes<N: Clone + Into<Note>>(tracks: &[&[N]], tempo: Tempo) -> Vec<Vec<NoteInfo>> {
+    let speed_mult = tempo.speed_mult();
+    to_notes(tracks)
         .iter()
-        .map(|v| {
-            let tracks_json = v
-                .notes
+        .map(|track| {
+            track
                 .iter()
-                .map(|track| {
-                    let notes_json = track
-                        .iter()
-                        .map(|note| {
-                            format!(
-                                "{{\"name\": \"{}\", \"frequency\": {:.2}, \"duration\": {:.3}}}",
-                                note.name, note.freq, note.duration
-                            )
-                        })
-                        .collect::<Vec<_>>()
-                        .join(", ");
-                    format!("[{}]", notes_json)
+                .map(|note| {
+                    let freq = note.pitch.to_freq();
+                    NoteInfo {
+                        name: freq_to_name(freq, NAME_TOLERANCE_CENTS),
+                        freq,
+                        chord: note.chord.iter().map(|p| p.to_freq()).collect(),
+                        duration: note.duration * speed_mult,
+                        velocity: note.velocity,
+                    }
                 })
-                .collect::<Vec<_>>()
-                .join(", ");
+                .collect()
+        })
+        .collect()
+}
+
+// Sample-accurate note slots of each track, at the default sample rate
+fn get_onsets<N>(tracks: &[&[N]], tempo: Tempo, sample_rate: u32) -> Vec<Vec<(usize, usize, f32)>>
+where
+    N: Clone + Into<Note>,
+{
+    let speed_mult = tempo.speed_mult();
+    to_notes(tracks)
+        .iter()
+        .map(|track| note_timings(track, speed_mult, sample_rate))
+        .collect()
+}
+
+/// Settings for turning a song into a file, beyond the notes themselves.
+#[derive(Clone, Copy)]
+struct RenderOptions<'a> {
+    sample_rate: u32,
+    /// Each track's timbre; tracks past the end are played on the piano.
+    profiles: &'a [HarmonicProfile],
+    /// When the sustain pedal is held, for the whole performance.
+    sustain: &'a [(bool, f32)],
+    /// Pitch bend over time, applied to every voice.
+    bend: &'a [(f32, f32)],
+    /// A second line of notes held beneath the melody (track 0), each with
+    /// its own envelope; empty for none.
+    under: &'a [Note],
+    /// Pitch and loudness wobble on the melody only.
+    vibrato: Vibrato,
+    tremolo: Tremolo,
+    reverb: Option<Reverb>,
+    /// Lowpass cutoff in Hz, for a muffled recording.
+    lowpass_hz: Option<f32>,
+    noise: Option<Noise>,
+    /// Scale the finished mix so its loudest sample sits at this level.
+    normalize_peak: Option<f32>,
+    /// Scale the finished mix by this instead, as normalizing another
+    /// render did, so a stem keeps its level in the mix.
+    gain: Option<f32>,
+    /// Mix in a metronome at this many beats per minute.
+    click_bpm: Option<f32>,
+    /// Start each note's partials at random phases rather than all at zero.
+    phase_scatter: Option<PhaseScatter>,
+    /// Levels of the voices and the mix, before any post-processing.
+    balance: Balance,
+    /// Play the first note of each measure louder.
+    accent: Option<Accent>,
+    /// Seconds a slurred note fades out over while the next comes in; 0.0
+    /// joins them with no fade.
+    legato_crossfade: f32,
+    /// How each note's velocity sets its amplitude.
+    velocity_curve: VelocityCurve,
+    /// Sample encoding `generate` writes the file in.
+    bit_depth: BitDepth,
+    /// Directory `generate` writes the file into.
+    out_dir: &'a str,
+}
+
+impl Default for RenderOptions<'_> {
+    fn default() -> Self {
+        Self {
+            sample_rate: SAMPLE_RATE,
+            profiles: &[],
+            sustain: &[],
+            bend: &[],
+            under: &[],
+            vibrato: Vibrato::default(),
+            tremolo: Tremolo::default(),
+            reverb: None,
+            lowpass_hz: None,
+            noise: None,
+            normalize_peak: None,
+            gain: None,
+            click_bpm: None,
+            phase_scatter: None,
+            balance: EVEN_BALANCE,
+            accent: None,
+            legato_crossfade: LEGATO_CROSSFADE_SECS,
+            velocity_curve: VelocityCurve::default(),
+            bit_depth: BitDepth::default(),
+            out_dir: "target_music",
+        }
+    }
+}
+
+/// Render `tracks` as `options` ask, without touching the filesystem, into
+/// the mix that `generate` writes, before it is rounded to the file's bit
+/// depth.
+fn render<N: Clone + Into<Note>>(
+    tracks: Vec<&[N]>,
+    speed_mult: f32,
+    options: RenderOptions,
+) -> io::Result<Audio<Ch32, 2>> {
+    let mut audio = Audio::with_silence(options.sample_rate, 0);
+    let (proc, total_duration) = processors(tracks, speed_mult, options)?;
+    render_into(&mut audio, proc, total_duration, options);
+    Ok(audio)
+}
+
+// The voices that play `tracks` as `options` ask, and how long they last
+fn processors<N: Clone + Into<Note>>(
+    tracks: Vec<&[N]>,
+    speed_mult: f32,
+    options: RenderOptions,
+) -> io::Result<(Processors, f32)> {
+    let tracks = checked_notes(&tracks)?;
+    let total_duration = total_duration(&tracks, speed_mult);
+    let (profiles, sustain, rate) = (options.profiles, options.sustain, options.sample_rate);
+    let proc = Processors::with_sustain(tracks, profiles, sustain, speed_mult, rate);
+    Ok((proc, total_duration))
+}
+
+/// Render one note of `freq` Hz held for `duration` seconds in `profile`'s
+/// timbre, through the full envelope and the default post-processing, for
+/// checking a single pitch or timbre on its own.
+fn render_note(
+    freq: f32,
+    duration: f32,
+    profile: &HarmonicProfile,
+    sample_rate: u32,
+) -> io::Result<Audio<Ch16, 2>> {
+    let note = [Note::from((freq, duration))];
+    let profiles = std::slice::from_ref(profile);
+    let options = RenderOptions { sample_rate, profiles, ..Default::default() };
+    let audio = render(vec![&note[..]], 1.0, options)?;
+    Ok(Audio::with_audio(sample_rate, &audio))
+}
+
+// Render `tracks` and write the mix to `{options.out_dir}/{filename}`
+fn generate<N: Clone + Into<Note>>(
+    filename: &str,
+    tracks: Vec<&[N]>,
+    tempo: Tempo,
+    options: RenderOptions,
+) -> io::Result<()> {
+    let mut audio = Audio::with_silence(options.sample_rate, 0);
+    generate_into(&mut audio, filename, tracks, tempo, options)
+}
+
+// Like `generate`, but render into `audio`, which is only reallocated if its
+// length or sample rate doesn't suit these tracks, so renders of the same
+// length can share one buffer
+fn generate_into<N: Clone + Into<Note>>(
+    audio: &mut Audio<Ch32, 2>,
+    filename: &str,
+    tracks: Vec<&[N]>,
+    tempo: Tempo,
+    options: RenderOptions,
+) -> io::Result<()> {
+    let (proc, total_duration) = processors(tracks, tempo.speed_mult(), options)?;
+    render_into(audio, proc, total_duration, options);
+    write_wav(audio, options.out_dir, filename, options.bit_depth)
+}
+
+// Calculate total duration (max of all tracks)
+fn total_duration(tracks: &[Vec<Note>], speed_mult: f32) -> f32 {
+    tracks.iter()
+        .map(|track| track.iter().map(|n| n.duration * speed_mult).sum::<f32>())
+        .fold(0.0, f32::max)
+}
+
+// Write `audio` to `{dir}/{filename}`
+fn write_wav(
+    audio: &Audio<Ch32, 2>,
+    dir: &str,
+    filename: &str,
+    depth: BitDepth,
+) -> io::Result<()> {
+    progress!("Writing {}", filename);
+    let path = format!("{}/{}", dir, filename);
+    wav::write(audio, path.as_str(), depth).map_err(at_path(&path))
+}
+
+// Name the file an I/O error happened on, for the message `main` prints
+fn at_path(path: &str) -> impl FnOnce(io::Error) -> io::Error + '_ {
+    move |err| io::Error::new(err.kind(), format!("{}: {}", path, err))
+}
+
+// Write `value` to `path` as pretty-printed JSON
+fn write_json<T: Serialize + ?Sized>(path: &str, value: &T) -> io::Result<()> {
+    let json = serde_json::to_string_pretty(value).map_err(io::Error::from).map_err(at_path(path))?;
+    std::fs::write(path, json).map_err(at_path(path))
+}
+
+// Render and post-process into `audio`, replacing it only when it is the
+// wrong size; returns the gain the mix was scaled by
+fn render_into(
+    audio: &mut Audio<Ch32, 2>,
+    mut proc: Processors,
+    total_duration: f32,
+    options: RenderOptions,
+) -> f32 {
+    let sample_rate = proc.sample_rate;
+    // The second line first, so everything set below reaches it too
+    if let Some(melody) = proc.voices.first_mut() {
+        if !options.under.is_empty() {
+            melody.hold_under(options.under.to_vec(), proc.speed_mult);
+        }
+        melody.vibrato = options.vibrato;
+        melody.tremolo = options.tremolo;
+    }
+    proc.bend = Control::new(options.bend, proc.speed_mult, sample_rate);
+    if let Some(scatter) = options.phase_scatter {
+        proc.scatter_phases(scatter);
+    }
+    proc.set_balance(options.balance);
+    proc.set_crossfade(options.legato_crossfade);
+    proc.set_velocity_curve(options.velocity_curve);
+    if let Some(accent) = options.accent {
+        proc.accent_downbeats(accent);
+    }
+
+    // Room after the last note for its release and any reverb to die away
+    // (RT60, so 60 dB down), and for the fade-out to fall on silence
+    let reverb_tail = options.reverb.map_or(0.0, |reverb| reverb.decay);
+    let tail = proc.release_secs() + reverb_tail + FADE_OUT_SECS;
+    let buffer_len = (sample_rate as f32 * (total_duration + tail)).ceil() as usize;
+    if audio.len() != buffer_len || audio.sample_rate().get() != sample_rate {
+        *audio = Audio::with_silence(sample_rate, buffer_len);
+    }
+
+    // Synthesize frame by frame, overwriting whatever was rendered before;
+    // twang's `Synth` only streams at 48 kHz
+    for frame in audio.iter_mut() {
+        *frame = proc.step();
+    }
+
+    if let Some(Reverb { decay, mix }) = options.reverb {
+        fx::reverb(audio, decay, mix);
+    }
+    if let Some(cutoff) = options.lowpass_hz {
+        fx::lowpass(audio, cutoff);
+    }
+    // Dry, after any reverb, so every click stays sharp
+    if let Some(bpm) = options.click_bpm {
+        let beats = (total_duration * bpm / 60.0).ceil() as usize;
+        metronome::mix_in(audio, &metronome::generate_click(bpm, beats, sample_rate), CLICK_PAN);
+    }
+    if let Some(Noise { snr_db, seed }) = options.noise {
+        fx::add_noise(audio, snr_db, seed);
+    }
+    // Second pass, once the true peak of the whole mix is known
+    let mut gain = 1.0;
+    if let Some(peak) = options.normalize_peak {
+        gain = fx::normalize(audio, peak);
+    } else if let Some(fixed) = options.gain {
+        fx::amplify(audio, fixed);
+        gain = fixed;
+    }
+    fx::fade(audio, FADE_IN_SECS, FADE_OUT_SECS);
+    gain
+}
+
+// Render the mix of `tracks` to `{base_name}.wav` and each track alone to
+// `{base_name}_stem{i}.wav`, returning the stem filenames. Each stem keeps
+// its stereo position and the mix's normalization, so the stems add up to
+// the mix. For practising one hand against the other, the melody alone also
+// goes to `{base_name}_melody.wav` and every other track to
+// `{base_name}_accompaniment.wav`; those two names follow the stems'.
+fn generate_stems(
+    base_name: &str,
+    tracks: Vec<&[(f32, f32)]>,
+    speed_mult: f32,
+    render: RenderOptions,
+) -> io::Result<(Vec<String>, Vec<String>)> {
+    let tracks = checked_notes(&tracks)?;
+    let total_duration = total_duration(&tracks, speed_mult);
+    let options = RenderOptions {
+        normalize_peak: (tracks.len() > 1).then_some(POLYPHONIC_PEAK),
+        ..render
+    };
+    let mut audio = Audio::with_silence(options.sample_rate, 0);
+    let (sustain, rate) = (options.sustain, options.sample_rate);
+    let proc = Processors::with_sustain(tracks.clone(), &[], sustain, speed_mult, rate);
+    let gain = render_into(&mut audio, proc, total_duration, options);
+    write_wav(&audio, options.out_dir, &format!("{}.wav", base_name), options.bit_depth)?;
+
+    let stem_options = RenderOptions { normalize_peak: None, gain: Some(gain), ..options };
+    let mut render_part = |keep: &dyn Fn(usize) -> bool, filename: &str| {
+        // The other tracks stay as silent voices, so the pans are unchanged
+        let part: Vec<Vec<Note>> = tracks
+            .iter()
+            .enumerate()
+            .map(|(j, track)| if keep(j) { track.clone() } else { Vec::new() })
+            .collect();
+        let proc = Processors::with_sustain(part, &[], sustain, speed_mult, rate);
+        render_into(&mut audio, proc, total_duration, stem_options);
+        write_wav(&audio, options.out_dir, filename, options.bit_depth)
+    };
+    let mut stems = Vec::new();
+    for i in 0..tracks.len() {
+        let filename = format!("{}_stem{}.wav", base_name, i);
+        render_part(&|j| j == i, &filename)?;
+        stems.push(filename);
+    }
+    let copies = [format!("{}_melody.wav", base_name), format!("{}_accompaniment.wav", base_name)];
+    render_part(&|j| j == 0, &copies[0])?;
+    render_part(&|j| j > 0, &copies[1])?;
+    Ok((stems, copies.to_vec()))
+}
+
+// Render `tracks` at the reference tempo, as their original is
+fn render_at_reference<N: Clone + Into<Note>>(
+    tracks: &[&[N]],
+    sample_rate: u32,
+) -> io::Result<Audio<Ch32, 2>> {
+    render_at_tempo(tracks, REFERENCE_TEMPO, sample_rate)
+}
+
+fn render_at_tempo<N: Clone + Into<Note>>(
+    tracks: &[&[N]],
+    tempo: Tempo,
+    sample_rate: u32,
+) -> io::Result<Audio<Ch32, 2>> {
+    let options = RenderOptions {
+        sample_rate,
+        normalize_peak: (tracks.len() > 1).then_some(POLYPHONIC_PEAK),
+        ..Default::default()
+    };
+    render(tracks.to_vec(), tempo.speed_mult(), options)
+}
+
+// Every selected song and variation, then the manifest if nothing was left
+// out, and the accuracy table on stdout and at `outputs.summary`
+fn generate_all(songs: &[Song], selection: &Selection, outputs: &Outputs) -> io::Result<()> {
+    let music_dir = selection.under("target_music");
+    // Made up front, so the generator threads never race to create it
+    std::fs::create_dir_all(&music_dir).map_err(at_path(&music_dir))?;
+
+    let threads = std::thread::available_parallelism().map_or(1, usize::from);
+    let mut all_variations =
+        generate_songs(songs, selection, outputs.trim_dbfs, &music_dir, threads)?;
+    if outputs.ab {
+        // Once any trimming is done, so both halves are as written
+        for info in all_variations.iter_mut().filter(|info| info.ideal_filename != info.filename) {
+            let read = |name: &str| {
+                let path = format!("{}/{}", music_dir, name);
+                wav::read::<Ch32>(&path).map_err(at_path(&path))
+            };
+            let (reference, variation) = (read(&info.ideal_filename)?, read(&info.filename)?);
+            let path = format!("{}/{}", music_dir, info.filename.replace(".wav", "_ab.wav"));
+            info.ab = Some(generate_ab(&reference, &variation, selection.bit_depth(), &path)?);
+        }
+    }
+
+    let summary = summary_table(&all_variations);
+    print!("\n{}", summary);
+    if outputs.spectrograms {
+        for info in &all_variations {
+            let wav_path = format!("{}/{}", music_dir, info.filename);
+            let audio = wav::read::<Ch16>(&wav_path).map_err(at_path(&wav_path))?;
+            let path = wav_path.replace(".wav", ".png");
+            progress!("Writing {}", path);
+            spectrogram::write_png(&audio, &path, SPECTROGRAM_FFT_SIZE, SPECTROGRAM_HOP)
+                .map_err(at_path(&path))?;
+        }
+    }
+    if outputs.csv {
+        for song in songs.iter().filter(|song| selection.wants_song(&song.title)) {
+            let path = format!("{}/{}_pitch.csv", music_dir, song.title);
+            progress!("Writing {}", path);
+            let melody = song.tracks.first().map_or(&[][..], Vec::as_slice);
+            csv::write_pitch_log(melody, PITCH_LOG_INTERVAL, song.key.as_deref(), &path)
+                .map_err(at_path(&path))?;
+        }
+    }
+    if outputs.calibration {
+        let (pitch, secs) = CALIBRATION_NOTE;
+        for (name, profile) in TIMBRES {
+            let path = format!("{}/calibration_{}.wav", music_dir, name);
+            progress!("Writing {}", path);
+            let audio = render_note(pitch.to_freq(), secs, &profile(), selection.sample_rate())?;
+            wav::write(&audio, &path, selection.bit_depth()).map_err(at_path(&path))?;
+        }
+    }
+    if let Some(path) = &outputs.summary {
+        let path = &selection.under(path);
+        std::fs::write(path, &summary).map_err(at_path(path))?;
+        progress!("Wrote {}", path);
+    }
+
+    // A partial run would leave the manifest listing only what it generated
+    if !selection.is_everything() {
+        return Ok(());
+    }
+
+    // Generate JSON
+    let manifest = Manifest::new(all_variations, selection);
+    let output_file = &selection.under("available_tests.json");
+    write_json(output_file, &manifest)?;
+    progress!("Wrote {}", output_file);
+    Ok(())
+}
 
-            format!(
-                "  {{\n    \"filename\": \"{}\",\n    \"ideal_filename\": \"{}\",\n    \"expected_tempo_accuracy\": {:.2},\n    \"expected_pitch_accuracy\": {:.2},\n    \"notes\": [{}]\n  }}",
-                v.filename, v.ideal_filename, v.tempo_accuracy, v.pitch_accuracy, tracks_json
-            )
+// Generate every selected variation of every selected song into `dir`, each
+// variation a job for one of up to `threads` threads. The variations are
+// independent, so the results, put back in song and `VARIATIONS` order, are
+// the same however many threads there are and however they finish.
+fn generate_songs(
+    songs: &[Song],
+    selection: &Selection,
+    trim_dbfs: Option<f32>,
+    dir: &str,
+    threads: usize,
+) -> io::Result<Vec<VariationInfo>> {
+    let jobs: Vec<(&Song, Selection)> = songs
+        .iter()
+        .filter(|song| selection.wants_song(&song.title))
+        .flat_map(|song| {
+            let wanted = VARIATIONS.iter().filter(|&&variation| selection.wants(variation));
+            wanted.map(move |&variation| (song, selection.only(variation)))
         })
         .collect();
+    let next = AtomicUsize::new(0);
+    let (jobs, next) = (&jobs, &next);
+    let mut done: Vec<(usize, io::Result<Vec<VariationInfo>>)> = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads.clamp(1, jobs.len().max(1)))
+            .map(|_| {
+                scope.spawn(move || {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some((song, only)) = jobs.get(i) else { break done };
+                        let infos = generate_variations(song, only, only.seed(), trim_dbfs, dir);
+                        done.push((i, infos));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("Generator thread panicked"))
+            .collect()
+    });
+    done.sort_by_key(|&(i, _)| i);
+    let per_job = done.into_iter().map(|(_, infos)| infos).collect::<io::Result<Vec<_>>>()?;
+    Ok(per_job.into_iter().flatten().collect())
+}
+
+// One line per file with its expected tempo and pitch accuracy, under a
+// header, the columns padded to line up
+fn summary_table(variations: &[VariationInfo]) -> String {
+    let width = variations.iter().map(|info| info.filename.len()).fold(4, usize::max);
+    let mut table = format!("{:<width$}  {:>6}  {:>6}\n", "File", "Tempo", "Pitch");
+    for info in variations {
+        table.push_str(&format!(
+            "{:<width$}  {:>5.1}%  {:>5.1}%\n",
+            info.filename,
+            info.tempo_accuracy * 100.0,
+            info.pitch_accuracy * 100.0
+        ));
+    }
+    table
+}
+
+// Render the one selected variation of the one selected song in memory and
+// write it to `out` as a WAV, for piping into a player or converter; no file
+// is written
+fn stream(songs: &[Song], selection: &Selection, out: &mut impl Write) -> io::Result<()> {
+    let song = songs
+        .iter()
+        .find(|song| selection.wants_song(&song.title))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no song selected"))?;
+    // Some variations are skipped for songs they would not change
+    let (filename, audio) = render_variation(song, selection, selection.seed())?.ok_or_else(|| {
+        let message = format!("{}: the chosen variation does not apply", song.title);
+        io::Error::new(io::ErrorKind::InvalidInput, message)
+    })?;
+
+    let bytes = wav::encode(&audio, selection.bit_depth())?;
+    out.write_all(&bytes)?;
+    out.flush()?;
+    progress!("Streamed {} ({} bytes)", filename, bytes.len());
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut songs = Song::all();
+    let song_names: Vec<String> = songs.iter().map(|song| song.title.clone()).collect();
+    let song_names: Vec<&str> = song_names.iter().map(String::as_str).collect();
+    let command = cli::parse(&args, &song_names, VARIATIONS);
+    let imports = command.as_ref().ok().and_then(Command::selection).map(Selection::imports);
+    for path in imports.unwrap_or_default() {
+        match Song::import(path) {
+            Ok(song) => songs.push(song),
+            Err(err) => {
+                eprintln!("Error: --import {}: {}", path, err);
+                std::process::exit(1);
+            }
+        }
+    }
+    let result = match command {
+        Ok(Command::Generate(selection, outputs)) => generate_all(&songs, &selection, &outputs),
+        Ok(Command::Medley(selection)) => {
+            medley::write_medley(&songs, &selection, &selection.under("target_music"))
+        }
+        Ok(Command::Ladder(selection)) => {
+            ladder::write_ladders(&songs, &selection, &selection.under("target_music"))
+        }
+        Ok(Command::Stream(selection)) => {
+            STREAMING.store(true, Ordering::Relaxed);
+            stream(&songs, &selection, &mut io::stdout().lock())
+        }
+        Ok(Command::List) => {
+            println!("Songs:\n    {}", song_names.join("\n    "));
+            println!("Variations:\n    {}", VARIATIONS.join("\n    "));
+            return;
+        }
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-    let json_output = format!("[\n{}\n]", json_items.join(",\n"));
-    let output_file = "available_tests.json";
-    std::fs::write(output_file, json_output).expect("Failed to write JSON output");
-    println!("Wrote {}", output_file);
-}
\ No newline at end of file
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own under the system temp dir for one test, named
+    /// after the test and the process so concurrent runs never share it, and
+    /// removed with everything in it when dropped, failed assert or not.
+    pub(super) struct TempDir(std::path::PathBuf);
+
+    impl TempDir {
+        pub(super) fn new(name: &str) -> Self {
+            let name = format!("piano_synth_{}_{}", name, std::process::id());
+            let dir = std::env::temp_dir().join(name);
+            let _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        /// The directory, as the generators take it.
+        pub(super) fn dir(&self) -> &str {
+            self.0.to_str().expect("A UTF-8 temp dir")
+        }
+
+        /// `filename` inside it.
+        pub(super) fn path(&self, filename: &str) -> String {
+            format!("{}/{}", self.dir(), filename)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn song(title: &str) -> Song {
+        Song::all().into_iter().find(|song| song.title == title).expect("A built-in song")
+    }
+
+    fn tracks(song: &Song) -> Vec<&[(f32, f32)]> {
+        song.tracks.iter().map(Vec::as_slice).collect()
+    }
+
+    // Power of a plain DFT of `samples` at one frequency
+    fn power_at(samples: &[f32], hz: f32, sample_rate: u32) -> f32 {
+        let step = hz * std::f32::consts::TAU / sample_rate as f32;
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, x) in samples.iter().enumerate() {
+            re += x * (step * i as f32).cos();
+            im += x * (step * i as f32).sin();
+        }
+        re * re + im * im
+    }
+
+    // Power of the `hz` harmonic, searching up to 2% above it for a piano's
+    // stretched partial
+    fn partial_power(samples: &[f32], hz: f32, sample_rate: u32) -> f32 {
+        let hz = hz.round() as u32;
+        (hz..=hz + hz / 50)
+            .map(|hz| power_at(samples, hz as f32, sample_rate))
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn render_fills_a_buffer_for_the_analyzer() {
+        let song = song("ode_to_joy");
+        let speed_mult = REFERENCE_TEMPO.speed_mult();
+        let audio = render(tracks(&song), speed_mult, RenderOptions::default()).unwrap();
+
+        // The notes, then the piano's (empty) release and the fade-out
+        let notes_secs = total_duration(&to_notes(&tracks(&song)), speed_mult);
+        let expected = (SAMPLE_RATE as f32 * (notes_secs + FADE_OUT_SECS)).ceil() as usize;
+        assert_eq!(audio.len(), expected);
+
+        let written = Audio::<Ch16, 2>::with_audio(SAMPLE_RATE, &audio);
+        assert!(analyze::levels(&written).rms_dbfs > SILENT_RMS_DBFS);
+        let notes = song.tracks[0].iter().filter(|&&(freq, _)| freq > 0.0).count();
+        let onsets = analyze::detect_onsets(&written).len();
+        assert!(onsets.abs_diff(notes) <= notes / 10, "{} onsets for {} notes", onsets, notes);
+    }
+
+    #[test]
+    fn each_velocity_curve_pins_its_amplitudes() {
+        let curve = |name| VelocityCurve::named(name).expect("A known curve");
+        // (velocity, amplitude) for each curve; every one keeps 0 and 1 fixed
+        let expected = [
+            ("linear", [(0.0, 0.0), (0.25, 0.25), (0.5, 0.5), (1.0, 1.0)]),
+            ("exponential", [(0.0, 0.0), (0.25, 0.077_643), (0.5, 0.222_70), (1.0, 1.0)]),
+            ("square", [(0.0, 0.0), (0.25, 0.0625), (0.5, 0.25), (1.0, 1.0)]),
+        ];
+        for (name, points) in expected {
+            for (velocity, amplitude) in points {
+                let got = curve(name).amplitude(velocity);
+                assert!((got - amplitude).abs() < 1e-5, "{} at {}: {}", name, velocity, got);
+            }
+        }
+        assert!(matches!(VelocityCurve::default(), VelocityCurve::Exponential));
+        assert_eq!(VELOCITY_CURVES[0].0, "exponential");
+        assert!(VelocityCurve::named("cubic").is_none());
+    }
+
+    #[test]
+    fn the_exponential_curve_halves_amplitude_faster_than_velocity() {
+        let curve = VelocityCurve::Exponential;
+        assert!(curve.amplitude(0.5) < curve.amplitude(1.0) / 2.0);
+        // About 13 dB down, as `VELOCITY_CURVE_STEEPNESS` says
+        let db = 20.0 * (curve.amplitude(0.5) / curve.amplitude(1.0)).log10();
+        assert!((db + 13.0).abs() < 0.5, "{} dB", db);
+        // flat_dynamics' 0.6 is about half as loud as it was under linear
+        assert!((curve.amplitude(0.6) - 0.311_35).abs() < 1e-5, "{}", curve.amplitude(0.6));
+    }
+
+    #[test]
+    fn half_the_sample_rate_renders_half_the_frames() {
+        let song = song("twinkle");
+        let at = |sample_rate| {
+            let options = RenderOptions { sample_rate, ..Default::default() };
+            render(tracks(&song), REFERENCE_TEMPO.speed_mult(), options).unwrap()
+        };
+        let (full, half) = (at(48_000), at(24_000));
+        assert_eq!(half.sample_rate().get(), 24_000);
+        assert_eq!(half.len() * 2, full.len());
+    }
+
+    #[test]
+    fn doubling_the_tempo_halves_the_duration() {
+        let song = song("fur_elise");
+        let (tempo, doubled) = (Tempo { bpm: 90.0 }, Tempo { bpm: 180.0 });
+        let notes = to_notes(&tracks(&song));
+        let secs = |tempo: Tempo| total_duration(&notes, tempo.speed_mult());
+        assert!((secs(tempo) - 2.0 * secs(doubled)).abs() < 1e-4);
+        assert_eq!(
+            Beat::Quarter.duration_secs(&tempo),
+            2.0 * Beat::Quarter.duration_secs(&doubled)
+        );
+
+        // The last note ends on half the sample, give or take its rounding
+        let end = |tempo: Tempo| get_onsets(&tracks(&song), tempo, 8000)[0].last().unwrap().1;
+        assert!(end(tempo).abs_diff(2 * end(doubled)) <= 1, "{} {}", end(tempo), end(doubled));
+    }
+
+    #[test]
+    fn imported_songs_are_named_after_their_files() {
+        let dir = TempDir::new("import");
+        let text = &dir.path("import_test_melody.txt");
+        std::fs::write(text, "tempo 120\nA4 4\nrest 4\nC5 2\n").unwrap();
+        let song = Song::import(text).unwrap();
+        assert_eq!(song.title, "import_test_melody");
+        assert_eq!(song.key, None);
+        assert_eq!(song.tracks, [vec![(440.0, 0.5), (0.0, 0.5), (song.tracks[0][2].0, 1.0)]]);
+
+        // A shorter part rests until the melody ends
+        let midi = &dir.path("import_test_parts.mid");
+        midi::write(&[&[(440.0, 0.5), (440.0, 0.5)], &[(220.0, 0.5)]], 120.0, midi).unwrap();
+        let song = Song::import(midi).unwrap();
+        let lengths: Vec<f32> =
+            song.tracks.iter().map(|track| track.iter().map(|&(_, dur)| dur).sum()).collect();
+        assert_eq!(lengths, [1.0, 1.0]);
+
+        std::fs::write(text, "rest 4\n").unwrap();
+        let Err(err) = Song::import(text) else {
+            panic!("imported a song without notes");
+        };
+        assert!(err.to_string().contains("no notes to play"), "{}", err);
+    }
+
+    #[test]
+    fn threads_do_not_change_the_output() {
+        let songs = Song::all();
+        let selection = selection("--song twinkle --sample-rate 8000");
+
+        let run = |threads: usize| {
+            let dir = TempDir::new(&format!("threads_{}", threads));
+            let infos = generate_songs(&songs, &selection, None, dir.dir(), threads);
+            let mut files: Vec<(String, Vec<u8>)> = std::fs::read_dir(dir.dir())
+                .unwrap()
+                .map(|entry| {
+                    let path = entry.unwrap().path();
+                    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                    (name, std::fs::read(&path).unwrap())
+                })
+                .collect();
+            files.sort();
+            (serde_json::to_string(&infos.unwrap()).unwrap(), files)
+        };
+        let (sequential, parallel) = (run(1), run(4));
+        assert!(sequential.1.len() > VARIATIONS.len() / 2, "{} files", sequential.1.len());
+        assert!(sequential.0 == parallel.0, "the manifest entries differ");
+        for ((name, bytes), (other, other_bytes)) in sequential.1.iter().zip(&parallel.1) {
+            assert_eq!(name, other);
+            assert!(bytes == other_bytes, "{} differs", name);
+        }
+        assert_eq!(sequential.1.len(), parallel.1.len());
+    }
+
+    #[test]
+    fn the_manifest_entries_read_back_into_their_structs() {
+        let selection = selection("--song twinkle --sample-rate 8000");
+        let dir = TempDir::new("read_back");
+        let infos = generate_songs(&Song::all(), &selection, Some(-60.0), dir.dir(), 1);
+
+        let json = serde_json::to_string_pretty(&infos.unwrap()).unwrap();
+        let read: Vec<VariationInfo> = serde_json::from_str(&json).unwrap();
+        assert!(read.iter().any(|info| info.pause.is_some()), "no pause to read back");
+        assert!(read.iter().any(|info| !info.duration_errors.is_empty()));
+        assert!(read.iter().all(|info| info.levels.is_some() && info.trimmed_samples.is_some()));
+        assert!(serde_json::to_string_pretty(&read).unwrap() == json, "the entries changed");
+    }
+
+    #[test]
+    fn json_is_written_into_a_nested_directory() {
+        let root = TempDir::new("nested_json");
+        let dir = root.path("a/b/c");
+        let path = &format!("{}/entries.json", dir);
+        let err = write_json(path, &[1, 2, 3]).unwrap_err();
+        assert!(err.to_string().starts_with(path), "{}", err);
+
+        std::fs::create_dir_all(&dir).unwrap();
+        write_json(path, &[1, 2, 3]).unwrap();
+        let read: Vec<u32> = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(read, [1, 2, 3]);
+    }
+
+    #[test]
+    fn a_config_writes_exactly_its_files_under_its_directory() {
+        let dir = TempDir::new("config_run");
+        let plan = dir.path("plan.json");
+        let out = std::path::PathBuf::from(dir.path("out"));
+        let json = serde_json::json!({
+            "songs": ["twinkle", "ode_to_joy"],
+            "variations": ["fast"],
+            "sample_rate": 8000,
+            "output_dir": out,
+        });
+        std::fs::write(&plan, json.to_string()).unwrap();
+        let selection = selection(&format!("--config {}", plan));
+        generate_all(&Song::all(), &selection, &Outputs::default()).unwrap();
+
+        let mut written: Vec<String> = std::fs::read_dir(out.join("target_music"))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        let outside = std::fs::read_dir(&out).unwrap().count();
+        written.sort();
+        assert_eq!(written, ["ode_to_joy_fast.wav", "twinkle_fast.wav"]);
+        assert_eq!(outside, 1, "only target_music belongs in the output directory");
+    }
+
+    #[test]
+    fn a_stream_is_one_wav_and_writes_no_files() {
+        let scratch = TempDir::new("stream");
+        let dir = std::path::PathBuf::from(scratch.path("out"));
+        let plan = scratch.path("plan.json");
+        std::fs::write(&plan, serde_json::json!({ "output_dir": dir }).to_string()).unwrap();
+        let songs = Song::all();
+        let titles: Vec<&str> = songs.iter().map(|song| song.title.as_str()).collect();
+        let args = format!(
+            "--stdout --song twinkle --variation swing --sample-rate 8000 --config {}",
+            plan
+        );
+        let args: Vec<String> = args.split(' ').map(String::from).collect();
+        let Ok(Command::Stream(selection)) = cli::parse(&args, &titles, VARIATIONS) else {
+            panic!("expected a stream");
+        };
+        let mut out = Vec::new();
+        stream(&songs, &selection, &mut out).unwrap();
+
+        assert!(!dir.exists(), "a stream wrote to disk");
+        let twinkle = songs.iter().find(|song| song.title == "twinkle").unwrap();
+        let (_, audio) = render_variation(twinkle, &selection, 0).unwrap().unwrap();
+        // A 44-byte header, then two 16-bit channels a frame
+        assert_eq!(out.len(), 44 + audio.len() * 4);
+        assert_eq!(&out[..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(out[40..44].try_into().unwrap()) as usize, out.len() - 44);
+    }
+
+    // A normal run over `args`, which must parse
+    fn selection(args: &str) -> Selection {
+        let songs = Song::all();
+        let titles: Vec<&str> = songs.iter().map(|song| song.title.as_str()).collect();
+        let args: Vec<String> = args.split(' ').map(String::from).collect();
+        let Ok(Command::Generate(selection, _)) = cli::parse(&args, &titles, VARIATIONS) else {
+            panic!("expected a normal run");
+        };
+        selection
+    }
+
+    #[test]
+    fn wrong_notes_measure_below_the_original() {
+        let args = "--song twinkle --variation original --variation wrong_notes";
+        let selection = selection(&format!("{} --sample-rate 24000", args));
+        let dir = TempDir::new("measured");
+        let infos = generate_songs(&Song::all(), &selection, None, dir.dir(), 1);
+
+        let infos = infos.unwrap();
+        let [original, wrong] = &infos[..] else { panic!("{} files", infos.len()) };
+        assert_eq!(original.measured_pitch_accuracy, 1.0);
+        assert!(original.measured_tempo_accuracy > 0.95, "{}", original.measured_tempo_accuracy);
+        assert!(wrong.measured_pitch_accuracy < 1.0);
+        let error = (wrong.measured_pitch_accuracy - wrong.pitch_accuracy).abs();
+        assert!(error < 0.05, "measured {}", wrong.measured_pitch_accuracy);
+    }
+
+    #[test]
+    fn clicks_are_found_at_their_times() {
+        // Eight beats at 120 bpm: one every half second from the start
+        let clicks = metronome::generate_click(120.0, 8, SAMPLE_RATE);
+        let detected = analyze::detect_onsets(&clicks);
+        let expected: Vec<f32> = (0..8).map(|beat| beat as f32 * 0.5).collect();
+        let accuracy = score::tempo_accuracy(&detected, &expected);
+        assert!(accuracy > 0.95, "{} for onsets at {:?}", accuracy, detected);
+        // Expecting them 50 ms late costs a tenth of the half-second interval
+        let late: Vec<f32> = expected.iter().map(|secs| secs + 0.05).collect();
+        let late_accuracy = score::tempo_accuracy(&detected, &late);
+        assert!((accuracy - late_accuracy - 0.1).abs() < 0.03, "{}", late_accuracy);
+    }
+
+    #[test]
+    fn trimming_cuts_the_copies_to_the_mix() {
+        let songs = Song::all();
+        let args = "--song fur_elise_harmony --variation original --sample-rate 8000";
+        let selection = selection(args);
+        let scratch = TempDir::new("trim");
+        let dir = scratch.dir();
+        let infos = generate_songs(&songs, &selection, Some(-60.0), dir, 1).unwrap();
+
+        let info = &infos[0];
+        let (lead, _) = info.trimmed_samples.expect("A trimmed original");
+        assert!(lead > 0, "the harmony's opening rest was left in");
+        let frames = |name: &str| wav::read::<Ch16>(&format!("{}/{}", dir, name)).unwrap().len();
+        let len = frames(&info.filename);
+        assert_eq!(info.copies.len(), 2);
+        for copy in info.stems.iter().chain(&info.copies) {
+            assert_eq!(frames(copy), len, "{}", copy);
+        }
+        let mono = info.mono_copy.as_ref().unwrap();
+        assert_eq!(wav::read_mono::<Ch16>(&format!("{}/{}", dir, mono)).unwrap().len(), len);
+    }
+
+    #[test]
+    fn a_calibration_note_peaks_at_its_pitch_in_every_timbre() {
+        let (pitch, secs) = CALIBRATION_NOTE;
+        let freq = pitch.to_freq();
+        for (name, profile) in TIMBRES {
+            let audio = render_note(freq, secs, &profile(), 8000).unwrap();
+            assert_eq!(audio.len(), ((secs + FADE_OUT_SECS) * 8000.0).ceil() as usize, "{}", name);
+            // The strongest component of a plain DFT, to the nearest hertz
+            let samples = analyze::to_mono(&audio);
+            let peak = (50..2000).map(|hz| hz as f32).max_by(|a, b| {
+                power_at(&samples, *a, 8000).total_cmp(&power_at(&samples, *b, 8000))
+            });
+            assert_eq!(peak, Some(freq), "{}", name);
+        }
+    }
+
+    #[test]
+    fn three_triplets_fill_two_of_their_notes() {
+        assert!((3.0 * TRIPLET_E - Q).abs() < 1e-6);
+        assert!((3.0 * triplet(E) - Q).abs() < 1e-6);
+        assert!((3.0 * triplet(Q) - H).abs() < 1e-6);
+    }
+
+    #[test]
+    fn a_flat_envelope_holds_its_level_like_an_organ() {
+        let organ = Envelope {
+            attack: 0.01,
+            decay: 0.0,
+            decay_per_octave: 1.0,
+            sustain: 1.0,
+            release: 0.0,
+        };
+        for freq in [C4.to_freq(), A4.to_freq(), 1760.0] {
+            let envelope = organ.for_pitch(freq);
+            for elapsed in [0.01, 0.1, 0.5, 1.0, 2.0] {
+                assert_eq!(envelope.level(elapsed), 1.0, "{} Hz at {} s", freq, elapsed);
+            }
+        }
+
+        // Played, the note keeps the same loudness from one window to the next
+        let note = vec![Note::from((A4, 1.0))];
+        let mut voice = Voice::new(note, 1.0, 8000, organ, HarmonicProfile::organ(), 0.0);
+        let samples: Vec<f32> = (0..8000).map(|_| voice.step(false, 1.0)).collect();
+        let rms = |window: &[f32]| (window.iter().map(|x| x * x).sum::<f32>() / 400.0).sqrt();
+        let levels: Vec<f32> = samples[400..].chunks(400).map(rms).collect();
+        let quiet = levels.iter().copied().fold(f32::MAX, f32::min);
+        let loud = levels.iter().copied().fold(0.0, f32::max);
+        assert!(quiet > 0.0 && loud / quiet < 1.01, "{:?}", levels);
+    }
+
+    #[test]
+    fn a_voice_panned_full_left_is_silent_on_the_right() {
+        let song = vec![Note::from((A4, 0.5)), Note::from((C5, 0.5))];
+        let mut proc = Processors::new(vec![song], &[], 1.0, 8000);
+        proc.voices[0].pan = -1.0;
+        let frames: Vec<Frame<Ch32, 2>> = (0..8000).map(|_| proc.step()).collect();
+        let (left, right): (Vec<f32>, Vec<f32>) = frames
+            .iter()
+            .map(|frame| (f32::from(frame.channels()[0]), f32::from(frame.channels()[1])))
+            .unzip();
+        assert!(right.iter().all(|&sample| sample == 0.0));
+        assert!(left.iter().any(|&sample| sample.abs() > 0.01));
+    }
+
+    #[test]
+    fn the_cursor_finds_the_note_a_full_scan_does() {
+        // The lookup `Voice` replaced: rescan the song from the start for
+        // the slot holding `sample`
+        let scan = |song: &[Note], speed_mult: f32, sample: usize| {
+            let rate = 8000.0;
+            let mut time_cursor = 0.0f32;
+            for (i, note) in song.iter().enumerate() {
+                let dur = note.duration * speed_mult;
+                let start_sample = (time_cursor * rate).round() as usize;
+                let end_sample = ((time_cursor + dur) * rate).round() as usize;
+                if sample >= start_sample && sample < end_sample {
+                    return Some(i);
+                }
+                time_cursor += dur;
+            }
+            None
+        };
+        // Every song's melody, then notes too short to get a sample of their own
+        let melodies = Song::all().into_iter().map(|mut song| song.tracks.swap_remove(0));
+        let mut song: Vec<Note> = melodies.flatten().map(Note::from).collect();
+        song.extend((0..50).map(|i| Note::from((A4, if i % 3 == 0 { 1e-5 } else { 3e-4 }))));
+        for speed_mult in [0.5, 1.0, 1.37] {
+            let envelope = Envelope::piano_default();
+            let mut voice =
+                Voice::new(song.clone(), speed_mult, 8000, envelope, HarmonicProfile::piano(), 0.0);
+            let end = voice.bounds.last().unwrap().1;
+            for sample in 0..end + 10 {
+                voice.step(false, 1.0);
+                let slot = voice.bounds.get(voice.cursor).filter(|&&(start, ..)| start <= sample);
+                let found = slot.map(|_| voice.cursor);
+                assert_eq!(found, scan(&song, speed_mult, sample), "sample {}", sample);
+            }
+        }
+    }
+
+    #[test]
+    fn a_step_costs_the_same_however_long_the_song() {
+        // Cursor advances over the first `samples` steps, and the most in one
+        let advances = |notes: usize, samples: usize| {
+            let song = vec![Note::from((A4, 0.01)); notes];
+            let envelope = Envelope::piano_default();
+            let mut voice = Voice::new(song, 1.0, 8000, envelope, HarmonicProfile::piano(), 0.0);
+            let (mut total, mut most) = (0, 0);
+            for _ in 0..samples {
+                let before = voice.cursor;
+                voice.step(false, 1.0);
+                total += voice.cursor - before;
+                most = most.max(voice.cursor - before);
+            }
+            (total, most)
+        };
+        // 80 samples a note, so ten notes fill 800 samples
+        let short = advances(10, 800);
+        assert_eq!(short, advances(10_000, 800));
+        assert!(short.0 <= 10 && short.1 <= 1, "{:?}", short);
+        // Deep into the long song a step still moves past at most one note
+        let (total, most) = advances(10_000, 80 * 9_000);
+        assert!(total <= 9_000 && most <= 1, "{} {}", total, most);
+    }
+
+    #[test]
+    fn lifting_the_pedal_restores_the_normal_decay() {
+        let rate = 8000;
+        // How far the second note falls over its second, from near its onset
+        let fall = |pedal: &[(bool, f32)]| {
+            let song = vec![Note::from((A4, 1.0)), Note::from((A4.to_freq() * 1.5, 1.0))];
+            let mut proc = Processors::with_sustain(vec![song], &[], pedal, 1.0, rate);
+            let samples: Vec<f32> =
+                (0..2 * rate).map(|_| f32::from(proc.step().channels()[0])).collect();
+            let rms = |from: f32, to: f32| {
+                let window = &samples[(from * rate as f32) as usize..(to * rate as f32) as usize];
+                (window.iter().map(|s| s * s).sum::<f32>() / window.len() as f32).sqrt()
+            };
+            rms(1.75, 1.95) / rms(1.05, 1.25)
+        };
+        let (unpedalled, held) = (fall(&[]), fall(&[(true, 2.0)]));
+        let lifted = fall(&[(true, 1.0), (false, 1.0)]);
+        assert!(held > unpedalled * 1.5, "held {} against {}", held, unpedalled);
+        assert!((lifted - unpedalled).abs() < 1e-3, "lifted {} against {}", lifted, unpedalled);
+    }
+
+    #[test]
+    fn every_song_and_variation_renders_what_the_manifest_says() {
+        let songs = Song::all();
+        let scratch = TempDir::new("every");
+        let dir = scratch.dir();
+        let infos = generate_songs(&songs, &selection("--sample-rate 8000"), None, dir, 1).unwrap();
+        for song in &songs {
+            assert!(infos.iter().any(|info| info.title == song.title), "{}", song.title);
+        }
+
+        for info in &infos {
+            let written = wav::read::<Ch16>(&format!("{}/{}", dir, info.filename)).unwrap();
+            let file = &info.filename;
+            // Every note, then no more than the release, a reverb tail and the fade
+            let notes_end = info.onsets_samples.iter().filter_map(|t| t.last()).map(|n| n.1);
+            let notes_end = notes_end.max().unwrap();
+            let timeline_end = info.timeline.last().map_or(0.0, |&(_, end, _)| end);
+            assert!(written.len() >= notes_end, "{} stops before its notes", file);
+            assert!(written.len() as f32 >= (timeline_end * 8000.0).floor(), "{}", file);
+            assert!(written.len() <= notes_end + 3 * 8000, "{} runs on", file);
+
+            let levels = analyze::levels(&written);
+            let recorded = info.levels.unwrap();
+            assert_eq!(recorded.peak_dbfs, levels.peak_dbfs, "{}", file);
+            assert_eq!(recorded.rms_dbfs, levels.rms_dbfs, "{}", file);
+            assert!(levels.peak_dbfs <= 0.0 && levels.peak_dbfs > -30.0, "{}", file);
+            assert!(levels.rms_dbfs > SILENT_RMS_DBFS, "{} is silent", file);
+        }
+
+        // Missing notes must not take the rest of the song with them
+        let missed = infos.iter().filter(|info| info.filename.contains("_missed_"));
+        for info in missed {
+            let played = info.notes.iter().flatten().filter(|note| note.freq > 0.0).count();
+            let written = wav::read::<Ch16>(&format!("{}/{}", dir, info.filename)).unwrap();
+            assert!(played > 0, "{} has no notes left", info.filename);
+            assert!(analyze::levels(&written).rms_dbfs > SILENT_RMS_DBFS, "{}", info.filename);
+        }
+    }
+
+    #[test]
+    fn an_organ_puts_more_of_its_energy_in_odd_harmonics_than_a_piano() {
+        // Odd over even harmonic power
+        let odd_to_even = |profile: &HarmonicProfile| {
+            let samples = analyze::to_mono(&render_note(A3.to_freq(), 1.0, profile, 8000).unwrap());
+            let partial = |n: f32| partial_power(&samples, A3.to_freq() * n, 8000);
+            let odd: f32 = [3.0, 5.0, 7.0].into_iter().map(partial).sum();
+            let even: f32 = [2.0, 4.0, 6.0].into_iter().map(partial).sum();
+            odd / even
+        };
+        let organ = odd_to_even(&HarmonicProfile::organ());
+        let piano = odd_to_even(&HarmonicProfile::piano());
+        assert!(organ > 5.0 * piano, "organ {} piano {}", organ, piano);
+    }
+
+    #[test]
+    fn staccato_leaves_the_end_of_each_slot_silent() {
+        let staccato = |freq| Note {
+            articulation: Articulation::Staccato,
+            ..Note::from((freq, 0.5))
+        };
+        let song = vec![staccato(A4), staccato(C5), staccato(E5)];
+        let mut proc = Processors::new(vec![song], &[], 1.0, 8000);
+        let samples: Vec<f32> =
+            (0..12_000).map(|_| f32::from(proc.step().channels()[0])).collect();
+        for slot in samples.chunks(4000) {
+            // Sounding for 40% of the slot, then let go
+            assert!(slot[..1600].iter().any(|sample| sample.abs() > 0.01));
+            assert!(slot[2400..].iter().all(|sample| sample.abs() < 1e-4));
+        }
+    }
+
+    #[test]
+    fn a_glide_is_heard_between_its_two_pitches() {
+        let song = [Note::from((A4, 0.5)), Note { glide: 0.4, ..Note::from((E5, 1.0)) }];
+        let options = RenderOptions { sample_rate: 8000, ..Default::default() };
+        let audio = render(vec![&song[..]], 1.0, options).unwrap();
+        let audio = Audio::<Ch16, 2>::with_audio(8000, &audio);
+        let pitches = analyze::detect_pitches(&audio, 256);
+        // Settled before the glide and after it, and in between halfway through
+        let at = |secs: f32| pitches[(secs * 8000.0 / 256.0) as usize];
+        let (a4, e5) = (A4.to_freq(), E5.to_freq());
+        assert!((at(0.3) - a4).abs() < 5.0, "{}", at(0.3));
+        assert!((at(1.2) - e5).abs() < 5.0, "{}", at(1.2));
+        let midway = at(0.7);
+        assert!(midway > a4 * 1.03 && midway < e5 / 1.03, "{}", midway);
+    }
+
+    #[test]
+    fn note_timings_are_contiguous_and_where_the_voice_sounds() {
+        let rest = Pitch::Rest;
+        let song: Vec<Note> = [(A4, 0.13), (rest, 0.07), (C5, 0.111), (rest, 0.093), (E5, 0.2)]
+            .into_iter()
+            .map(Note::from)
+            .collect();
+        let (speed_mult, rate) = (1.3, 8000);
+        let slots = note_timings(&song, speed_mult, rate);
+        assert_eq!(slots[0].0, 0);
+        for pair in slots.windows(2) {
+            assert_eq!(pair[1].0, pair[0].1);
+        }
+        let secs: f32 = song.iter().map(|note| note.duration * speed_mult).sum();
+        assert_eq!(slots.last().unwrap().1, (secs * rate as f32).round() as usize);
+
+        // The voice is silent through each rest and sounds from the sample
+        // after each note's start
+        let mut voice = Voice::new(
+            song,
+            speed_mult,
+            rate,
+            Envelope::piano_default(),
+            HarmonicProfile::piano(),
+            0.0,
+        );
+        let samples: Vec<f32> =
+            (0..slots.last().unwrap().1).map(|_| voice.step(false, 1.0)).collect();
+        for &(start, end, freq) in &slots {
+            if freq > 0.0 {
+                assert!(samples[start + 1] != 0.0, "{}", start);
+            } else {
+                assert!(samples[start..end].iter().all(|&sample| sample == 0.0), "{}", start);
+            }
+        }
+    }
+
+    #[test]
+    fn a_triad_sounds_all_three_of_its_pitches() {
+        let triad = [Note { chord: vec![E4, G4], ..Note::from((C4, 1.0)) }];
+        let options = RenderOptions { sample_rate: 8000, ..Default::default() };
+        let audio = render(vec![&triad[..]], 1.0, options).unwrap();
+        let samples = analyze::to_mono(&Audio::<Ch16, 2>::with_audio(8000, &audio));
+        // Each chord tone stands well above the notes around it
+        let power = |pitch: Pitch| power_at(&samples, pitch.to_freq(), 8000);
+        let quietest = [C4, E4, G4].map(power).into_iter().fold(f32::MAX, f32::min);
+        let loudest_other = [D4, F4, A4].map(power).into_iter().fold(0.0, f32::max);
+        assert!(quietest > 10.0 * loudest_other, "{} {}", quietest, loudest_other);
+    }
+
+    #[test]
+    fn a_stiff_string_sharpens_its_tenth_partial() {
+        // The strongest frequency between the (stretched) 9th and 11th partials
+        let tenth = |profile: &HarmonicProfile| {
+            let samples = analyze::to_mono(&render_note(A2.to_freq(), 1.0, profile, 8000).unwrap());
+            (1050..1200).map(|hz| hz as f32).max_by(|a, b| {
+                power_at(&samples, *a, 8000).total_cmp(&power_at(&samples, *b, 8000))
+            })
+        };
+        let stiff = HarmonicProfile::piano();
+        assert!(stiff.inharmonicity > 0.0);
+        let ideal = HarmonicProfile { inharmonicity: 0.0, ..stiff.clone() };
+        assert_eq!(tenth(&ideal), Some(10.0 * A2.to_freq()));
+        // 10 * sqrt(1 + 100 B) times the fundamental, about 22 Hz sharp
+        let expected = 10.0 * A2.to_freq() * (1.0 + 100.0 * stiff.inharmonicity).sqrt();
+        let heard = tenth(&stiff).unwrap();
+        assert!((heard - expected).abs() <= 2.0, "{} Hz, not {}", heard, expected);
+    }
+
+    #[test]
+    fn a_reused_buffer_renders_what_a_fresh_one_does() {
+        let song = song("ode_to_joy");
+        let original = tracks(&song);
+        // Same rhythm, so the same length, but every note a tone higher
+        let tone = 2f32.powf(2.0 / 12.0);
+        let higher: Vec<Vec<(f32, f32)>> = original
+            .iter()
+            .map(|track| track.iter().map(|&(freq, dur)| (freq * tone, dur)).collect())
+            .collect();
+        let higher: Vec<&[(f32, f32)]> = higher.iter().map(Vec::as_slice).collect();
+        let options = RenderOptions { sample_rate: 8000, ..Default::default() };
+
+        let fresh = render(higher.clone(), 1.0, options).unwrap();
+        let mut reused = render(original, 1.0, options).unwrap();
+        assert_eq!(reused.len(), fresh.len());
+        assert!(reused.iter().zip(fresh.iter()).any(|(a, b)| a != b));
+        let notes = to_notes(&higher);
+        let total_duration = total_duration(&notes, 1.0);
+        let proc = Processors::with_sustain(notes, &[], options.sustain, 1.0, 8000);
+        render_into(&mut reused, proc, total_duration, options);
+        assert!(reused.iter().zip(fresh.iter()).all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn generating_into_a_used_buffer_writes_what_generate_does() {
+        let song = song("ode_to_joy");
+        let original = tracks(&song);
+        let higher: Vec<Vec<(f32, f32)>> = original
+            .iter()
+            .map(|track| track.iter().map(|&(freq, dur)| (freq * 1.5, dur)).collect())
+            .collect();
+        let higher: Vec<&[(f32, f32)]> = higher.iter().map(Vec::as_slice).collect();
+        let dir = TempDir::new("generate_into");
+        let options = RenderOptions { sample_rate: 8000, out_dir: dir.dir(), ..Default::default() };
+
+        generate("fresh.wav", higher.clone(), REFERENCE_TEMPO, options).unwrap();
+        let mut buffer = render(original, 1.0, options).unwrap();
+        let held = buffer.len();
+        generate_into(&mut buffer, "reused.wav", higher, REFERENCE_TEMPO, options).unwrap();
+        assert_eq!(buffer.len(), held);
+        let read = |filename: &str| std::fs::read(dir.path(filename)).unwrap();
+        assert_eq!(read("reused.wav"), read("fresh.wav"));
+    }
+
+    #[test]
+    fn a_rolled_chord_strikes_its_pitches_in_order() {
+        for downward in [false, true] {
+            let roll = Roll { spread: 0.3, downward };
+            let chord = [Note { roll, chord: vec![C4, G4], ..Note::from((E4, 1.0)) }];
+            let options = RenderOptions { sample_rate: 8000, ..Default::default() };
+            let audio = render(vec![&chord[..]], 1.0, options).unwrap();
+            let samples = analyze::to_mono(&Audio::<Ch16, 2>::with_audio(8000, &audio));
+            // First 40 ms window where each pitch reaches a tenth of its peak
+            let onset = |pitch: Pitch| {
+                let powers: Vec<f32> = samples
+                    .chunks(320)
+                    .map(|window| power_at(window, pitch.to_freq(), 8000))
+                    .collect();
+                let peak = powers.iter().copied().fold(0.0, f32::max);
+                powers.iter().position(|&power| power > 0.1 * peak).unwrap()
+            };
+            let mut onsets = [C4, E4, G4].map(onset);
+            if downward {
+                onsets.reverse();
+            }
+            assert!(onsets.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", onsets);
+        }
+    }
+
+    #[test]
+    fn every_pitch_in_every_song_has_a_name() {
+        for song in Song::all() {
+            for &(freq, _) in song.tracks.iter().flatten() {
+                let name = freq_to_name(freq, NAME_TOLERANCE_CENTS);
+                if freq <= 0.0 {
+                    assert_eq!(name, "Rest");
+                } else {
+                    // Not printed raw, as a frequency off every semitone is
+                    let named = name.starts_with(|letter| ('A'..='G').contains(&letter));
+                    assert!(named, "{}: {} Hz is {}", song.title, freq, name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn vibrato_swings_the_heard_pitch_by_its_depth() {
+        // Cents from A4 heard in each 1024-sample window of a held A4
+        let heard = |vibrato: Vibrato| {
+            let note = [Note::from((A4, 1.0))];
+            let options = RenderOptions { vibrato, ..Default::default() };
+            let audio = render(vec![&note[..]], 1.0, options).unwrap();
+            let audio = Audio::<Ch16, 2>::with_audio(SAMPLE_RATE, &audio);
+            let pitches = analyze::detect_pitches(&audio, 1024);
+            let cents = |&hz: &f32| 1200.0 * (hz / A4.to_freq()).log2();
+            pitches[..40].iter().map(cents).collect::<Vec<f32>>()
+        };
+        // The stretched partials pull the detector a little sharp even
+        // without vibrato, so the swing is measured around that
+        let steady = heard(Vibrato::default());
+        let centre = steady.iter().sum::<f32>() / steady.len() as f32;
+        let cents = heard(Vibrato { rate: 4.0, depth_cents: 50.0 });
+        let highest = cents.iter().copied().fold(f32::MIN, f32::max) - centre;
+        let lowest = cents.iter().copied().fold(f32::MAX, f32::min) - centre;
+        assert!((40.0..60.0).contains(&highest), "{}", highest);
+        assert!((-60.0..-40.0).contains(&lowest), "{}", lowest);
+    }
+
+    #[test]
+    fn tremolo_wobbles_the_level_at_its_rate() {
+        let note = [Note::from((A4, 2.0))];
+        let rendered = |tremolo: Tremolo| {
+            let options = RenderOptions { sample_rate: 8000, tremolo, ..Default::default() };
+            render(vec![&note[..]], 1.0, options).unwrap()
+        };
+        let plain = rendered(Tremolo::default());
+        assert!(rendered(Tremolo { rate: 6.0, depth: 0.0 }).iter().eq(plain.iter()));
+
+        // RMS of each 10 ms of the left channel
+        let envelope = |audio: &Audio<Ch32, 2>| {
+            let samples: Vec<f32> = audio.iter().map(|f| f32::from(f.channels()[0])).collect();
+            samples[..16_000]
+                .chunks(80)
+                .map(|window| (window.iter().map(|x| x * x).sum::<f32>() / 80.0).sqrt())
+                .collect::<Vec<f32>>()
+        };
+        // Over the plain note's, so the piano's decay drops out
+        let wobble: Vec<f32> = envelope(&rendered(Tremolo { rate: 6.0, depth: 0.5 }))
+            .iter()
+            .zip(envelope(&plain))
+            .map(|(shaped, plain)| shaped / plain)
+            .collect();
+        let mean = wobble.iter().sum::<f32>() / wobble.len() as f32;
+        let crossings = wobble.windows(2).filter(|w| (w[0] - mean) * (w[1] - mean) < 0.0).count();
+        // Two crossings a wobble, for two seconds
+        let rate = crossings as f32 / 2.0 / 2.0;
+        assert!((rate - 6.0).abs() <= 0.5, "{} Hz", rate);
+    }
+
+    #[test]
+    fn partials_above_nyquist_do_not_fold_back() {
+        // At 8 kHz, the 5th to 10th partials of A5 are all past the 4 kHz limit
+        let (a5, profile) = (2.0 * A4.to_freq(), HarmonicProfile::piano());
+        let samples = analyze::to_mono(&render_note(a5, 1.0, &profile, 8000).unwrap());
+        // Hann-windowed, so the partials leak too little to hide an alias
+        // a few hertz from one of them
+        let len = samples.len() as f32;
+        let samples: Vec<f32> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, x)| x * (std::f32::consts::PI * i as f32 / len).sin().powi(2))
+            .collect();
+        let fundamental = power_at(&samples, a5, 8000);
+        for ratio in profile.partial_ratios().into_iter().filter(|ratio| a5 * ratio > 4000.0) {
+            // Where the partial would land had it been sampled anyway
+            let alias = (8000.0 - a5 * ratio).abs();
+            let power = power_at(&samples, alias, 8000);
+            assert!(power < 1e-4 * fundamental, "{} Hz: {} of {}", alias, power, fundamental);
+        }
+    }
+
+    #[test]
+    fn the_buffer_ends_soon_after_the_last_release_dies_away() {
+        for release in [0.0, 0.3, 1.2] {
+            let envelope = Envelope { sustain: 0.5, release, ..Envelope::piano_default() };
+            let song = vec![Note::from((A4, 0.5)), Note::from((C5, 0.5))];
+            let mut proc = Processors::new(vec![song], &[], 1.0, 8000);
+            proc.voices[0].envelope = envelope;
+            let mut audio = Audio::with_silence(8000, 0);
+            let options = RenderOptions { sample_rate: 8000, ..Default::default() };
+            render_into(&mut audio, proc, 1.0, options);
+
+            // Last sample within 60 dB of the peak, and the silence after it
+            let samples: Vec<f32> = audio.iter().map(|f| f32::from(f.channels()[0])).collect();
+            let peak = samples.iter().fold(0.0, |peak: f32, x| peak.max(x.abs()));
+            let last = samples.iter().rposition(|x| x.abs() > 1e-3 * peak).unwrap();
+            let after = (samples.len() - 1 - last) as f32 / 8000.0;
+            // Nothing cut off by the fade-out, which falls on silence, and
+            // no more than a few ms besides
+            let room = FADE_OUT_SECS..=FADE_OUT_SECS + 0.005;
+            assert!(room.contains(&after), "{} s release: {} s after the sound", release, after);
+        }
+    }
+
+    #[test]
+    fn a_harder_strike_is_brighter_as_well_as_louder() {
+        // Power in the 4th to 8th partials over that in the first two
+        let brightness = |velocity: f32| {
+            let note = [Note { velocity, ..Note::from((A3, 1.0)) }];
+            let options = RenderOptions { sample_rate: 8000, ..Default::default() };
+            let audio = render(vec![&note[..]], 1.0, options).unwrap();
+            let samples = analyze::to_mono(&Audio::<Ch16, 2>::with_audio(8000, &audio));
+            let partial = |n: f32| partial_power(&samples, A3.to_freq() * n, 8000);
+            let high: f32 = (4..=8).map(|n| partial(n as f32)).sum();
+            let low: f32 = (1..=2).map(|n| partial(n as f32)).sum();
+            high / low
+        };
+        let (hard, soft) = (brightness(1.0), brightness(0.3));
+        assert!(hard > 2.0 * soft, "{} at full velocity, {} at 0.3", hard, soft);
+    }
+
+    #[test]
+    fn a_held_note_underneath_sounds_with_the_line_above() {
+        let voice = |song: Vec<Note>| {
+            let (envelope, harmonics) = (Envelope::piano_default(), HarmonicProfile::piano());
+            Voice::new(song, 1.0, 8000, envelope, harmonics, 0.0)
+        };
+        let melody = || [(A4, 0.25), (C5, 0.25), (E5, 0.5)].map(Note::from).to_vec();
+        let held = || vec![Note::from((C4, 1.0))];
+        let mut both = voice(melody());
+        both.hold_under(held(), 1.0);
+        let (mut above, mut below) = (voice(melody()), voice(held()));
+
+        // 0.625 s in, with the held note struck long before and the top
+        // line on its third note
+        let mut at = (0.0, 0.0, 0.0);
+        for _ in 0..=5000 {
+            at = (both.step(false, 1.0), above.step(false, 1.0), below.step(false, 1.0));
+        }
+        let (both, above, below) = at;
+        assert!(above.abs() > 1e-3 && below.abs() > 1e-3, "{} {}", above, below);
+        assert!((both - (above + below)).abs() < 1e-6, "{} {} {}", both, above, below);
+    }
+
+    #[test]
+    fn a_slower_attack_peaks_later() {
+        // Seconds into a struck A4 of the loudest 5 ms
+        let peak_at = |attack: f32| {
+            let song = vec![Note { attack: Some(attack), ..Note::from((A4, 0.5)) }];
+            let (envelope, harmonics) = (Envelope::piano_default(), HarmonicProfile::piano());
+            let mut voice = Voice::new(song, 1.0, 8000, envelope, harmonics, 0.0);
+            let samples: Vec<f32> = (0..4000).map(|_| voice.step(false, 1.0)).collect();
+            let levels: Vec<f32> =
+                samples.chunks(40).map(|w| w.iter().map(|x| x * x).sum::<f32>()).collect();
+            let loudest = (0..levels.len()).max_by(|&a, &b| levels[a].total_cmp(&levels[b]));
+            loudest.unwrap() as f32 * 0.005
+        };
+        let (fast, slow) = (peak_at(0.005), peak_at(0.1));
+        assert!(fast < 0.02, "{}", fast);
+        assert!((slow - 0.1).abs() <= 0.01, "{}", slow);
+    }
+
+    #[test]
+    fn the_stems_add_up_to_the_mix() {
+        let dir = TempDir::new("stems");
+        let out_dir = dir.dir();
+        let song = song("fur_elise");
+        let options = RenderOptions { sample_rate: 8000, out_dir, ..Default::default() };
+        let (stems, _) = generate_stems("fur_elise", tracks(&song), 1.0, options).unwrap();
+        assert_eq!(stems.len(), song.tracks.len());
+
+        let read = |filename: &str| {
+            let audio = wav::read::<Ch32>(&format!("{}/{}", out_dir, filename)).unwrap();
+            audio.iter().map(|f| f32::from(f.channels()[0])).collect::<Vec<f32>>()
+        };
+        let mix = read("fur_elise.wav");
+        let mut summed = vec![0.0; mix.len()];
+        for stem in &stems {
+            for (total, sample) in summed.iter_mut().zip(read(stem)) {
+                *total += sample;
+            }
+        }
+        // Each file rounded to 16 bits on its own
+        let rounding = (stems.len() + 1) as f32 / 32_768.0;
+        let worst = mix.iter().zip(&summed).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+        assert!(worst <= rounding, "{} apart", worst);
+        assert!(mix.iter().any(|sample| sample.abs() > 0.1));
+    }
+
+    #[test]
+    fn bending_up_two_semitones_raises_the_pitch_a_whole_tone() {
+        // Median pitch heard over a held A4
+        let heard = |bend: &[(f32, f32)]| {
+            let note = [Note::from((A4, 1.0))];
+            let options = RenderOptions { bend, ..Default::default() };
+            let audio = render(vec![&note[..]], 1.0, options).unwrap();
+            let audio = Audio::<Ch16, 2>::with_audio(SAMPLE_RATE, &audio);
+            let mut pitches = analyze::detect_pitches(&audio, 1024)[2..40].to_vec();
+            pitches.sort_by(f32::total_cmp);
+            pitches[pitches.len() / 2]
+        };
+        let bent: BendTrack = vec![(2.0, 1.0)];
+        let cents = 1200.0 * (heard(&bent) / heard(&[])).log2();
+        assert!((cents - 200.0).abs() < 2.0, "{} cents", cents);
+    }
+
+    #[test]
+    fn a_phase_seed_renders_the_same_every_time() {
+        let song = song("ode_to_joy");
+        let rendered = |phase_scatter: Option<PhaseScatter>| {
+            let options = RenderOptions { sample_rate: 8000, phase_scatter, ..Default::default() };
+            render(tracks(&song), 1.0, options).unwrap()
+        };
+        let scattered = |seed| rendered(Some(PhaseScatter { spread: 0.25, seed }));
+        assert!(scattered(1).iter().eq(scattered(1).iter()));
+        assert!(!scattered(1).iter().eq(scattered(2).iter()));
+        // No scatter is the deterministic default
+        assert!(!scattered(1).iter().eq(rendered(None).iter()));
+        assert!(rendered(None).iter().eq(rendered(None).iter()));
+    }
+
+    #[test]
+    fn a_three_partial_profile_sounds_only_three_partials() {
+        let profile = HarmonicProfile::normalized(&[1.0, 0.5, 0.3]);
+        assert_eq!(profile.partial_ratios(), [1.0, 2.0, 3.0]);
+        let samples = analyze::to_mono(&render_note(A3.to_freq(), 1.0, &profile, 8000).unwrap());
+        let power = |n: u32| power_at(&samples, A3.to_freq() * n as f32, 8000);
+        let fundamental = power(1);
+        for n in 2..=3 {
+            assert!(power(n) > 0.01 * fundamental, "partial {}", n);
+        }
+        // Up to the tenth, where the piano would still have partials
+        for n in 4..=10 {
+            assert!(power(n) < 1e-4 * fundamental, "partial {}: {}", n, power(n));
+        }
+    }
+
+    #[test]
+    fn the_summary_lines_up_one_row_per_file() {
+        let info = |filename: &str, tempo_accuracy, pitch_accuracy| VariationInfo {
+            filename: filename.to_string(),
+            tempo_accuracy,
+            pitch_accuracy,
+            ..Default::default()
+        };
+        let table = summary_table(&[
+            info("twinkle.wav", 1.0, 1.0),
+            info("twinkle_wrong_notes.wav", 1.0, 0.875),
+            info("twinkle_slow.wav", 0.5, 1.0),
+        ]);
+        let expected = [
+            "File                      Tempo   Pitch",
+            "twinkle.wav              100.0%  100.0%",
+            "twinkle_wrong_notes.wav  100.0%   87.5%",
+            "twinkle_slow.wav          50.0%  100.0%",
+        ];
+        assert_eq!(table.lines().collect::<Vec<_>>(), expected);
+        assert_eq!(summary_table(&[]), "File   Tempo   Pitch\n");
+    }
+
+    #[test]
+    fn a_bass_note_rings_on_after_a_high_one_has_died_away() {
+        let piano = Envelope::piano_default();
+        let (low, high) = (piano.for_pitch(E2.to_freq()), piano.for_pitch(E5.to_freq()));
+        // When E5 is down to a tenth after its attack, E2 is still well above
+        let mut after_attack = (1..300).map(|cs| piano.attack + cs as f32 / 100.0);
+        let when = after_attack.find(|&t| high.level(t) < 0.1).unwrap();
+        assert!(low.level(when) > 0.5, "{} at {} s", low.level(when), when);
+
+        // And so in what each voice plays, against its own start
+        let fall = |pitch: Pitch| {
+            let audio = render_note(pitch.to_freq(), 1.5, &HarmonicProfile::piano(), 8000).unwrap();
+            let samples = analyze::to_mono(&audio);
+            let rms = |from: f32| {
+                let window = &samples[(from * 8000.0) as usize..][..800];
+                (window.iter().map(|x| x * x).sum::<f32>() / 800.0).sqrt()
+            };
+            rms(when) / rms(0.05)
+        };
+        let (low, high) = (fall(E2), fall(E5));
+        assert!(low > 0.5 && high < 0.15, "E2 {} E5 {}", low, high);
+    }
+
+    #[test]
+    fn doubling_the_master_gain_doubles_every_sample() {
+        let song = song("ode_to_joy_harmony");
+        let frames = |master_gain: f32| {
+            let mut proc = Processors::new(to_notes(&tracks(&song)), &[], 1.0, 8000);
+            proc.master_gain = master_gain;
+            (0..16_000).map(|_| proc.step()).collect::<Vec<Frame<Ch32, 2>>>()
+        };
+        let (single, double) = (frames(MASTER_GAIN), frames(2.0 * MASTER_GAIN));
+        for (one, two) in single.iter().zip(&double) {
+            for (a, b) in one.channels().iter().zip(two.channels()) {
+                assert_eq!(2.0 * f32::from(*a), f32::from(*b));
+            }
+        }
+        assert!(single.iter().any(|frame| f32::from(frame.channels()[0]).abs() > 0.01));
+    }
+
+    #[test]
+    fn the_accompaniment_has_the_harmony_and_none_of_the_melody() {
+        let dir = TempDir::new("accompaniment");
+        let out_dir = dir.dir();
+        let song = song("ode_to_joy_harmony");
+        let options = RenderOptions { sample_rate: 8000, out_dir, ..Default::default() };
+        let (_, copies) = generate_stems("ode", tracks(&song), 1.0, options).unwrap();
+        let read = |filename: &str| {
+            let audio = wav::read::<Ch32>(&format!("{}/{}", out_dir, filename)).unwrap();
+            audio.iter().map(|f| f32::from(f.channels()[0])).collect::<Vec<f32>>()
+        };
+        let (mix, accompaniment) = (read("ode.wav"), read(&copies[1]));
+
+        for hz in [C3, G3].map(Pitch::to_freq) {
+            let (kept, full) = (power_at(&accompaniment, hz, 8000), power_at(&mix, hz, 8000));
+            assert!(kept > 0.25 * full, "{} Hz: {} of {}", hz, kept, full);
+        }
+        // Melody pitches that no partial of C3 or G3 lands on
+        for hz in [D4, E4, F4].map(Pitch::to_freq) {
+            let (kept, full) = (power_at(&accompaniment, hz, 8000), power_at(&mix, hz, 8000));
+            assert!(kept < 0.01 * full, "{} Hz: {} of {}", hz, kept, full);
+        }
+    }
+
+    #[test]
+    fn a_sawtooth_sounds_harmonics_a_single_sine_does_not() {
+        // Power of each of the first five harmonics over the fundamental's
+        let harmonics = |profile: &HarmonicProfile| {
+            let samples = analyze::to_mono(&render_note(A3.to_freq(), 1.0, profile, 8000).unwrap());
+            let partial = |n: u32| partial_power(&samples, A3.to_freq() * n as f32, 8000);
+            (2..=5).map(|n| partial(n) / partial(1)).collect()
+        };
+        let sine: Vec<f32> = harmonics(&HarmonicProfile::normalized(&[1.0]));
+        let sawtooth: Vec<f32> = harmonics(&HarmonicProfile::synth_lead());
+        for (n, (sine, sawtooth)) in (2..).zip(sine.into_iter().zip(sawtooth)) {
+            // A sawtooth's nth harmonic is 1/n of its fundamental
+            let expected = 1.0 / (n * n) as f32;
+            assert!(sawtooth > 0.5 * expected, "harmonic {}: {}", n, sawtooth);
+            assert!(sine < 1e-3 * expected, "harmonic {}: {}", n, sine);
+        }
+    }
+
+    #[test]
+    fn a_slurred_note_hands_over_without_a_click() {
+        let slurred = |freq| Note { articulation: Articulation::Legato, ..Note::from((freq, 0.5)) };
+        // Sharpest kink in the waveform (largest second difference) over the
+        // steady end of the first note, and at the join into the second
+        let kinks = |crossfade: f32| {
+            let notes = vec![slurred(A4), slurred(E5)];
+            let mut proc = Processors::new(vec![notes], &[], 1.0, SAMPLE_RATE);
+            proc.set_crossfade(crossfade);
+            let samples: Vec<f32> =
+                (0..25_000).map(|_| f32::from(proc.step().channels()[0])).collect();
+            let kink = |range: std::ops::Range<usize>| {
+                let second = |i: usize| samples[i + 1] - 2.0 * samples[i] + samples[i - 1];
+                range.map(|i| second(i).abs()).fold(0.0, f32::max)
+            };
+            (kink(20_000..23_990), kink(23_990..24_010))
+        };
+        let (steady, join) = kinks(LEGATO_CROSSFADE_SECS);
+        assert!(join <= steady, "{} at the join, {} before it", join, steady);
+        // Cut off with no fade, the first note clicks
+        let (_, cut) = kinks(0.0);
+        assert!(cut > 2.0 * steady, "{} at the join, {} before it", cut, steady);
+    }
+
+    #[test]
+    fn a_voice_plays_a_song_written_by_pitch_as_the_same_song_in_hertz() {
+        let play = |mut voice: Voice| -> Vec<f32> {
+            (0..16_000).map(|_| voice.step(false, 1.0)).collect()
+        };
+        let (envelope, piano) = (Envelope::piano_default(), HarmonicProfile::piano());
+        let by_pitch = vec![(C4, E), (Pitch::Rest, E), (G_SHARP_4, Q)];
+        let by_pitch = Voice::new(by_pitch, 1.0, 8000, envelope, piano.clone(), 0.0);
+        let in_hz = vec![(C4.to_freq(), E), (0.0, E), (G_SHARP_4.to_freq(), Q)];
+        let in_hz = Voice::new(in_hz, 1.0, 8000, envelope, piano, 0.0);
+        assert_eq!(play(by_pitch), play(in_hz));
+    }
+}
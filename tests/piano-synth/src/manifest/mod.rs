@@ -0,0 +1,96 @@
+//! `available_tests.json`: the run's settings and every test file listed.
+
+use serde::{Deserialize, Serialize};
+
+use super::VariationInfo;
+use super::cli::Selection;
+
+/// Layout version of `available_tests.json`, bumped whenever a field is
+/// added, removed or changes meaning. Version 1 was a bare array of tests.
+const SCHEMA_VERSION: &str = "2";
+
+/// Top level of `available_tests.json`.
+#[derive(Serialize, Deserialize)]
+pub(super) struct Manifest {
+    schema_version: String,
+    /// UTC time of the run, RFC 3339; `SOURCE_DATE_EPOCH` overrides the clock
+    /// so a rebuild can reproduce the file exactly.
+    generated_at: String,
+    /// Seed every randomized variation was drawn from.
+    seed: u64,
+    /// Frames per second of every file listed, which the sample positions
+    /// count in.
+    sample_rate: u32,
+    /// Sample encoding of every file listed: "16", "24" or "float".
+    bit_depth: String,
+    /// How each note's velocity set its amplitude, as `--velocity-curve`
+    /// names it.
+    velocity_curve: String,
+    tests: Vec<VariationInfo>,
+}
+
+impl Manifest {
+    pub(super) fn new(tests: Vec<VariationInfo>, selection: &Selection) -> Self {
+        let secs = std::env::var("SOURCE_DATE_EPOCH")
+            .ok()
+            .and_then(|epoch| epoch.parse().ok())
+            .unwrap_or_else(|| {
+                let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH);
+                now.map_or(0, |since| since.as_secs())
+            });
+        Self {
+            schema_version: SCHEMA_VERSION.to_string(),
+            generated_at: utc_timestamp(secs),
+            seed: selection.seed(),
+            sample_rate: selection.sample_rate(),
+            bit_depth: selection.bit_depth().name().to_string(),
+            velocity_curve: selection.velocity_curve().to_string(),
+            tests,
+        }
+    }
+}
+
+// "YYYY-MM-DDThh:mm:ssZ" for seconds since the Unix epoch
+fn utc_timestamp(secs: u64) -> String {
+    let (days, rem) = ((secs / 86_400) as i64, secs % 86_400);
+    // Civil date from a day count (Howard Hinnant's `civil_from_days`)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem / 60 % 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{self, Command};
+
+    #[test]
+    fn the_manifest_reads_back_with_its_schema_version() {
+        let tests = vec![VariationInfo { title: "twinkle".to_string(), ..Default::default() }];
+        let args = ["--seed".to_string(), "3".to_string()];
+        let Ok(Command::Generate(selection, _)) = cli::parse(&args, &[], &[]) else {
+            panic!("expected a normal run");
+        };
+        let json = serde_json::to_string(&Manifest::new(tests, &selection)).unwrap();
+        let manifest: Manifest = serde_json::from_str(&json).unwrap();
+        assert_eq!(manifest.schema_version, SCHEMA_VERSION);
+        assert_eq!(manifest.seed, 3);
+        assert_eq!(manifest.tests.len(), 1);
+        assert_eq!(manifest.tests[0].title, "twinkle");
+    }
+}
@@ -0,0 +1,113 @@
+//! Medleys: several songs played one after another in a single file.
+
+use fon::chan::Ch32;
+use fon::{Audio, Frame};
+use serde::Serialize;
+use std::io;
+
+use super::cli::Selection;
+use super::wav::BitDepth;
+use super::{Song, at_path, render_at_reference, wav, write_json};
+
+/// Seconds of silence between the songs of a medley.
+const MEDLEY_GAP_SECS: f32 = 2.0;
+
+/// Several songs played one after another in a single file.
+#[derive(Serialize)]
+struct MedleyInfo {
+    filename: String,
+    gap_secs: f32,
+    songs: Vec<MedleyEntry>,
+}
+
+#[derive(Serialize)]
+struct MedleyEntry {
+    title: String,
+    /// Sample the song's first note starts on.
+    offset_samples: usize,
+}
+
+/// A song's title and tracks, as played in a medley.
+type MedleySong<'a> = (&'a str, Vec<&'a [(f32, f32)]>);
+
+// Render each song at the reference tempo and write them to `path` back to
+// back, with `gap_secs` of silence between songs
+fn generate_medley(
+    songs: &[MedleySong],
+    gap_secs: f32,
+    sample_rate: u32,
+    depth: BitDepth,
+    path: &str,
+) -> io::Result<MedleyInfo> {
+    let gap = Frame::<Ch32, 2>::default();
+    let gap_len = (gap_secs.max(0.0) * sample_rate as f32).round() as usize;
+    let mut frames = Vec::new();
+    let mut entries = Vec::new();
+
+    for (i, (title, tracks)) in songs.iter().enumerate() {
+        if i > 0 {
+            frames.extend(std::iter::repeat_n(gap, gap_len));
+        }
+        let part = render_at_reference(tracks, sample_rate)?;
+        entries.push(MedleyEntry { title: title.to_string(), offset_samples: frames.len() });
+        frames.extend(part.iter().copied());
+    }
+
+    let audio = Audio::<Ch32, 2>::with_frames(sample_rate, frames);
+    progress!("Writing {}", path);
+    wav::write(&audio, path, depth).map_err(at_path(path))?;
+
+    let filename = std::path::Path::new(path).file_name().unwrap_or_default();
+    Ok(MedleyInfo { filename: filename.to_string_lossy().into_owned(), gap_secs, songs: entries })
+}
+
+// The selected songs back to back in `dir`, and where each begins
+pub(super) fn write_medley(songs: &[Song], selection: &Selection, dir: &str) -> io::Result<()> {
+    std::fs::create_dir_all(dir).map_err(at_path(dir))?;
+    let chosen: Vec<MedleySong> = songs
+        .iter()
+        .filter(|song| selection.wants_song(&song.title))
+        .map(|song| (song.title.as_str(), song.tracks.iter().map(Vec::as_slice).collect()))
+        .collect();
+    let path = format!("{}/medley.wav", dir);
+    let (rate, depth) = (selection.sample_rate(), selection.bit_depth());
+    let info = generate_medley(&chosen, MEDLEY_GAP_SECS, rate, depth, &path)?;
+    let json_path = format!("{}/medley.json", dir);
+    write_json(&json_path, &info)?;
+    progress!("Wrote {}", json_path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SAMPLE_RATE;
+    use crate::tests::TempDir;
+
+    #[test]
+    fn a_medley_is_its_songs_and_the_gaps_between_them() {
+        let songs = Song::all();
+        let chosen: Vec<MedleySong> = songs
+            .iter()
+            .take(3)
+            .map(|song| (song.title.as_str(), song.tracks.iter().map(Vec::as_slice).collect()))
+            .collect();
+        let rate = SAMPLE_RATE / 6;
+        let dir = TempDir::new("medley");
+        let path = &dir.path("medley.wav");
+        let info = generate_medley(&chosen, 1.0, rate, BitDepth::default(), path).unwrap();
+        let written = wav::read::<Ch32>(path).unwrap();
+
+        let lengths: Vec<usize> = chosen
+            .iter()
+            .map(|(_, tracks)| render_at_reference(tracks, rate).unwrap().len())
+            .collect();
+        let gaps = (chosen.len() - 1) * rate as usize;
+        assert_eq!(written.len(), lengths.iter().sum::<usize>() + gaps);
+        let mut offset = 0;
+        for (entry, length) in info.songs.iter().zip(&lengths) {
+            assert_eq!(entry.offset_samples, offset, "{}", entry.title);
+            offset += length + rate as usize;
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Metronome click track, to play or align a performance against.
+
+use fon::chan::{Ch16, Ch32, Channel};
+use fon::{Audio, Frame};
+use std::f32::consts::{FRAC_PI_4, TAU};
+
+/// Beats per bar; the first of each bar is accented.
+const BEATS_PER_BAR: usize = 4;
+
+/// Length of each blip in seconds.
+const CLICK_SECS: f32 = 0.03;
+
+/// Blip pitch and level for the downbeat and the other beats.
+const ACCENT: (f32, f32) = (1_500.0, 0.6);
+const BEAT: (f32, f32) = (1_000.0, 0.35);
+
+/// `beats` clicks at `bpm`, starting on the first sample: a short decaying
+/// sine blip per beat, higher and louder on each downbeat.
+pub(super) fn generate_click(bpm: f32, beats: usize, sample_rate: u32) -> Audio<Ch16, 2> {
+    let rate = sample_rate as f32;
+    let beat_len = 60.0 / bpm * rate;
+    let click_len = (CLICK_SECS * rate) as usize;
+    let len = (beats as f32 * beat_len).round() as usize;
+    let mut audio = Audio::<Ch16, 2>::with_silence(sample_rate, len);
+
+    for beat in 0..beats {
+        let (freq, level) = if beat % BEATS_PER_BAR == 0 { ACCENT } else { BEAT };
+        let start = (beat as f32 * beat_len).round() as usize;
+        for i in 0..click_len {
+            let Some(frame) = audio.get_mut(start + i) else {
+                break;
+            };
+            let t = i as f32 / rate;
+            // Falls to about 1% by the end of the blip
+            let envelope = (-5.0 * i as f32 / click_len as f32).exp();
+            let sample = Ch16::from(level * envelope * (TAU * freq * t).sin());
+            *frame = Frame::<Ch16, 2>::new(sample, sample);
+        }
+    }
+    audio
+}
+
+/// Add `click` into `audio` from the first sample, panned to `pan`
+/// (-1.0 left ..= 1.0 right); any click past the end of `audio` is dropped.
+pub(super) fn mix_in(audio: &mut Audio<Ch32, 2>, click: &Audio<Ch16, 2>, pan: f32) {
+    // Constant power, as for the voices
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_4;
+    let gains = [angle.cos(), angle.sin()];
+    for (frame, tick) in audio.iter_mut().zip(click.iter()) {
+        let mono = tick.channels().iter().map(|s| s.to_f32()).sum::<f32>() / 2.0;
+        for (sample, gain) in frame.channels_mut().iter_mut().zip(gains) {
+            *sample = Ch32::from(sample.to_f32() + mono * gain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loudest sample of `audio`'s left channel in `from..to`
+    fn peak(audio: &Audio<Ch16, 2>, from: usize, to: usize) -> f32 {
+        let frames = audio.iter().skip(from).take(to - from);
+        frames.map(|frame| frame.channels()[0].to_f32().abs()).fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn clicks_land_on_the_beats_and_accent_the_bar() {
+        // 120 BPM at 8 kHz: a beat every 4000 samples, a click 240 long
+        let click = generate_click(120.0, 5, 8000);
+        assert_eq!(click.len(), 20_000);
+        let beats: Vec<f32> =
+            (0..5).map(|beat| peak(&click, beat * 4000, beat * 4000 + 240)).collect();
+        assert!(beats[0] > beats[1] && (beats[0] - beats[4]).abs() < 1e-3, "{:?}", beats);
+        assert!(beats[1..4].iter().all(|&level| level > 0.2));
+        // Silence, as near as 16 bits can say
+        assert!(peak(&click, 240, 4000) < 1e-4);
+    }
+
+    #[test]
+    fn a_click_is_mixed_in_where_it_is_panned() {
+        let click = generate_click(120.0, 1, 8000);
+        let mut audio = Audio::<Ch32, 2>::with_silence(8000, 2000);
+        mix_in(&mut audio, &click, -1.0);
+        let (left, right) = audio.iter().fold((0.0f32, 0.0f32), |(left, right), frame| {
+            let [l, r] = [frame.channels()[0], frame.channels()[1]].map(|s| s.to_f32().abs());
+            (left.max(l), right.max(r))
+        });
+        assert!(left > 0.5 && right < 1e-6, "left {}, right {}", left, right);
+        assert_eq!(audio.len(), 2000);
+    }
+}
@@ -0,0 +1,346 @@
+//! Minimal Standard MIDI File (SMF) reader and writer.
+//!
+//! Tracks are monophonic both ways, so chords don't survive a read: within
+//! a track only the topmost of any overlapping notes is kept.
+//!
+//! https://www.music.mcgill.ca/~ich/classes/mumt306/StandardMIDIfileformat.html
+
+use super::pitch::{freq_to_midi, midi_to_freq};
+use std::{fs, io};
+
+/// Ticks per quarter note.
+const DIVISION: u16 = 480;
+
+/// Note-on velocity used for every note.
+const VELOCITY: u8 = 100;
+
+/// Write a type-1 SMF with one MTrk per track of `(frequency, seconds)` notes
+pub(super) fn write(tracks: &[&[(f32, f32)]], tempo_bpm: f32, path: &str) -> io::Result<()> {
+    let mut buf = vec![];
+    write_header(&mut buf, tracks.len());
+    for (i, track) in tracks.iter().enumerate() {
+        write_track(&mut buf, track, tempo_bpm, i as u8 & 0x0F, i == 0);
+    }
+    fs::write(path, buf)
+}
+
+fn write_header(buf: &mut Vec<u8>, num_tracks: usize) {
+    buf.extend(b"MThd");
+    buf.extend(&6u32.to_be_bytes());
+    // Format 1: simultaneous tracks
+    buf.extend(&1u16.to_be_bytes());
+    buf.extend(&(num_tracks as u16).to_be_bytes());
+    buf.extend(&DIVISION.to_be_bytes());
+}
+
+fn write_track(
+    buf: &mut Vec<u8>,
+    track: &[(f32, f32)],
+    tempo_bpm: f32,
+    channel: u8,
+    with_tempo: bool,
+) {
+    let ticks_per_sec = tempo_bpm / 60.0 * DIVISION as f32;
+    let mut events = vec![];
+
+    if with_tempo {
+        // Tempo meta event: microseconds per quarter note
+        let micros = (60_000_000.0 / tempo_bpm).round() as u32;
+        write_var_len(&mut events, 0);
+        events.extend(&[0xFF, 0x51, 0x03]);
+        events.extend(&micros.to_be_bytes()[1..]);
+    }
+
+    // Round cumulative positions so durations don't drift
+    let mut elapsed = 0.0f32;
+    let mut last_tick = 0u32;
+    for &(freq, dur) in track {
+        let start = (elapsed * ticks_per_sec).round() as u32;
+        elapsed += dur;
+        let end = (elapsed * ticks_per_sec).round() as u32;
+        // Rests are just a gap before the next note-on
+        if freq <= 0.0 || end == start {
+            continue;
+        }
+        let note = freq_to_midi(freq).round().clamp(0.0, 127.0) as u8;
+        write_var_len(&mut events, start - last_tick);
+        events.extend(&[0x90 | channel, note, VELOCITY]);
+        write_var_len(&mut events, end - start);
+        events.extend(&[0x80 | channel, note, 0]);
+        last_tick = end;
+    }
+
+    // End of track, after any trailing rest
+    let total = (elapsed * ticks_per_sec).round() as u32;
+    write_var_len(&mut events, total - last_tick);
+    events.extend(&[0xFF, 0x2F, 0x00]);
+
+    buf.extend(b"MTrk");
+    buf.extend(&(events.len() as u32).to_be_bytes());
+    buf.extend(events);
+}
+
+// Variable-length quantity: 7 bits per byte, high bit set on all but the last
+fn write_var_len(buf: &mut Vec<u8>, mut value: u32) {
+    let mut bytes = vec![(value & 0x7F) as u8];
+    value >>= 7;
+    while value > 0 {
+        bytes.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.extend(bytes.iter().rev());
+}
+
+/// Read a type-0 or type-1 SMF into one `(frequency, seconds)` track per MTrk.
+///
+/// Each track is monophonic: where notes overlap, the highest sounding pitch
+/// wins, and a lower note still held resumes once the higher one ends.
+/// Gaps become rests (frequency 0.0).
+pub(super) fn read(path: &str) -> io::Result<Vec<Vec<(f32, f32)>>> {
+    let buf = fs::read(path)?;
+    let header = chunk(&buf, 0, b"MThd")?;
+    if header.len() < 6 {
+        return Err(invalid("MThd chunk too short"));
+    }
+    let format = u16::from_be_bytes([header[0], header[1]]);
+    let num_tracks = u16::from_be_bytes([header[2], header[3]]) as usize;
+    let division = u16::from_be_bytes([header[4], header[5]]);
+    if format > 1 {
+        return Err(invalid("only format 0 and 1 files are supported"));
+    }
+    if division & 0x8000 != 0 || division == 0 {
+        return Err(invalid("only metrical (ticks per quarter) timing is supported"));
+    }
+
+    let mut pos = 8 + header.len();
+    let mut tracks = Vec::with_capacity(num_tracks);
+    for _ in 0..num_tracks {
+        let body = chunk(&buf, pos, b"MTrk")?;
+        tracks.push(read_track(body)?);
+        pos += 8 + body.len();
+    }
+
+    // The tempo map (normally in the first track) applies to every track
+    let mut tempos: Vec<(u32, u32)> = tracks.iter().flat_map(|t| t.tempos.clone()).collect();
+    tempos.sort_by_key(|&(tick, _)| tick);
+    let secs = |tick: u32| ticks_to_secs(tick, &tempos, division);
+
+    Ok(tracks
+        .iter()
+        .map(|track| {
+            collapse(&track.notes, track.end)
+                .into_iter()
+                .map(|(key, start, end)| {
+                    let freq = key.map_or(0.0, |k| midi_to_freq(k as f32));
+                    (freq, secs(end) - secs(start))
+                })
+                .collect()
+        })
+        .collect())
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Body of the chunk at `pos`, which must have the given ID
+fn chunk<'a>(buf: &'a [u8], pos: usize, id: &[u8; 4]) -> io::Result<&'a [u8]> {
+    let head = buf.get(pos..pos + 8).ok_or_else(|| invalid("truncated chunk header"))?;
+    if &head[0..4] != id {
+        return Err(invalid("unexpected chunk"));
+    }
+    let len = u32::from_be_bytes(head[4..8].try_into().unwrap()) as usize;
+    buf.get(pos + 8..pos + 8 + len).ok_or_else(|| invalid("truncated chunk"))
+}
+
+struct TrackEvents {
+    /// (key, start tick, end tick)
+    notes: Vec<(u8, u32, u32)>,
+    /// (tick, microseconds per quarter)
+    tempos: Vec<(u32, u32)>,
+    /// Tick of the end-of-track event
+    end: u32,
+}
+
+fn read_track(body: &[u8]) -> io::Result<TrackEvents> {
+    let mut events = TrackEvents { notes: vec![], tempos: vec![], end: 0 };
+    // Notes held down: (channel, key, start tick)
+    let mut held: Vec<(u8, u8, u32)> = vec![];
+    let mut pos = 0;
+    let mut tick = 0u32;
+    let mut running_status = None;
+    let byte = |pos: usize| body.get(pos).copied().ok_or_else(|| invalid("truncated event"));
+
+    while pos < body.len() {
+        tick += read_var_len(body, &mut pos)?;
+        let mut status = byte(pos)?;
+        if status & 0x80 != 0 {
+            pos += 1;
+        } else {
+            // Running status: reuse the previous channel message's status
+            status = running_status.ok_or_else(|| invalid("data byte without status"))?;
+        }
+
+        match status {
+            0xFF => {
+                let kind = byte(pos)?;
+                pos += 1;
+                let len = read_var_len(body, &mut pos)? as usize;
+                let data = body.get(pos..pos + len).ok_or_else(|| invalid("truncated meta event"))?;
+                pos += len;
+                match kind {
+                    0x51 if len == 3 => {
+                        let micros = u32::from_be_bytes([0, data[0], data[1], data[2]]);
+                        events.tempos.push((tick, micros));
+                    }
+                    0x2F => break,
+                    _ => {}
+                }
+            }
+            0xF0 | 0xF7 => {
+                let len = read_var_len(body, &mut pos)? as usize;
+                pos += len;
+            }
+            _ => {
+                running_status = Some(status);
+                let channel = status & 0x0F;
+                let data_len = if matches!(status & 0xF0, 0xC0 | 0xD0) { 1 } else { 2 };
+                let key = byte(pos)?;
+                let velocity = if data_len == 2 { byte(pos + 1)? } else { 0 };
+                pos += data_len;
+
+                let is_on = status & 0xF0 == 0x90 && velocity > 0;
+                let is_off = status & 0xF0 == 0x80 || (status & 0xF0 == 0x90 && velocity == 0);
+                if is_on || is_off {
+                    // A note-on for a held key also ends the earlier strike
+                    if let Some(i) = held.iter().position(|&(c, k, _)| c == channel && k == key) {
+                        let (_, _, start) = held.remove(i);
+                        events.notes.push((key, start, tick));
+                    }
+                    if is_on {
+                        held.push((channel, key, tick));
+                    }
+                }
+            }
+        }
+    }
+
+    // Close any notes left hanging at the end of the track
+    for (_, key, start) in held {
+        events.notes.push((key, start, tick));
+    }
+    events.end = tick;
+    Ok(events)
+}
+
+fn read_var_len(buf: &[u8], pos: &mut usize) -> io::Result<u32> {
+    let mut value = 0u32;
+    for _ in 0..4 {
+        let b = *buf.get(*pos).ok_or_else(|| invalid("truncated variable-length value"))?;
+        *pos += 1;
+        value = (value << 7) | (b & 0x7F) as u32;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid("variable-length value too long"))
+}
+
+// Split the track into segments sounding the highest held key (or a rest),
+// merging segments that continue the same note.
+fn collapse(notes: &[(u8, u32, u32)], end: u32) -> Vec<(Option<u8>, u32, u32)> {
+    let mut bounds: Vec<u32> = notes.iter().flat_map(|&(_, s, e)| [s, e]).collect();
+    bounds.extend([0, end]);
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    let mut segments: Vec<(Option<usize>, u32, u32)> = vec![];
+    for pair in bounds.windows(2) {
+        let (from, to) = (pair[0], pair[1]);
+        let top = (0..notes.len())
+            .filter(|&i| notes[i].1 <= from && from < notes[i].2)
+            .max_by_key(|&i| (notes[i].0, notes[i].1));
+        match segments.last_mut() {
+            Some(last) if last.0 == top => last.2 = to,
+            _ => segments.push((top, from, to)),
+        }
+    }
+    segments
+        .into_iter()
+        .map(|(top, from, to)| (top.map(|i| notes[i].0), from, to))
+        .collect()
+}
+
+fn ticks_to_secs(tick: u32, tempos: &[(u32, u32)], division: u16) -> f32 {
+    let mut secs = 0.0f64;
+    let mut last_tick = 0u32;
+    let mut micros = 500_000u32; // 120 BPM until told otherwise
+    for &(at, new_micros) in tempos.iter().take_while(|&&(at, _)| at < tick) {
+        secs += (at - last_tick) as f64 * micros as f64;
+        last_tick = at;
+        micros = new_micros;
+    }
+    secs += (tick - last_tick) as f64 * micros as f64;
+    (secs / division as f64 / 1e6) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+
+    // Read `bytes` back the way `read` reads a file
+    fn read_bytes(name: &str, bytes: &[u8]) -> Vec<Vec<(f32, f32)>> {
+        let dir = TempDir::new(name);
+        let path = dir.path("bytes.mid");
+        fs::write(&path, bytes).unwrap();
+        read(&path).unwrap()
+    }
+
+    #[test]
+    fn what_is_written_reads_back() {
+        let melody = [(440.0, 0.5), (0.0, 0.25), (523.25, 0.3), (392.0, 0.7)];
+        let bass = [(110.0, 1.0), (146.83, 0.75)];
+        let dir = TempDir::new("midi_round_trip");
+        let path = &dir.path("round_trip.mid");
+        write(&[&melody, &bass], 100.0, path).unwrap();
+
+        let tracks = read(path).unwrap();
+        assert_eq!(tracks.len(), 2);
+        // Times can only move to the nearest tick
+        let tick_secs = 60.0 / 100.0 / DIVISION as f32;
+        for (read, written) in tracks.iter().zip([&melody[..], &bass[..]]) {
+            assert_eq!(read.len(), written.len());
+            for (&(freq, dur), &(expected_freq, expected_dur)) in read.iter().zip(written) {
+                assert!((freq - expected_freq).abs() < 0.01, "{} for {}", freq, expected_freq);
+                assert!((dur - expected_dur).abs() <= tick_secs, "{} for {}", dur, expected_dur);
+            }
+        }
+    }
+
+    #[test]
+    fn overlapping_notes_keep_the_topmost() {
+        // C4 held from 0 to 720 ticks, with G4 over it from 240 to 480
+        let mut bytes = b"MThd\0\0\0\x06\0\0\0\x01\x01\xE0".to_vec();
+        let events = [
+            0x00, 0x90, 0x3C, 0x64, // C4 on
+            0x81, 0x70, 0x90, 0x43, 0x64, // G4 on
+            0x81, 0x70, 0x80, 0x43, 0x00, // G4 off
+            0x81, 0x70, 0x80, 0x3C, 0x00, // C4 off
+            0x00, 0xFF, 0x2F, 0x00,
+        ];
+        bytes.extend(b"MTrk");
+        bytes.extend((events.len() as u32).to_be_bytes());
+        bytes.extend(events);
+
+        let tracks = read_bytes("midi_overlap", &bytes);
+        // 120 BPM without a tempo event: 240 ticks are a quarter of a second
+        let pitches: Vec<f32> = tracks[0].iter().map(|&(freq, _)| freq_to_midi(freq)).collect();
+        let durations: Vec<f32> = tracks[0].iter().map(|&(_, dur)| dur).collect();
+        assert_eq!(pitches.len(), 3);
+        for (pitch, expected) in pitches.iter().zip([60.0, 67.0, 60.0]) {
+            assert!((pitch - expected).abs() < 0.01, "{:?}", pitches);
+        }
+        assert!(durations.iter().all(|dur| (dur - 0.25).abs() < 1e-6), "{:?}", durations);
+    }
+}
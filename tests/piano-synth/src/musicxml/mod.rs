@@ -0,0 +1,300 @@
+//! Minimal MusicXML (score-partwise) writer, so notation editors and MIR
+//! tools can read the expected notes.
+//!
+//! https://www.w3.org/2021/06/musicxml40/
+
+use super::pitch::spell;
+use super::{Q, REFERENCE_TEMPO, Song, TRIPLET_E, triplet};
+use std::{fs, io};
+
+/// Duration units per quarter note: enough for sixteenths and triplets.
+const DIVISIONS: u32 = 12;
+
+/// Note types by duration in divisions, undotted and dotted.
+const NOTE_TYPES: [(u32, &str, bool); 9] = [
+    (72, "whole", true),
+    (48, "whole", false),
+    (36, "half", true),
+    (24, "half", false),
+    (18, "quarter", true),
+    (12, "quarter", false),
+    (9, "eighth", true),
+    (6, "eighth", false),
+    (3, "16th", false),
+];
+
+/// Triplet notes, three in the time of two, by their length in seconds.
+const TRIPLET_TYPES: [(f32, &str); 2] = [(TRIPLET_E, "eighth"), (triplet(Q), "quarter")];
+
+/// Major tonics by the number of sharps (positive) or flats in their key
+/// signature, from seven flats to seven sharps; minor keys are looked up by
+/// their relative major.
+const MAJOR_TONICS: [&str; 15] = [
+    "Cb", "Gb", "Db", "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#",
+];
+const MINOR_TONICS: [&str; 15] = [
+    "Ab", "Eb", "Bb", "F", "C", "G", "D", "A", "E", "B", "F#", "C#", "G#", "D#", "A#",
+];
+
+// Part of a note that fits in one measure; a note crossing a barline is
+// split into pieces tied together
+struct Piece {
+    freq: f32,
+    duration: u32,
+    tied_from: bool,
+    tied_to: bool,
+}
+
+/// Write `song` as a single piano part marked at `bpm` quarter notes per
+/// minute, one voice per track. Notes crossing a barline are split and tied;
+/// rests (frequency 0.0) become `<rest/>` elements.
+pub(super) fn write(song: &Song, bpm: f32, path: &str) -> io::Result<()> {
+    let (beats, beat_type) = song.time_signature;
+    let measure_len = beats * DIVISIONS * 4 / beat_type.max(1);
+    let pickup = to_divisions(song.pickup).min(measure_len);
+    let measures: Vec<Vec<Vec<Piece>>> =
+        song.tracks.iter().map(|track| split_measures(track, measure_len, pickup)).collect();
+    let count = measures.iter().map(Vec::len).max().unwrap_or(0);
+    let two_staves = song.tracks.len() > 1;
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(
+        "<!DOCTYPE score-partwise PUBLIC \"-//Recordare//DTD MusicXML 4.0 Partwise//EN\" \
+         \"http://www.musicxml.org/dtds/partwise.dtd\">\n",
+    );
+    xml.push_str("<score-partwise version=\"4.0\">\n");
+    xml.push_str(&format!("  <work><work-title>{}</work-title></work>\n", song.title));
+    xml.push_str("  <part-list>\n");
+    xml.push_str("    <score-part id=\"P1\"><part-name>Piano</part-name></score-part>\n");
+    xml.push_str("  </part-list>\n");
+    xml.push_str("  <part id=\"P1\">\n");
+
+    for m in 0..count {
+        // An upbeat is an incomplete measure numbered 0
+        let number = if pickup > 0 { m } else { m + 1 };
+        let implicit = if number == 0 { " implicit=\"yes\"" } else { "" };
+        xml.push_str(&format!("    <measure number=\"{}\"{}>\n", number, implicit));
+        if m == 0 {
+            write_attributes(&mut xml, song, bpm, two_staves);
+        }
+
+        let mut previous = 0;
+        for (voice, track) in measures.iter().enumerate() {
+            let Some(pieces) = track.get(m) else {
+                continue;
+            };
+            // Each further voice starts again from the barline
+            if previous > 0 {
+                let backup = format!("      <backup><duration>{}</duration></backup>\n", previous);
+                xml.push_str(&backup);
+            }
+            let staff = if two_staves && voice > 0 { 2 } else { 1 };
+            for piece in pieces {
+                write_note(&mut xml, piece, voice + 1, staff, song.key.as_deref());
+            }
+            previous = pieces.iter().map(|p| p.duration).sum();
+        }
+        xml.push_str("    </measure>\n");
+    }
+
+    xml.push_str("  </part>\n");
+    xml.push_str("</score-partwise>\n");
+    fs::write(path, xml)
+}
+
+// Seconds at the reference tempo to divisions
+fn to_divisions(secs: f32) -> u32 {
+    let quarters = secs * REFERENCE_TEMPO.bpm / 60.0;
+    (quarters * DIVISIONS as f32).round() as u32
+}
+
+// Cut a track into measures of `measure_len` divisions, after an upbeat of
+// `pickup`. Positions are rounded from the running total so that rounding
+// never drifts the notes against the barlines.
+fn split_measures(track: &[(f32, f32)], measure_len: u32, pickup: u32) -> Vec<Vec<Piece>> {
+    // Shift so every barline falls on a multiple of `measure_len`
+    let offset = if pickup > 0 { measure_len - pickup } else { 0 };
+    let mut measures: Vec<Vec<Piece>> = Vec::new();
+    let mut elapsed = 0.0;
+    for &(freq, duration) in track {
+        let start = to_divisions(elapsed) + offset;
+        elapsed += duration;
+        let end = to_divisions(elapsed) + offset;
+        let mut pos = start;
+        while pos < end {
+            let m = (pos / measure_len) as usize;
+            let take = end.min((m as u32 + 1) * measure_len) - pos;
+            if measures.len() <= m {
+                measures.resize_with(m + 1, Vec::new);
+            }
+            // Rests are just repeated, never tied
+            let pitched = freq > 0.0;
+            let tied_from = pitched && pos > start;
+            pos += take;
+            let tied_to = pitched && pos < end;
+            measures[m].push(Piece { freq, duration: take, tied_from, tied_to });
+        }
+    }
+    measures
+}
+
+fn write_attributes(xml: &mut String, song: &Song, bpm: f32, two_staves: bool) {
+    let (beats, beat_type) = song.time_signature;
+    xml.push_str("      <attributes>\n");
+    xml.push_str(&format!("        <divisions>{}</divisions>\n", DIVISIONS));
+    if let Some((fifths, mode)) = song.key.as_deref().and_then(key_signature) {
+        xml.push_str(&format!(
+            "        <key><fifths>{}</fifths><mode>{}</mode></key>\n",
+            fifths, mode
+        ));
+    }
+    xml.push_str(&format!(
+        "        <time><beats>{}</beats><beat-type>{}</beat-type></time>\n",
+        beats, beat_type
+    ));
+    if two_staves {
+        xml.push_str("        <staves>2</staves>\n");
+        xml.push_str("        <clef number=\"1\"><sign>G</sign><line>2</line></clef>\n");
+        xml.push_str("        <clef number=\"2\"><sign>F</sign><line>4</line></clef>\n");
+    } else {
+        xml.push_str("        <clef><sign>G</sign><line>2</line></clef>\n");
+    }
+    xml.push_str("      </attributes>\n");
+    xml.push_str(&format!(
+        "      <direction placement=\"above\"><direction-type><metronome>\
+         <beat-unit>quarter</beat-unit><per-minute>{:.0}</per-minute></metronome>\
+         </direction-type><sound tempo=\"{:.2}\"/></direction>\n",
+        bpm, bpm
+    ));
+}
+
+fn write_note(xml: &mut String, piece: &Piece, voice: usize, staff: usize, key: Option<&str>) {
+    xml.push_str("      <note>\n");
+    match step_alter_octave(piece.freq, key) {
+        Some((step, alter, octave)) => {
+            let alter =
+                if alter != 0 { format!("<alter>{}</alter>", alter) } else { String::new() };
+            xml.push_str(&format!(
+                "        <pitch><step>{}</step>{}<octave>{}</octave></pitch>\n",
+                step, alter, octave
+            ));
+        }
+        None => xml.push_str("        <rest/>\n"),
+    }
+    xml.push_str(&format!("        <duration>{}</duration>\n", piece.duration));
+    if piece.tied_from {
+        xml.push_str("        <tie type=\"stop\"/>\n");
+    }
+    if piece.tied_to {
+        xml.push_str("        <tie type=\"start\"/>\n");
+    }
+    xml.push_str(&format!("        <voice>{}</voice>\n", voice));
+    // Durations with no single note value are left for the reader to spell
+    let triplet = TRIPLET_TYPES.iter().find(|&&(secs, _)| to_divisions(secs) == piece.duration);
+    if let Some(&(_, name, dotted)) = NOTE_TYPES.iter().find(|&&(d, ..)| d == piece.duration) {
+        xml.push_str(&format!("        <type>{}</type>\n", name));
+        if dotted {
+            xml.push_str("        <dot/>\n");
+        }
+    } else if let Some(&(_, name)) = triplet {
+        xml.push_str(&format!("        <type>{}</type>\n", name));
+        xml.push_str(
+            "        <time-modification><actual-notes>3</actual-notes>\
+             <normal-notes>2</normal-notes></time-modification>\n",
+        );
+    }
+    xml.push_str(&format!("        <staff>{}</staff>\n", staff));
+    if piece.tied_from || piece.tied_to {
+        xml.push_str("        <notations>");
+        if piece.tied_from {
+            xml.push_str("<tied type=\"stop\"/>");
+        }
+        if piece.tied_to {
+            xml.push_str("<tied type=\"start\"/>");
+        }
+        xml.push_str("</notations>\n");
+    }
+    xml.push_str("      </note>\n");
+}
+
+// Step letter, semitones of sharp or flat, and octave of the nearest note,
+// spelled for `key`; `None` for a rest
+fn step_alter_octave(freq: f32, key: Option<&str>) -> Option<(char, i32, i32)> {
+    if freq < 1.0 {
+        return None;
+    }
+    // Any tolerance names the nearest semitone instead of printing Hz
+    let name = spell(freq, f32::INFINITY, key);
+    let mut chars = name.chars();
+    let step = chars.next()?;
+    let rest = chars.as_str();
+    let alter = match rest.chars().next() {
+        Some('#') => 1,
+        Some('b') => -1,
+        _ => 0,
+    };
+    let octave = rest[(alter != 0) as usize..].parse().ok()?;
+    Some((step, alter, octave))
+}
+
+// Fifths and mode of a key such as "A minor"; `None` if it isn't recognised
+fn key_signature(key: &str) -> Option<(i32, &'static str)> {
+    let mut words = key.split_whitespace();
+    let tonic = words.next()?;
+    let (tonics, mode) = match words.next().map(str::to_ascii_lowercase).as_deref() {
+        Some("minor") => (&MINOR_TONICS, "minor"),
+        _ => (&MAJOR_TONICS, "major"),
+    };
+    let index = tonics.iter().position(|&t| t == tonic)?;
+    Some((index as i32 - 7, mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{C4, E, E4, G4, H, Pitch};
+
+    #[test]
+    fn triplets_are_marked_three_in_the_time_of_two() {
+        let melody = [
+            (C4, TRIPLET_E), (E4, TRIPLET_E), (G4, TRIPLET_E),
+            (C4, triplet(Q)), (E4, triplet(Q)), (G4, triplet(Q)),
+            (C4, E), (E4, E), (G4, H),
+        ];
+        let song = Song::new("triplets", &[&melody], "C major");
+        let path = std::env::temp_dir().join("musicxml_test_triplets.musicxml");
+        let path = path.to_str().unwrap();
+        write(&song, REFERENCE_TEMPO.bpm, path).unwrap();
+
+        let xml = fs::read_to_string(path).unwrap();
+        assert_eq!(xml.matches("<time-modification>").count(), 6);
+        assert_eq!(xml.matches("<duration>4</duration>").count(), 3);
+        assert_eq!(xml.matches("<duration>8</duration>").count(), 3);
+        assert_eq!(xml.matches("<type>eighth</type>").count(), 5);
+    }
+
+    #[test]
+    fn every_note_and_rest_is_written_once() {
+        let rest = Pitch::Rest;
+        let melody = [(C4, Q), (rest, Q), (E4, H), (G4, E), (rest, E), (C4, Q), (E4, H)];
+        let song = Song::new("counted", &[&melody], "C major");
+        let path = std::env::temp_dir().join("musicxml_test_counted.musicxml");
+        let path = path.to_str().unwrap();
+        write(&song, REFERENCE_TEMPO.bpm, path).unwrap();
+        let xml = fs::read_to_string(path).unwrap();
+        assert_eq!(xml.matches("<note>").count(), melody.len());
+        assert_eq!(xml.matches("<rest/>").count(), 2);
+
+        // In the built-in songs a note across a barline is written as tied
+        // pieces, each piece after the first marked as a tie's stop
+        for song in Song::all() {
+            write(&song, REFERENCE_TEMPO.bpm, path).unwrap();
+            let xml = fs::read_to_string(path).unwrap();
+            let pitched = song.tracks.iter().flatten().filter(|&&(freq, _)| freq > 0.0).count();
+            let pieces = xml.matches("<pitch>").count();
+            let ties = xml.matches("<tie type=\"stop\"/>").count();
+            assert_eq!(pieces - ties, pitched, "{}", song.title);
+        }
+    }
+}
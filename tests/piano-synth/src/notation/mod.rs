@@ -0,0 +1,151 @@
+//! Plain-text song notation, one note per line:
+//!
+//! ```text
+//! ; Comments start with a semicolon
+//! tempo 120   ; optional, quarter notes per minute
+//! E5 16       ; sixteenth note
+//! A4 8.       ; dotted eighth (each dot adds half again)
+//! Bb3 4
+//! rest 2
+//! ```
+//!
+//! Note values are 1 (whole) through 64; without a `tempo` line the song is
+//! read at the reference tempo of the built-in tables.
+
+use super::pitch::Pitch;
+use super::{REFERENCE_TEMPO, Tempo};
+use std::fmt;
+
+/// Malformed input, located by 1-based line and column.
+#[derive(Debug)]
+pub(super) struct ParseError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Parse a song into `(frequency, duration)` pairs; rests have frequency 0.0.
+pub(super) fn parse(input: &str) -> Result<Vec<(f32, f32)>, ParseError> {
+    let mut tempo = REFERENCE_TEMPO;
+    let mut song = Vec::new();
+
+    for (line_idx, raw_line) in input.lines().enumerate() {
+        let line = raw_line.split(';').next().unwrap_or("");
+        let mut tokens = tokens(line);
+        let Some((col, first)) = tokens.next() else {
+            continue;
+        };
+        let error = |column: usize, message: String| ParseError {
+            line: line_idx + 1,
+            column,
+            message,
+        };
+        let Some((value_col, value)) = tokens.next() else {
+            return Err(error(col, format!("expected a note value after '{}'", first)));
+        };
+        if let Some((extra_col, extra)) = tokens.next() {
+            return Err(error(extra_col, format!("unexpected '{}'", extra)));
+        }
+
+        if first.eq_ignore_ascii_case("tempo") {
+            tempo = match value.parse::<f32>() {
+                Ok(bpm) if bpm > 0.0 => Tempo { bpm },
+                _ => return Err(error(value_col, format!("invalid tempo '{}'", value))),
+            };
+            continue;
+        }
+
+        let freq = if first.eq_ignore_ascii_case("rest") {
+            0.0
+        } else {
+            parse_pitch(first)
+                .ok_or_else(|| error(col, format!("unknown note '{}'", first)))?
+                .to_freq()
+        };
+        let beats = parse_beats(value)
+            .ok_or_else(|| error(value_col, format!("invalid note value '{}'", value)))?;
+        song.push((freq, beats * 60.0 / tempo.bpm));
+    }
+    Ok(song)
+}
+
+// Whitespace-separated tokens with their 1-based starting columns
+fn tokens(line: &str) -> impl Iterator<Item = (usize, &str)> {
+    line.split_whitespace()
+        .map(move |token| (token.as_ptr() as usize - line.as_ptr() as usize + 1, token))
+}
+
+// Note name, any number of '#' or 'b', then octave: "C4", "F#3", "Bb2"
+fn parse_pitch(token: &str) -> Option<Pitch> {
+    let mut chars = token.chars();
+    let class = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let octave_at = rest.find(|c: char| c.is_ascii_digit() || c == '-')?;
+    let (accidentals, octave) = rest.split_at(octave_at);
+    let alter = accidentals.chars().try_fold(0, |alter, c| match c {
+        '#' => Some(alter + 1),
+        'b' => Some(alter - 1),
+        _ => None,
+    })?;
+    let octave: i32 = octave.parse().ok()?;
+    Some(Pitch::from_midi((octave + 1) * 12 + class + alter))
+}
+
+// "4" = quarter = 1 beat, "8." = dotted eighth = 0.75 beats
+fn parse_beats(token: &str) -> Option<f32> {
+    let digits = token.trim_end_matches('.');
+    let dots = token.len() - digits.len();
+    let value: u32 = digits.parse().ok()?;
+    if !matches!(value, 1 | 2 | 4 | 8 | 16 | 32 | 64) {
+        return None;
+    }
+    let base = 4.0 / value as f32;
+    Some((0..=dots).map(|d| base / 2f32.powi(d as i32)).sum())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notes_rests_and_dots_are_read_at_the_tempo() {
+        let song = parse("tempo 120\nA4 4 ; a beat\nrest 8\nBb3 8.\nC#5 1\n").unwrap();
+        let expected = [(440.0, 0.5), (0.0, 0.25), (233.08, 0.375), (554.37, 2.0)];
+        assert_eq!(song.len(), expected.len());
+        for (&(freq, secs), &(expected_freq, expected_secs)) in song.iter().zip(&expected) {
+            assert!((freq - expected_freq).abs() < 0.01, "{} for {}", freq, expected_freq);
+            assert!((secs - expected_secs).abs() < 1e-6, "{} for {}", secs, expected_secs);
+        }
+    }
+
+    #[test]
+    fn without_a_tempo_the_reference_tempo_is_used() {
+        let song = parse("C4 4").unwrap();
+        assert_eq!(song[0].1, 60.0 / REFERENCE_TEMPO.bpm);
+    }
+
+    #[test]
+    fn errors_point_at_the_token() {
+        let error = |input: &str| parse(input).unwrap_err().to_string();
+        assert_eq!(error("\nC4 4\n  H4 4"), "line 3, column 3: unknown note 'H4'");
+        assert_eq!(error("C4 3"), "line 1, column 4: invalid note value '3'");
+        assert_eq!(error("C4"), "line 1, column 1: expected a note value after 'C4'");
+        assert_eq!(error("C4 4 4"), "line 1, column 6: unexpected '4'");
+        assert_eq!(error("tempo 0"), "line 1, column 7: invalid tempo '0'");
+    }
+}
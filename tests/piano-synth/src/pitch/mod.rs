@@ -0,0 +1,417 @@
+//! Equal-tempered pitches and note naming (A4 = 440 Hz).
+
+use std::fmt;
+
+/// Pitch class names, indexed by MIDI note number modulo 12.
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// [`NOTE_NAMES`] with the black keys spelled as flats.
+const FLAT_NOTE_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "Gb", "G", "Ab", "A", "Bb", "B",
+];
+
+/// Tonics of the key signatures written with flats.
+const FLAT_MAJOR_TONICS: [&str; 7] = ["F", "Bb", "Eb", "Ab", "Db", "Gb", "Cb"];
+const FLAT_MINOR_TONICS: [&str; 7] = ["D", "G", "C", "F", "Bb", "Eb", "Ab"];
+
+/// Krumhansl-Kessler key profiles: how well each pitch class, from the
+/// tonic up, was heard to fit a major or minor key.
+const MAJOR_PROFILE: [f32; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f32; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+/// Tonics as key signatures usually spell them, by pitch class.
+const MAJOR_KEY_NAMES: [&str; 12] = [
+    "C", "Db", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+const MINOR_KEY_NAMES: [&str; 12] = [
+    "C", "C#", "D", "Eb", "E", "F", "F#", "G", "G#", "A", "Bb", "B",
+];
+
+/// How far (in cents) a frequency may sit from a semitone and still be named.
+pub(super) const NAME_TOLERANCE_CENTS: f32 = 30.0;
+
+/// A frequency within this many cents of a semitone counts as in tune, so
+/// rounding in a written frequency such as 659.25 Hz is no deviation.
+pub(super) const IN_TUNE_CENTS: f32 = 1.0;
+
+/// A note name plus octave, a raw frequency, or silence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Pitch {
+    C(i32),
+    CSharp(i32),
+    D(i32),
+    DSharp(i32),
+    E(i32),
+    F(i32),
+    FSharp(i32),
+    G(i32),
+    GSharp(i32),
+    A(i32),
+    ASharp(i32),
+    B(i32),
+    /// A frequency that is played exactly as given (e.g. a detuned note).
+    Hz(f32),
+    Rest,
+}
+
+impl Pitch {
+    /// Build the named pitch for a MIDI note number (60 = C4).
+    pub(super) fn from_midi(midi: i32) -> Self {
+        let octave = midi.div_euclid(12) - 1;
+        match midi.rem_euclid(12) {
+            0 => Pitch::C(octave),
+            1 => Pitch::CSharp(octave),
+            2 => Pitch::D(octave),
+            3 => Pitch::DSharp(octave),
+            4 => Pitch::E(octave),
+            5 => Pitch::F(octave),
+            6 => Pitch::FSharp(octave),
+            7 => Pitch::G(octave),
+            8 => Pitch::GSharp(octave),
+            9 => Pitch::A(octave),
+            10 => Pitch::ASharp(octave),
+            _ => Pitch::B(octave),
+        }
+    }
+
+    /// Snap a frequency to the nearest named pitch (or `Rest` below 1 Hz).
+    pub(super) fn from_freq(freq: f32) -> Self {
+        if freq < 1.0 {
+            return Pitch::Rest;
+        }
+        Pitch::from_midi(freq_to_midi(freq).round() as i32)
+    }
+
+    /// MIDI note number of a named pitch.
+    pub(super) fn midi(&self) -> Option<i32> {
+        let (class, octave) = match *self {
+            Pitch::C(o) => (0, o),
+            Pitch::CSharp(o) => (1, o),
+            Pitch::D(o) => (2, o),
+            Pitch::DSharp(o) => (3, o),
+            Pitch::E(o) => (4, o),
+            Pitch::F(o) => (5, o),
+            Pitch::FSharp(o) => (6, o),
+            Pitch::G(o) => (7, o),
+            Pitch::GSharp(o) => (8, o),
+            Pitch::A(o) => (9, o),
+            Pitch::ASharp(o) => (10, o),
+            Pitch::B(o) => (11, o),
+            Pitch::Hz(_) | Pitch::Rest => return None,
+        };
+        Some((octave + 1) * 12 + class)
+    }
+
+    /// Frequency in Hz; rests are 0.0.
+    pub(super) fn to_freq(self) -> f32 {
+        match self {
+            Pitch::Hz(freq) => freq,
+            Pitch::Rest => 0.0,
+            _ => midi_to_freq(self.midi().unwrap_or(69) as f32),
+        }
+    }
+}
+
+/// Lets a `(f32, f32)` table, such as an imported song or a variation's
+/// altered notes, stand in for pitches.
+impl From<f32> for Pitch {
+    fn from(freq: f32) -> Self {
+        if freq <= 0.0 { Pitch::Rest } else { Pitch::Hz(freq) }
+    }
+}
+
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self, self.midi()) {
+            (_, Some(midi)) => {
+                let name = NOTE_NAMES[midi.rem_euclid(12) as usize];
+                write!(f, "{}{}", name, midi.div_euclid(12) - 1)
+            }
+            (Pitch::Hz(freq), None) => write!(f, "{:.2} Hz", freq),
+            _ => write!(f, "Rest"),
+        }
+    }
+}
+
+/// Fractional MIDI note number of a frequency.
+pub(super) fn freq_to_midi(freq: f32) -> f32 {
+    12.0 * (freq / 440.0).log2() + 69.0
+}
+
+pub(super) fn midi_to_freq(midi: f32) -> f32 {
+    440.0 * 2f32.powf((midi - 69.0) / 12.0)
+}
+
+/// Name the nearest equal-tempered note, e.g. "F#3".
+///
+/// Frequencies further than `tolerance_cents` from any semitone are printed raw.
+pub(super) fn freq_to_name(freq: f32, tolerance_cents: f32) -> String {
+    if freq < 1.0 {
+        return "Rest".to_string();
+    }
+    let midi = freq_to_midi(freq);
+    if ((midi - midi.round()) * 100.0).abs() > tolerance_cents {
+        return format!("{:.2} Hz", freq);
+    }
+    Pitch::from_freq(freq).to_string()
+}
+
+/// Signed distance in cents from the nearest equal-tempered semitone, from
+/// -50 to +50; 0.0 for a rest.
+pub(super) fn cents_off(freq: f32) -> f32 {
+    if freq < 1.0 {
+        return 0.0;
+    }
+    let midi = freq_to_midi(freq);
+    (midi - midi.round()) * 100.0
+}
+
+/// The nearest note, spelled for `key` as [`spell`] does, and how far
+/// `freq` sits from it in whole cents, e.g. "A4 (+12c)" or "Bb3 (-3c)".
+/// However far out of tune, the note is still named; a rest is "Rest".
+pub(super) fn freq_to_name_cents(freq: f32, key: Option<&str>) -> String {
+    if freq < 1.0 {
+        return "Rest".to_string();
+    }
+    let name = spell(freq, f32::INFINITY, key);
+    format!("{} ({:+}c)", name, cents_off(freq).round() as i32)
+}
+
+/// A note name without its octave, 0 (C) ..= 11 (B).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) struct PitchClass(pub(super) u8);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(super) enum Mode {
+    Major,
+    Minor,
+}
+
+/// Share of the sounding time spent on each pitch class, C first, summing
+/// to 1.0; all zeros for a song of only rests. Each note counts towards its
+/// nearest semitone.
+pub(super) fn pitch_class_histogram(song: &[(f32, f32)]) -> [f32; 12] {
+    let mut histogram = [0.0; 12];
+    for &(freq, duration) in song.iter().filter(|&&(freq, _)| freq >= 1.0) {
+        let class = (freq_to_midi(freq).round() as i32).rem_euclid(12);
+        histogram[class as usize] += duration.max(0.0);
+    }
+    let total: f32 = histogram.iter().sum();
+    if total > 0.0 {
+        histogram.iter_mut().for_each(|weight| *weight /= total);
+    }
+    histogram
+}
+
+/// Whether black keys are named as sharps or flats.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) enum Spelling {
+    #[default]
+    Sharps,
+    Flats,
+}
+
+impl Spelling {
+    /// The spelling of a key signature such as "F major" or "Bb minor";
+    /// sharps for anything not recognised.
+    pub(super) fn for_key(key: &str) -> Self {
+        let mut words = key.split_whitespace();
+        let (Some(tonic), mode) = (words.next(), words.next()) else {
+            return Spelling::Sharps;
+        };
+        let flat_tonics = match mode.map(str::to_ascii_lowercase).as_deref() {
+            Some("minor") => &FLAT_MINOR_TONICS,
+            _ => &FLAT_MAJOR_TONICS,
+        };
+        if flat_tonics.contains(&tonic) { Spelling::Flats } else { Spelling::Sharps }
+    }
+}
+
+/// [`freq_to_name`], spelling black keys as the key signature `key` would
+/// (e.g. "Bb4" in F major); with no key they are sharps.
+pub(super) fn spell(freq: f32, tolerance_cents: f32, key: Option<&str>) -> String {
+    let name = freq_to_name(freq, tolerance_cents);
+    if key.map(Spelling::for_key) != Some(Spelling::Flats) {
+        return name;
+    }
+    match Pitch::from_freq(freq).midi() {
+        Some(midi) if name.contains('#') => {
+            let class = FLAT_NOTE_NAMES[midi.rem_euclid(12) as usize];
+            format!("{}{}", class, midi.div_euclid(12) - 1)
+        }
+        _ => name,
+    }
+}
+
+/// The key whose Krumhansl-Schmuckler profile best correlates with the
+/// song's [`pitch_class_histogram`]. Closely related keys share most of a
+/// scale, so a short tune can come out as one of them, such as its relative
+/// minor; a song of only rests is called C major.
+pub(super) fn estimate_key(song: &[(f32, f32)]) -> (PitchClass, Mode) {
+    let histogram = pitch_class_histogram(song);
+    let mut best = (PitchClass(0), Mode::Major);
+    let mut best_fit = f32::NEG_INFINITY;
+    for (mode, profile) in [(Mode::Major, &MAJOR_PROFILE), (Mode::Minor, &MINOR_PROFILE)] {
+        for tonic in 0..12 {
+            // The profile turned so its tonic lines up with `tonic`
+            let rotated: Vec<f32> =
+                (0..12).map(|class| profile[(class + 12 - tonic) % 12]).collect();
+            let fit = correlation(&histogram, &rotated);
+            if fit > best_fit {
+                best_fit = fit;
+                best = (PitchClass(tonic as u8), mode);
+            }
+        }
+    }
+    best
+}
+
+/// A key as `Song` writes it, e.g. "A minor" or "Bb major".
+pub(super) fn key_name((tonic, mode): (PitchClass, Mode)) -> String {
+    let class = tonic.0 as usize % 12;
+    match mode {
+        Mode::Major => format!("{} major", MAJOR_KEY_NAMES[class]),
+        Mode::Minor => format!("{} minor", MINOR_KEY_NAMES[class]),
+    }
+}
+
+// Pearson correlation of two equally long series; 0.0 if either is flat
+fn correlation(a: &[f32], b: &[f32]) -> f32 {
+    let mean = |x: &[f32]| x.iter().sum::<f32>() / x.len() as f32;
+    let (mean_a, mean_b) = (mean(a), mean(b));
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for (x, y) in a.iter().zip(b) {
+        cov += (x - mean_a) * (y - mean_b);
+        var_a += (x - mean_a).powi(2);
+        var_b += (y - mean_b).powi(2);
+    }
+    if var_a == 0.0 || var_b == 0.0 { 0.0 } else { cov / (var_a * var_b).sqrt() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One bar of the major scale on `tonic`, the tonic and fifth held longest
+    fn major_scale(tonic: i32) -> Vec<(f32, f32)> {
+        let steps = [(0, 2.0), (2, 0.5), (4, 1.0), (5, 0.5), (7, 1.5), (9, 0.5), (11, 0.5)];
+        let note = |&(step, beats): &(i32, f32)| (midi_to_freq((60 + tonic + step) as f32), beats);
+        steps.iter().map(note).collect()
+    }
+
+    // Song tables, one after another, as `(frequency, duration)` pairs
+    fn in_hz(tables: &[&[(Pitch, f32)]]) -> Vec<(f32, f32)> {
+        tables.concat().into_iter().map(|(pitch, beats)| (pitch.to_freq(), beats)).collect()
+    }
+
+    #[test]
+    fn every_semitone_is_named_in_two_octaves() {
+        for octave in [3, 4] {
+            for (class, name) in NOTE_NAMES.iter().enumerate() {
+                let freq = midi_to_freq(((octave + 1) * 12 + class as i32) as f32);
+                let expected = format!("{}{}", name, octave);
+                assert_eq!(freq_to_name(freq, NAME_TOLERANCE_CENTS), expected);
+                // A little out of tune is still the same note
+                assert_eq!(freq_to_name(freq * 1.01, NAME_TOLERANCE_CENTS), expected);
+            }
+        }
+        assert_eq!(freq_to_name(452.0, NAME_TOLERANCE_CENTS), "452.00 Hz");
+        assert_eq!(freq_to_name(0.0, NAME_TOLERANCE_CENTS), "Rest");
+    }
+
+    #[test]
+    fn black_keys_are_spelled_for_the_key() {
+        let b_flat = midi_to_freq(70.0);
+        assert_eq!(spell(b_flat, NAME_TOLERANCE_CENTS, None), "A#4");
+        assert_eq!(spell(b_flat, NAME_TOLERANCE_CENTS, Some("F major")), "Bb4");
+        assert_eq!(spell(b_flat, NAME_TOLERANCE_CENTS, Some("D minor")), "Bb4");
+        assert_eq!(spell(b_flat, NAME_TOLERANCE_CENTS, Some("E minor")), "A#4");
+        // White keys and raw frequencies are left as they are
+        assert_eq!(spell(midi_to_freq(60.0), NAME_TOLERANCE_CENTS, Some("Eb major")), "C4");
+        assert_eq!(spell(452.0, NAME_TOLERANCE_CENTS, Some("F major")), "452.00 Hz");
+    }
+
+    #[test]
+    fn a_scale_is_heard_in_its_own_key() {
+        for tonic in 0..12 {
+            let key = estimate_key(&major_scale(tonic));
+            assert_eq!(key, (PitchClass(tonic as u8), Mode::Major), "tonic {}", tonic);
+        }
+        // A minor: the same notes as C major, but resting on A and E
+        let a_minor: Vec<(f32, f32)> = [(57, 3.0), (60, 1.0), (64, 2.0), (59, 0.5), (56, 1.0)]
+            .iter()
+            .map(|&(midi, beats)| (midi_to_freq(midi as f32), beats))
+            .collect();
+        assert_eq!(estimate_key(&a_minor), (PitchClass(9), Mode::Minor));
+        assert_eq!(estimate_key(&[(0.0, 1.0)]), (PitchClass(0), Mode::Major));
+    }
+
+    #[test]
+    fn keys_are_named_as_songs_write_them() {
+        assert_eq!(key_name((PitchClass(0), Mode::Major)), "C major");
+        assert_eq!(key_name((PitchClass(10), Mode::Major)), "Bb major");
+        assert_eq!(key_name((PitchClass(6), Mode::Major)), "F# major");
+        assert_eq!(key_name((PitchClass(1), Mode::Minor)), "C# minor");
+        assert_eq!(key_name((PitchClass(3), Mode::Minor)), "Eb minor");
+        assert_eq!(key_name((PitchClass(9), Mode::Minor)), "A minor");
+    }
+
+    #[test]
+    fn ode_to_joy_leans_on_its_tonic_and_dominant() {
+        let score = in_hz(&[crate::ODE_TO_JOY, crate::ODE_TO_JOY_HARMONY]);
+        let histogram = pitch_class_histogram(&score);
+        assert!((histogram.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+        // C and G, the bass under the melody, outweigh every other class
+        let (tonic, dominant) = (histogram[0], histogram[7]);
+        for (class, &weight) in histogram.iter().enumerate().filter(|&(c, _)| c != 0 && c != 7) {
+            assert!(weight < tonic.min(dominant), "{}: {:?}", NOTE_NAMES[class], histogram);
+        }
+        assert_eq!(pitch_class_histogram(&[(0.0, 1.0)]), [0.0; 12]);
+    }
+
+    #[test]
+    fn the_songs_are_heard_in_their_written_keys() {
+        let fur_elise = in_hz(&[crate::FUR_ELISE, crate::FUR_ELISE_HARMONY]);
+        assert_eq!(key_name(estimate_key(&fur_elise)), "A minor");
+        assert_eq!(key_name(estimate_key(&in_hz(&[crate::FUR_ELISE]))), "A minor");
+        let ode_to_joy = in_hz(&[crate::ODE_TO_JOY, crate::ODE_TO_JOY_HARMONY]);
+        assert_eq!(key_name(estimate_key(&ode_to_joy)), "C major");
+        // The melody alone dwells on E, and comes out as the related E minor
+        assert_eq!(key_name(estimate_key(&in_hz(&[crate::ODE_TO_JOY]))), "E minor");
+    }
+
+    #[test]
+    fn an_out_of_tune_note_is_named_with_its_cents() {
+        let a4 = midi_to_freq(69.0);
+        assert_eq!(freq_to_name_cents(a4 * 2f32.powf(20.0 / 1200.0), None), "A4 (+20c)");
+        assert_eq!(freq_to_name_cents(a4 * 2f32.powf(-7.0 / 1200.0), None), "A4 (-7c)");
+        assert_eq!(freq_to_name_cents(a4, None), "A4 (+0c)");
+        // Named however far out, and spelled for a flat key
+        let b_flat = midi_to_freq(70.0) * 2f32.powf(-45.0 / 1200.0);
+        assert_eq!(freq_to_name_cents(b_flat, Some("F major")), "Bb4 (-45c)");
+        assert_eq!(freq_to_name_cents(b_flat, None), "A#4 (-45c)");
+        assert_eq!(freq_to_name_cents(0.0, Some("F major")), "Rest");
+    }
+
+    #[test]
+    fn every_named_pitch_survives_a_trip_through_its_frequency() {
+        for octave in 0..=8 {
+            for class in 0..12 {
+                let pitch = Pitch::from_midi((octave + 1) * 12 + class);
+                assert_eq!(Pitch::from_freq(pitch.to_freq()), pitch, "{}", pitch);
+                // A little out of tune snaps back to the same pitch
+                assert_eq!(Pitch::from_freq(pitch.to_freq() * 1.02), pitch, "{}", pitch);
+                assert_eq!(Pitch::from_freq(pitch.to_freq() / 1.02), pitch, "{}", pitch);
+            }
+        }
+        assert_eq!(Pitch::A(4).to_freq(), 440.0);
+        assert_eq!(Pitch::C(4), Pitch::from_freq(261.63));
+        assert_eq!(Pitch::from_freq(Pitch::Rest.to_freq()), Pitch::Rest);
+    }
+}
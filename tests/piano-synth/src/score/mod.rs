@@ -0,0 +1,194 @@
+//! Scores comparing measurements of a recording against the ground truth.
+
+use super::pitch::freq_to_midi;
+
+/// Samples per pitch estimate expected by [`pitch_accuracy`].
+pub(super) const PITCH_WINDOW: usize = 2048;
+
+/// A detected pitch this close to the written one counts as correct.
+const PITCH_TOLERANCE_CENTS: f32 = 50.0;
+
+/// Seconds at the start of each note left out while the attack settles.
+const ATTACK_SKIP: f32 = 0.03;
+
+/// Furthest (seconds) a detected onset may be from the expected one it matches.
+const ONSET_TOLERANCE: f32 = 0.1;
+
+/// Note boundaries in different tracks closer than this (seconds) are taken
+/// as one, so rounding in the running totals never leaves a sliver segment.
+const BOUNDARY_TOLERANCE: f32 = 1e-4;
+
+/// Fraction of notes whose detected pitch matches the ground truth.
+///
+/// `detected` holds one estimate per [`PITCH_WINDOW`] samples (0.0 for
+/// silence), as returned by `analyze::detect_pitches`, and `ground_truth` the
+/// `(start_sample, end_sample, freq)` slots of one track. Each note is judged
+/// by the median of the windows lying wholly inside it; a rest is correct
+/// when that median is silence.
+pub(super) fn pitch_accuracy(
+    detected: &[f32],
+    ground_truth: &[(usize, usize, f32)],
+    sample_rate: u32,
+) -> f32 {
+    if ground_truth.is_empty() {
+        return 1.0;
+    }
+    let skip = (ATTACK_SKIP * sample_rate as f32) as usize;
+
+    let correct = ground_truth
+        .iter()
+        .filter(|&&(start, end, freq)| {
+            let first = (start + skip).div_ceil(PITCH_WINDOW);
+            let last = end / PITCH_WINDOW;
+            let mut windows: Vec<f32> = detected.get(first..last).unwrap_or_default().to_vec();
+            // Notes shorter than a window fall back to the one at their middle
+            if windows.is_empty() {
+                windows.extend(detected.get((start + end) / 2 / PITCH_WINDOW));
+            }
+            let Some(heard) = median(&mut windows) else {
+                return false;
+            };
+            if freq <= 0.0 || heard <= 0.0 {
+                return freq <= 0.0 && heard <= 0.0;
+            }
+            let cents = 100.0 * (freq_to_midi(heard) - freq_to_midi(freq));
+            cents.abs() <= PITCH_TOLERANCE_CENTS
+        })
+        .count();
+    correct as f32 / ground_truth.len() as f32
+}
+
+/// Timing score in 0.0..=1.0 for onsets (seconds) detected in a recording.
+///
+/// Each expected onset is paired with the nearest unused detected onset
+/// within [`ONSET_TOLERANCE`]. The mean absolute error of the pairs, as a
+/// share of the mean inter-onset interval, is taken off a perfect score,
+/// which is then scaled by the matched share of all expected and spurious
+/// onsets, so missed and extra notes both count against it.
+pub(super) fn tempo_accuracy(detected_onsets: &[f32], expected_onsets: &[f32]) -> f32 {
+    if expected_onsets.is_empty() {
+        return if detected_onsets.is_empty() { 1.0 } else { 0.0 };
+    }
+
+    let mut used = vec![false; detected_onsets.len()];
+    let mut errors = vec![];
+    for &expected in expected_onsets {
+        let nearest = (0..detected_onsets.len())
+            .filter(|&i| !used[i])
+            .map(|i| (i, (detected_onsets[i] - expected).abs()))
+            .filter(|&(_, error)| error <= ONSET_TOLERANCE)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, error)) = nearest {
+            used[i] = true;
+            errors.push(error);
+        }
+    }
+    if errors.is_empty() {
+        return 0.0;
+    }
+
+    // With a single onset there is no interval, so fall back to the tolerance
+    let n = expected_onsets.len();
+    let span = expected_onsets[n - 1] - expected_onsets[0];
+    let interval = if n > 1 && span > 0.0 { span / (n - 1) as f32 } else { ONSET_TOLERANCE };
+    let mean_error = errors.iter().sum::<f32>() / errors.len() as f32;
+    let timing = (1.0 - mean_error / interval).max(0.0);
+
+    let matched = errors.len();
+    let spurious = detected_onsets.len() - matched;
+    timing * matched as f32 / (n + spurious) as f32
+}
+
+/// Collapse `(frequency, duration)` tracks into one timeline of `(start,
+/// end, frequencies)` segments, a new segment beginning wherever any track
+/// starts a note. Each lists every frequency sounding throughout it, in
+/// track order; rests add nothing, so a segment can be empty.
+pub(super) fn merge_tracks(tracks: &[&[(f32, f32)]]) -> Vec<(f32, f32, Vec<f32>)> {
+    let spans: Vec<(f32, f32, f32)> = tracks
+        .iter()
+        .flat_map(|track| {
+            let mut elapsed = 0.0;
+            track.iter().map(move |&(freq, duration)| {
+                let start = elapsed;
+                elapsed += duration;
+                (start, elapsed, freq)
+            })
+        })
+        .collect();
+    let mut bounds: Vec<f32> = spans.iter().flat_map(|&(start, end, _)| [start, end]).collect();
+    bounds.sort_by(f32::total_cmp);
+    bounds.dedup_by(|later, kept| *later - *kept < BOUNDARY_TOLERANCE);
+
+    bounds
+        .windows(2)
+        .map(|pair| {
+            // Judged at the middle, clear of any boundary's rounding
+            let middle = (pair[0] + pair[1]) / 2.0;
+            let freqs = spans
+                .iter()
+                .filter(|&&(start, end, freq)| freq >= 1.0 && start <= middle && middle < end)
+                .map(|&(.., freq)| freq)
+                .collect();
+            (pair[0], pair[1], freqs)
+        })
+        .collect()
+}
+
+fn median(values: &mut [f32]) -> Option<f32> {
+    values.sort_by(f32::total_cmp);
+    values.get(values.len() / 2).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn onsets_on_time_score_perfectly() {
+        let expected = [0.0, 0.5, 1.0, 1.5];
+        assert_eq!(tempo_accuracy(&expected, &expected), 1.0);
+        assert_eq!(tempo_accuracy(&[], &[]), 1.0);
+        assert_eq!(tempo_accuracy(&[0.3], &[]), 0.0);
+        assert_eq!(tempo_accuracy(&[], &expected), 0.0);
+    }
+
+    #[test]
+    fn late_missed_and_extra_onsets_all_cost() {
+        let expected = [0.0, 0.5, 1.0, 1.5];
+        // Every onset 50 ms late: a tenth of the half-second interval
+        let late = [0.05, 0.55, 1.05, 1.55];
+        assert!((tempo_accuracy(&late, &expected) - 0.9).abs() < 1e-5);
+        // One missed, then one extra: three quarters and four fifths matched
+        assert_eq!(tempo_accuracy(&[0.0, 0.5, 1.5], &expected), 0.75);
+        assert_eq!(tempo_accuracy(&[0.0, 0.5, 0.75, 1.0, 1.5], &expected), 0.8);
+        // Beyond the tolerance is no match at all
+        assert_eq!(tempo_accuracy(&[0.3, 0.8, 1.3, 1.8], &expected), 0.0);
+    }
+
+    #[test]
+    fn tracks_merge_into_what_sounds_when() {
+        let melody = [(440.0, 0.5), (494.0, 0.5), (0.0, 0.5)];
+        let bass = [(110.0, 1.0), (147.0, 0.5)];
+        let timeline = merge_tracks(&[&melody, &bass]);
+        let expected = [
+            (0.0, 0.5, vec![440.0, 110.0]),
+            (0.5, 1.0, vec![494.0, 110.0]),
+            (1.0, 1.5, vec![147.0]),
+        ];
+        assert_eq!(timeline, expected);
+        // Both resting leaves an empty segment rather than a gap
+        let merged = merge_tracks(&[&[(0.0, 0.25), (440.0, 0.25)], &[(0.0, 0.5)]]);
+        assert_eq!(merged, [(0.0, 0.25, vec![]), (0.25, 0.5, vec![440.0])]);
+    }
+
+    #[test]
+    fn each_note_is_judged_by_the_windows_inside_it() {
+        // Two notes of four windows each, the second heard a semitone sharp
+        let truth = [(0, 4 * PITCH_WINDOW, 440.0), (4 * PITCH_WINDOW, 8 * PITCH_WINDOW, 440.0)];
+        let detected = [440.0, 440.0, 441.0, 439.0, 466.2, 466.2, 466.2, 466.2];
+        assert_eq!(pitch_accuracy(&detected, &truth, 48_000), 0.5);
+        let rest = [(0, 4 * PITCH_WINDOW, 0.0)];
+        assert_eq!(pitch_accuracy(&[0.0; 4], &rest, 48_000), 1.0);
+        assert_eq!(pitch_accuracy(&[], &[], 48_000), 1.0);
+    }
+}
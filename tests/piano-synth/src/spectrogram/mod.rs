@@ -0,0 +1,215 @@
+//! Spectrogram pictures of a render, for checking pitch detection by eye.
+//!
+//! https://www.w3.org/TR/png/
+
+use super::analyze::to_mono;
+use fon::Audio;
+use fon::chan::Ch16;
+use std::{fs, io};
+
+/// Levels this far below the loudest bin are drawn black.
+const DYNAMIC_RANGE_DB: f32 = 90.0;
+
+/// Most bytes a stored (uncompressed) deflate block can hold.
+const STORED_BLOCK_LEN: usize = 65_535;
+
+/// Write the magnitude STFT of `audio`, mixed to mono, as an 8-bit grayscale
+/// PNG: one column per `fft_size`-sample Hann window, `hop` samples apart,
+/// and one row per frequency bin below Nyquist, lowest at the bottom.
+/// Brightness is in dB against the loudest bin.
+pub(super) fn write_png(
+    audio: &Audio<Ch16, 2>,
+    path: &str,
+    fft_size: usize,
+    hop: usize,
+) -> io::Result<()> {
+    if fft_size < 2 || !fft_size.is_power_of_two() || hop == 0 {
+        let message = format!("FFT size {} with hop {} can't be drawn", fft_size, hop);
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+    let samples = to_mono(audio);
+    if samples.len() < fft_size {
+        let message = format!("{} samples is shorter than one FFT window", samples.len());
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+    }
+
+    let width = (samples.len() - fft_size) / hop + 1;
+    let height = fft_size / 2;
+    let window: Vec<f32> = (0..fft_size)
+        .map(|i| 0.5 - 0.5 * (std::f32::consts::TAU * i as f32 / fft_size as f32).cos())
+        .collect();
+    // Power spectra, column by column
+    let columns: Vec<Vec<f32>> = (0..width)
+        .map(|column| {
+            let start = column * hop;
+            let mut re: Vec<f32> =
+                samples[start..start + fft_size].iter().zip(&window).map(|(s, w)| s * w).collect();
+            let mut im = vec![0.0; fft_size];
+            fft(&mut re, &mut im);
+            (0..height).map(|bin| re[bin] * re[bin] + im[bin] * im[bin]).collect()
+        })
+        .collect();
+
+    let loudest = columns.iter().flatten().copied().fold(0.0, f32::max);
+    let mut pixels = Vec::with_capacity((width + 1) * height);
+    for row in 0..height {
+        // Filter type 0 (none) before every scanline
+        pixels.push(0);
+        let bin = height - 1 - row;
+        pixels.extend(columns.iter().map(|column| {
+            let db = 10.0 * (column[bin] / loudest).log10();
+            (255.0 * (1.0 + db / DYNAMIC_RANGE_DB)).clamp(0.0, 255.0) as u8
+        }));
+    }
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    let mut header = Vec::new();
+    header.extend(&(width as u32).to_be_bytes());
+    header.extend(&(height as u32).to_be_bytes());
+    // Bit depth 8, colour type 0 (grayscale), deflate, no filter, no interlace
+    header.extend(&[8, 0, 0, 0, 0]);
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&pixels));
+    write_chunk(&mut png, b"IEND", &[]);
+    fs::write(path, png)
+}
+
+// In-place iterative radix-2 FFT; the length must be a power of two
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    // Bit-reversal permutation
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -std::f32::consts::TAU / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+// Length, type, data and CRC of one PNG chunk
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(&crc.to_be_bytes());
+}
+
+// A zlib stream of stored deflate blocks: valid, if no smaller than the data
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    // Deflate with a 32 KiB window, no preset dictionary
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK_LEN).peekable();
+    if blocks.peek().is_none() {
+        out.extend(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        // BFINAL on the last block, BTYPE 00 (stored)
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend(&len.to_le_bytes());
+        out.extend(&(!len).to_le_bytes());
+        out.extend(block);
+    }
+    out.extend(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65_521;
+        b = (b + a) % 65_521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+    use fon::Frame;
+
+    #[test]
+    fn checksums_match_their_references() {
+        // Every PNG ends with this IEND chunk CRC
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        let stored = zlib_stored(&[7; STORED_BLOCK_LEN + 1]);
+        // Two blocks with five-byte headers, after the zlib header and before the Adler-32
+        assert_eq!(stored.len(), 2 + 5 + STORED_BLOCK_LEN + 5 + 1 + 4);
+        assert_eq!((stored[2], stored[7 + STORED_BLOCK_LEN]), (0, 1));
+    }
+
+    #[test]
+    fn a_tone_lands_in_its_bin() {
+        // Bin 8 of a 64-point FFT, exactly
+        let n = 64;
+        let tone = |i: usize| (std::f32::consts::TAU * 8.0 * i as f32 / n as f32).cos();
+        let mut re: Vec<f32> = (0..n).map(tone).collect();
+        let mut im = vec![0.0; n];
+        fft(&mut re, &mut im);
+        let power: Vec<f32> = (0..n / 2).map(|bin| re[bin] * re[bin] + im[bin] * im[bin]).collect();
+        let loudest = (0..n / 2).max_by(|&a, &b| power[a].total_cmp(&power[b]));
+        assert_eq!(loudest, Some(8));
+        assert!((re[8] - 32.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn the_picture_is_a_column_per_hop_and_a_row_per_bin() {
+        let frames = (0..1000).map(|i| {
+            let s = Ch16::from((i as f32 * 0.3).sin() * 0.5);
+            Frame::<Ch16, 2>::new(s, s)
+        });
+        let audio = Audio::<Ch16, 2>::with_frames(8000, frames.collect::<Vec<_>>());
+        let dir = TempDir::new("spectrogram");
+        let path = &dir.path("spectrogram.png");
+        write_png(&audio, path, 256, 64).unwrap();
+        let png = fs::read(path).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        // IHDR: width then height, right after its length and type
+        let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+        let height = u32::from_be_bytes(png[20..24].try_into().unwrap());
+        assert_eq!((width, height), ((1000 - 256) / 64 + 1, 128));
+
+        assert!(write_png(&audio, path, 100, 64).is_err());
+        assert!(write_png(&audio, path, 2048, 64).is_err());
+    }
+}
@@ -0,0 +1,191 @@
+//! Sanity checks on `(frequency, duration)` song tables before they are
+//! rendered, so a typo in a table fails loudly instead of playing wrong.
+
+use super::pitch::Pitch;
+use std::fmt;
+
+/// Furthest (seconds) the tracks of one score may drift apart in length.
+const ALIGN_TOLERANCE: f32 = 1e-3;
+
+/// Furthest (seconds) a measure may be from the time signature's length.
+const MEASURE_TOLERANCE: f32 = 1e-3;
+
+/// The notes of one bar of a track, as `(pitch, duration)` pairs; an empty
+/// bar is a full bar's rest.
+pub(super) type Measure<'a> = &'a [(Pitch, f32)];
+
+/// A problem found in a song table; indices are 0-based.
+#[derive(Debug, PartialEq)]
+pub(super) enum SongError {
+    /// A note or rest (frequency 0.0) that takes no time or runs backwards.
+    NonPositiveDuration { index: usize, duration: f32, rest: bool },
+    NanFrequency { index: usize },
+    /// An accompaniment whose total length differs from the melody's.
+    LengthMismatch { expected: f32, found: f32 },
+    /// A bar whose notes don't add up to the time signature.
+    MeasureLength { measure: usize, expected: f32, found: f32 },
+}
+
+impl fmt::Display for SongError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SongError::NonPositiveDuration { index, duration, rest } => {
+                let kind = if rest { "rest" } else { "note" };
+                write!(f, "{} {} lasts {} s", kind, index, duration)
+            }
+            SongError::NanFrequency { index } => write!(f, "note {} has a NaN frequency", index),
+            SongError::LengthMismatch { expected, found } => {
+                write!(f, "lasts {:.3} s but the melody lasts {:.3} s", found, expected)
+            }
+            SongError::MeasureLength { measure, expected, found } => {
+                let side = if found < expected { "short" } else { "long" };
+                let by = (expected - found).abs();
+                write!(f, "measure {} lasts {:.3} s, {:.3} s too {}", measure, found, by, side)
+            }
+        }
+    }
+}
+
+/// Check every entry of one track, collecting all problems rather than
+/// stopping at the first. A NaN frequency is reported as such, not as a rest.
+pub(super) fn validate_song(song: &[(f32, f32)]) -> Result<(), Vec<SongError>> {
+    let mut errors = Vec::new();
+    for (index, &(freq, duration)) in song.iter().enumerate() {
+        if freq.is_nan() {
+            errors.push(SongError::NanFrequency { index });
+        }
+        // A NaN duration is no more playable than a negative one
+        if duration.is_nan() || duration <= 0.0 {
+            let rest = freq <= 0.0;
+            errors.push(SongError::NonPositiveDuration { index, duration, rest });
+        }
+    }
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// Check the tracks of one score, which should all end together: the
+/// melody (track 0) sets the length the others must match. Errors in a
+/// single track are returned alongside the track's index.
+pub(super) fn validate_tracks(tracks: &[&[(f32, f32)]]) -> Result<(), Vec<(usize, SongError)>> {
+    let mut errors = Vec::new();
+    for (track, song) in tracks.iter().enumerate() {
+        if let Err(found) = validate_song(song) {
+            errors.extend(found.into_iter().map(|e| (track, e)));
+        }
+    }
+
+    let length = |song: &[(f32, f32)]| song.iter().map(|&(_, d)| d).sum::<f32>();
+    if let Some((melody, rest)) = tracks.split_first() {
+        let expected = length(melody);
+        for (i, song) in rest.iter().enumerate() {
+            let found = length(song);
+            if (found - expected).abs() > ALIGN_TOLERANCE {
+                errors.push((i + 1, SongError::LengthMismatch { expected, found }));
+            }
+        }
+    }
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// Check that every bar of a track fills `measure_secs`. After an upbeat of
+/// `pickup` seconds the first bar holds just the upbeat, and the last may
+/// be short by as much, completing it.
+pub(super) fn validate_measures(
+    measures: &[Measure],
+    measure_secs: f32,
+    pickup: f32,
+) -> Result<(), Vec<SongError>> {
+    let errors: Vec<SongError> = measures
+        .iter()
+        .enumerate()
+        .filter_map(|(measure, notes)| {
+            let found = notes.iter().map(|&(_, d)| d).sum::<f32>();
+            let expected = expected_length(measure, measures.len(), measure_secs, pickup);
+            let fits = |length: f32| (found - length).abs() <= MEASURE_TOLERANCE;
+            // A last bar may also be whole, leaving the upbeat incomplete
+            let last_after_upbeat = pickup > 0.0 && measure + 1 == measures.len();
+            if notes.is_empty() || fits(expected) || last_after_upbeat && fits(measure_secs) {
+                None
+            } else {
+                Some(SongError::MeasureLength { measure, expected, found })
+            }
+        })
+        .collect();
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+/// One track from its bars, each empty bar filled with a rest as long as
+/// the bar should be.
+pub(super) fn join_measures(
+    measures: &[Measure],
+    measure_secs: f32,
+    pickup: f32,
+) -> Vec<(Pitch, f32)> {
+    let mut track = Vec::new();
+    for (measure, notes) in measures.iter().enumerate() {
+        if notes.is_empty() {
+            let length = expected_length(measure, measures.len(), measure_secs, pickup);
+            track.push((Pitch::Rest, length));
+        } else {
+            track.extend_from_slice(notes);
+        }
+    }
+    track
+}
+
+// Length bar `measure` of `count` should have: the upbeat, the rest of the
+// bar it opened, or a whole bar
+fn expected_length(measure: usize, count: usize, measure_secs: f32, pickup: f32) -> f32 {
+    if pickup <= 0.0 {
+        return measure_secs;
+    }
+    if measure == 0 {
+        pickup
+    } else if measure + 1 == count {
+        measure_secs - pickup
+    } else {
+        measure_secs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_bad_entry_is_reported() {
+        assert_eq!(validate_song(&[(440.0, 0.5), (0.0, 0.25)]), Ok(()));
+        let errors = validate_song(&[(440.0, 0.0), (f32::NAN, 0.5), (0.0, -0.25)]).unwrap_err();
+        assert_eq!(errors.len(), 3);
+        let zero = SongError::NonPositiveDuration { index: 0, duration: 0.0, rest: false };
+        assert_eq!(errors[0], zero);
+        assert_eq!(errors[1], SongError::NanFrequency { index: 1 });
+        assert_eq!(errors[2].to_string(), "rest 2 lasts -0.25 s");
+    }
+
+    #[test]
+    fn an_accompaniment_must_end_with_the_melody() {
+        let melody = [(440.0, 0.5), (494.0, 0.5)];
+        assert_eq!(validate_tracks(&[&melody, &[(220.0, 1.0)]]), Ok(()));
+        let errors = validate_tracks(&[&melody, &[(220.0, 1.0)], &[(110.0, 0.75)]]).unwrap_err();
+        assert_eq!(errors, [(2, SongError::LengthMismatch { expected: 1.0, found: 0.75 })]);
+        assert_eq!(errors[0].1.to_string(), "lasts 0.750 s but the melody lasts 1.000 s");
+    }
+
+    #[test]
+    fn bars_fill_the_time_signature_after_an_upbeat() {
+        let (bar, upbeat) = (1.5, 0.5);
+        let (a4, rest) = (Pitch::A(4), Pitch::Rest);
+        let measures: [Measure; 4] = [&[(a4, 0.5)], &[(a4, 1.0), (rest, 0.5)], &[], &[(a4, 1.0)]];
+        assert_eq!(validate_measures(&measures, bar, upbeat), Ok(()));
+        // An empty bar becomes a rest of the length it should have
+        let joined = join_measures(&measures, bar, upbeat);
+        assert_eq!(joined[3], (rest, 1.5));
+        assert_eq!(joined.iter().map(|&(_, d)| d).sum::<f32>(), 4.5);
+
+        let short: [Measure; 2] = [&[(a4, 1.5)], &[(a4, 1.25)]];
+        let errors = validate_measures(&short, bar, 0.0).unwrap_err();
+        assert_eq!(errors, [SongError::MeasureLength { measure: 1, expected: 1.5, found: 1.25 }]);
+        assert_eq!(errors[0].to_string(), "measure 1 lasts 1.250 s, 0.250 s too short");
+    }
+}
@@ -0,0 +1,2133 @@
+//! The ways a performance can differ from the score, each rendered as a
+//! test file of its own, and the checks each file passes before it is listed.
+
+use fon::Audio;
+use fon::chan::{Ch16, Ch32};
+use fon::pos::{Left, Right};
+use std::io;
+
+use super::{
+    Accent, Articulation, Balance, BendTrack, DurationError, E, EVEN_BALANCE, H, HarmonicProfile,
+    Levels, Note, NoteInfo, POLYPHONIC_PEAK, PauseInfo, PhaseScatter, Pitch, Q, REFERENCE_TEMPO,
+    RenderOptions, SILENT_RMS_DBFS, Selection, Song, Tempo, Tremolo, VOICE_GAIN, VariationInfo,
+    VelocityCurve, Vibrato,
+};
+use super::{analyze, fx, midi, musicxml, score, wav};
+use super::{at_path, generate, generate_into, generate_stems, get_notes, get_onsets, invalid_song};
+use super::{measure_secs, to_notes, validate_measures, validate_tracks};
+use super::fx::{Noise, Reverb};
+use super::pitch::{IN_TUNE_CENTS, NAME_TOLERANCE_CENTS, cents_off, estimate_key, freq_to_midi};
+use super::pitch::{freq_to_name_cents, key_name, spell};
+use super::rng::Rng;
+use super::wav::BitDepth;
+
+// The seed a randomized variation draws from: the run's `seed` combined
+// with the variation's own constant, so that one seed fixes every variation
+// yet no two share a sequence. Seed 0 leaves each constant as it is.
+fn sub_seed(seed: u64, variation_seed: u64) -> u64 {
+    seed ^ variation_seed
+}
+
+/// Seed for the notes picked by the wrong-notes variation.
+const WRONG_NOTES_SEED: u64 = 0x5EED_0012;
+
+/// Seed and relative standard deviation for the timing-jitter variation.
+const JITTER_SEED: u64 = 0x5EED_0014;
+const JITTER_SD: f32 = 0.08;
+
+/// Seed and standard deviations (relative duration, velocity) for the
+/// humanized variation: a good take, not quite as steady as a machine.
+const HUMANIZE_SEED: u64 = 0x5EED_0053;
+const HUMANIZE_TIMING_SD: f32 = 0.02;
+const HUMANIZE_VELOCITY_SD: f32 = 0.1;
+
+/// Seed and largest offset (cents) for the poorly-tuned piano variation.
+const DETUNE_SEED: u64 = 0x5EED_0024;
+const DETUNE_MAX_CENTS: f32 = 15.0;
+
+/// Mistuning at which the detuned variation's pitch accuracy reaches zero;
+/// a quarter tone is as far as a note can drift before it reads as another.
+const DETUNE_ZERO_ACCURACY_CENTS: f32 = 50.0;
+
+/// Cutoff for the muffled variation: above most fundamentals, below most
+/// of their harmonics, like a piano heard through a wall.
+const MUFFLED_CUTOFF_HZ: f32 = 1_000.0;
+
+/// Room hiss for the noisy variation.
+const ROOM_NOISE: Noise = Noise { snr_db: 20.0, seed: 0x5EED_0036 };
+
+/// A medium room, for the reverb variation.
+const ROOM_REVERB: Reverb = Reverb { decay: 1.2, mix: 0.25 };
+
+/// How far the melody trails the accompaniment in the one-hand-late
+/// variation: a slight lag, unlike the harmony lags, that still spreads each
+/// shared onset well apart.
+const ONE_HAND_LATE_SECS: f32 = 0.06;
+
+/// Melody notes struck twice, each strike taking half the written duration.
+const STUTTER_POSITIONS: [usize; 2] = [5, 12];
+
+/// Softest and loudest melody velocities of the crescendo and decrescendo
+/// variations.
+const HAIRPIN_RANGE: (f32, f32) = (0.3, 1.0);
+
+/// Velocity multiplier on notes starting a measure, for the downbeat-accent
+/// variation.
+const DOWNBEAT_ACCENT_GAIN: f32 = 1.3;
+
+/// Melody notes sounding for a multiple of their written length, for the
+/// wrong-lengths variation: held on half again, over the next note's start,
+/// or let go halfway, leaving a gap before it.
+const LENGTH_ERRORS: [(usize, f32); 4] = [(3, 1.5), (7, 0.5), (11, 1.5), (15, 0.5)];
+
+/// Long-to-short ratio of swung eighth pairs, for the swing variation.
+const SWING_RATIO: f32 = 2.0;
+
+/// Melody vibrato for the vibrato variation, kept inside the pitch tolerance.
+const MELODY_VIBRATO: Vibrato = Vibrato { rate: 5.5, depth_cents: 20.0 };
+
+/// Melody tremolo for the tremolo variation: a loudness swing that should
+/// not be mistaken for new notes.
+const MELODY_TREMOLO: Tremolo = Tremolo { rate: 6.0, depth: 0.5 };
+
+/// Phase offsets for the random-phase variation: enough to vary each
+/// attack slightly without changing how any note sounds once it settles.
+const PHASE_SCATTER: PhaseScatter = PhaseScatter { spread: 0.1, seed: 0x5EED_0067 };
+
+/// Accompaniment played 6 dB under the melody, for the quiet-harmony
+/// variation.
+const QUIET_HARMONY: Balance = Balance { harmony: VOICE_GAIN * 0.5, ..EVEN_BALANCE };
+
+/// Seconds each melody note takes to slide in from the previous pitch, for
+/// the glide variation.
+const GLIDE_TIME: f32 = 0.08;
+
+/// Seed and range (seconds) of the melody's attack times for the
+/// uneven-touch variation, from crisp to noticeably slow to speak.
+const UNEVEN_TOUCH_SEED: u64 = 0x5EED_0063;
+const UNEVEN_ATTACK: (f32, f32) = (0.005, 0.06);
+
+/// Melody notes the ritardando slows over, and how much longer the very end
+/// is drawn out than the written tempo.
+const RITARDANDO_NOTES: usize = 6;
+const RITARDANDO_STRETCH: f32 = 1.6;
+
+/// How long the last moment of the accelerando lasts against the written
+/// tempo; the player rushes steadily more from the first note.
+const ACCELERANDO_STRETCH: f32 = 0.7;
+
+/// Semitones the whole piano is bent by in each quarter of the song, for the
+/// sharp-drift variation: a synth or tape going sharp, within the pitch
+/// tolerance throughout.
+const SHARP_DRIFT: [f32; 4] = [0.0, 0.1, 0.2, 0.3];
+
+/// Passes, and seconds of silence between them, for the repeated variation.
+const REPEAT_PASSES: usize = 3;
+const REPEAT_GAP_SECS: f32 = 1.0;
+
+/// Seconds the player freezes for in the hesitation variation, and the share
+/// of the melody played before it (moved on to where every part can stop).
+const HESITATION_SECS: f32 = 0.5;
+const HESITATION_FRACTION: f32 = 0.5;
+
+/// Share of the melody played before stopping, for the stop-early variation.
+const STOP_EARLY_FRACTION: f32 = 0.75;
+
+/// Variation names accepted by `--variation`, in generation order.
+pub(super) const VARIATIONS: &[&str] = &[
+    "original",
+    "fast",
+    "slow",
+    "missed_melody",
+    "missed_harmony",
+    "missed_both",
+    "incorrect_melody",
+    "incorrect_harmony",
+    "incorrect_both",
+    "sync_slight_lag",
+    "sync_major_lag",
+    "extra_melody_simul",
+    "extra_melody_before",
+    "extra_melody_after",
+    "extra_harmony_simul",
+    "extra_harmony_before",
+    "extra_harmony_after",
+    "transposed",
+    "flat_dynamics",
+    "wrong_notes",
+    "extra_melody_inserted",
+    "jitter",
+    "reverb",
+    "flute_organ",
+    "stops_early",
+    "stutter",
+    "staccato",
+    "legato",
+    "glide",
+    "detuned",
+    "crescendo",
+    "decrescendo",
+    "click",
+    "muffled",
+    "noisy",
+    "swing",
+    "vibrato",
+    "tremolo",
+    "repeated",
+    "humanized",
+    "ritardando",
+    "accelerando",
+    "uneven_touch",
+    "sharp_drift",
+    "random_phase",
+    "hesitation",
+    "double_octaves",
+    "wrong_rhythm",
+    "quiet_harmony",
+    "downbeat_accent",
+    "synth",
+    "one_hand_late",
+    "wrong_lengths",
+    "pedal",
+];
+
+// What every variation of one song shares, and the variations written so far
+struct Batch<'a> {
+    /// [`Song::title`], which every filename starts with.
+    base_name: &'a str,
+    /// The original's file, which a variation is measured against unless it
+    /// is its own ideal.
+    ideal_filename: String,
+    /// Notes the original plays, of which a missed or extra note is a share.
+    playable_notes: usize,
+    /// Where the files go, or `None` to keep each rendering in `kept` instead.
+    dir: Option<&'a str>,
+    infos: Vec<VariationInfo>,
+    kept: Vec<Audio<Ch32, 2>>,
+    /// Most variations last as long as the original, so the written ones
+    /// share one buffer.
+    buffer: Audio<Ch32, 2>,
+}
+
+impl Batch<'_> {
+    // Render `tracks` at `tempo` to `{base_name}_{name}.wav` and list it with
+    // the expected scores in `info`, and the notes and onsets as played
+    fn push<N: Clone + Into<Note>>(
+        &mut self,
+        name: &str,
+        tracks: &[&[N]],
+        tempo: Tempo,
+        options: RenderOptions,
+        info: VariationInfo,
+    ) -> io::Result<&mut VariationInfo> {
+        let filename = format!("{}_{}.wav", self.base_name, name);
+        self.render(&filename, tracks, tempo, options)?;
+        self.infos.push(VariationInfo {
+            filename,
+            ideal_filename: self.ideal_filename.clone(),
+            notes: get_notes(tracks, tempo),
+            onsets_samples: get_onsets(tracks, tempo, options.sample_rate),
+            ..info
+        });
+        Ok(self.infos.last_mut().expect("A variation was just listed"))
+    }
+
+    // Render `tracks` at `tempo` to `filename`, or keep them if nothing is
+    // being written
+    fn render<N: Clone + Into<Note>>(
+        &mut self,
+        filename: &str,
+        tracks: &[&[N]],
+        tempo: Tempo,
+        options: RenderOptions,
+    ) -> io::Result<()> {
+        if self.dir.is_some() {
+            return generate_into(&mut self.buffer, filename, tracks.to_vec(), tempo, options);
+        }
+        self.kept.push(super::render(tracks.to_vec(), tempo.speed_mult(), options)?);
+        Ok(())
+    }
+
+    // Pitch accuracy of a take with `wrong` of the original's notes missed,
+    // mistaken or played on top of
+    fn played_share(&self, wrong: usize) -> f32 {
+        (self.playable_notes as f32 - wrong as f32) / self.playable_notes as f32
+    }
+}
+
+// An entry with just its expected scores, for `Batch::push` to fill in
+fn scored(tempo_accuracy: f32, pitch_accuracy: f32) -> VariationInfo {
+    VariationInfo { tempo_accuracy, pitch_accuracy, ..Default::default() }
+}
+
+/// Render every variation of `song` that `selection` wants into `dir`, and
+/// list each with its expected and measured accuracy.
+pub(super) fn generate_variations(
+    song: &Song,
+    selection: &Selection,
+    seed: u64,
+    trim_dbfs: Option<f32>,
+    dir: &str,
+) -> io::Result<Vec<VariationInfo>> {
+    let mut variations = play(song, selection, seed, Some(dir))?.infos;
+
+    let tracks: Vec<&[(f32, f32)]> = song.tracks.iter().map(|t| t.as_slice()).collect();
+    let tempo = selection.tempo_bpm().map_or(REFERENCE_TEMPO, |bpm| Tempo { bpm });
+    let rate = selection.sample_rate();
+    // Every track counts, so a bass line can settle a melody's mode
+    let score: Vec<(f32, f32)> = tracks.concat();
+    let estimated_key = key_name(estimate_key(&score));
+    // The original as written, which every variation but the ones that are
+    // their own ideal is measured against
+    let original_onsets = get_onsets(&tracks, tempo, rate);
+    for info in &mut variations {
+        info.title = song.title.clone();
+        info.default_bpm = song.default_bpm / tempo.speed_mult();
+        // A song written without a key is spelled in the one it sounds in
+        info.key = song.key.clone().or_else(|| Some(estimated_key.clone()));
+        info.estimated_key = estimated_key.clone();
+        // Measured from the file, so the levels include the 16-bit rounding
+        let lines = sounding_lines(&info.notes);
+        let lines: Vec<&[(f32, f32)]> = lines.iter().map(Vec::as_slice).collect();
+        info.timeline = score::merge_tracks(&lines);
+        let path = format!("{}/{}", dir, info.filename);
+        let mut written = wav::read::<Ch16>(&path).map_err(at_path(&path))?;
+        // Before any trimming, which would move this file but not the ideal
+        let ideal = if info.ideal_filename == info.filename {
+            &info.onsets_samples
+        } else {
+            &original_onsets
+        };
+        (info.measured_tempo_accuracy, info.measured_pitch_accuracy) =
+            measured_accuracy(&written, ideal);
+        if let Some(threshold) = trim_dbfs {
+            written = trim_written(info, &written, threshold, selection.bit_depth(), dir)?;
+        }
+        let levels = analyze::levels(&written);
+        // Distorted but still usable, so only worth a warning
+        let clipped = analyze::count_clipped_samples(&written);
+        if clipped > 0 {
+            eprintln!("Warning: {}: {} samples clipped", path, clipped);
+        }
+        check_written(info, &written, levels).map_err(at_path(&path))?;
+        info.levels = Some(levels);
+        // Named once the key is known, so a flat key reads "Bb" not "A#"
+        for note in info.notes.iter_mut().flatten() {
+            let key = info.key.as_deref();
+            note.name = if cents_off(note.freq).abs() < IN_TUNE_CENTS {
+                spell(note.freq, NAME_TOLERANCE_CENTS, key)
+            } else {
+                freq_to_name_cents(note.freq, key)
+            };
+        }
+    }
+    Ok(variations)
+}
+
+/// Render the first variation of `song` that `selection` wants without
+/// writing anything, with the name its file would have; `None` if the
+/// variation does not apply to the song.
+pub(super) fn render_variation(
+    song: &Song,
+    selection: &Selection,
+    seed: u64,
+) -> io::Result<Option<(String, Audio<Ch32, 2>)>> {
+    let batch = play(song, selection, seed, None)?;
+    let filenames = batch.infos.into_iter().map(|info| info.filename);
+    Ok(filenames.zip(batch.kept).next())
+}
+
+// Render every variation of `song` that `selection` wants into `dir`, or
+// keep them in memory with no `dir`, and list each with its expected scores
+fn play<'a>(
+    song: &'a Song,
+    selection: &Selection,
+    seed: u64,
+    dir: Option<&'a str>,
+) -> io::Result<Batch<'a>> {
+    let base_name = song.title.as_str();
+    let tracks: Vec<&[(f32, f32)]> = song.tracks.iter().map(|t| t.as_slice()).collect();
+    // Every variation plays relative to this, the written tempo unless the
+    // plan asks for another
+    let tempo = selection.tempo_bpm().map_or(REFERENCE_TEMPO, |bpm| Tempo { bpm });
+    // Only the written score must line up; some variations misalign it on purpose
+    let mut errors = validate_tracks(&tracks).err().unwrap_or_default();
+    let measure_secs = measure_secs(song.time_signature);
+    for (track, measures) in song.measures.iter().enumerate() {
+        if let Err(found) = validate_measures(measures, measure_secs, song.pickup) {
+            errors.extend(found.into_iter().map(|e| (track, e)));
+        }
+    }
+    if !errors.is_empty() {
+        let error = invalid_song(&errors);
+        return Err(io::Error::new(error.kind(), format!("{}: {}", base_name, error)));
+    }
+
+    let count_playable_notes = |tracks: &[&[(f32, f32)]]| -> usize {
+        tracks
+            .iter()
+            .map(|t| t.iter().filter(|(f, _)| *f > 0.0).count())
+            .sum()
+    };
+
+    // Several voices can sum past full scale, so polyphonic mixes are normalized
+    let rate = selection.sample_rate();
+    let render = RenderOptions {
+        sample_rate: rate,
+        bit_depth: selection.bit_depth(),
+        velocity_curve: VelocityCurve::named(selection.velocity_curve()).unwrap_or_default(),
+        out_dir: dir.unwrap_or_default(),
+        normalize_peak: (tracks.len() > 1).then_some(POLYPHONIC_PEAK),
+        ..Default::default()
+    };
+
+    let original_filename = format!("{}.wav", base_name);
+    let mut batch = Batch {
+        base_name,
+        ideal_filename: original_filename.clone(),
+        playable_notes: count_playable_notes(&tracks),
+        dir,
+        infos: Vec::new(),
+        kept: Vec::new(),
+        buffer: Audio::with_silence(selection.sample_rate(), 0),
+    };
+
+    // 1. Original
+    if selection.wants("original") {
+        let (stems, copies, mono_copy) = match dir {
+            Some(dir) => write_original(song, &tracks, tempo, render, dir)?,
+            // Just the mix, for streaming
+            None => {
+                batch.render(&original_filename, &tracks, tempo, render)?;
+                Default::default()
+            }
+        };
+        batch.infos.push(VariationInfo {
+            filename: original_filename.clone(),
+            ideal_filename: original_filename.clone(),
+            notes: get_notes(&tracks, tempo),
+            onsets_samples: get_onsets(&tracks, tempo, rate),
+            stems,
+            copies,
+            mono_copy,
+            ..scored(1.0, 1.0)
+        });
+    }
+
+    // 2. Fast (1.15x speed)
+    if selection.wants("fast") {
+        let tempo_fast = Tempo { bpm: tempo.bpm * 1.15 };
+        batch.push("fast", &tracks, tempo_fast, render, scored(0.85, 1.0))?;
+    }
+
+    // 3. Slow (0.9x speed)
+    if selection.wants("slow") {
+        let tempo_slow = Tempo { bpm: tempo.bpm * 0.90 };
+        batch.push("slow", &tracks, tempo_slow, render, scored(0.90, 1.0))?;
+    }
+
+    // 4. Missed Notes (Melody only)
+    if selection.wants("missed_melody") && !tracks.is_empty() {
+        let mut melody = tracks[0].to_vec();
+        let mut missed_count = 0;
+
+        if melody.len() > 12 {
+            if let Some(note) = melody.get_mut(4)
+                && note.0 > 0.0
+            {
+                note.0 = 0.0;
+                missed_count += 1;
+            }
+            if let Some(note) = melody.get_mut(11)
+                && note.0 > 0.0
+            {
+                note.0 = 0.0;
+                missed_count += 1;
+            }
+        }
+
+        let mut missed_tracks = vec![melody.as_slice()];
+        missed_tracks.extend_from_slice(&tracks[1..]);
+
+        let info = scored(1.0, batch.played_share(missed_count));
+        batch.push("missed_melody", &missed_tracks, tempo, render, info)?;
+    }
+
+    // 5. Missed Notes (Harmony only)
+    if selection.wants("missed_harmony") && tracks.len() >= 2 {
+        let mut harmony = tracks[1].to_vec();
+        let mut missed_count = 0;
+
+        // Try to remove a couple of notes
+        let indices_to_remove = [1, 3, 5];
+        for &i in &indices_to_remove {
+            if i < harmony.len() && harmony[i].0 > 0.0 {
+                harmony[i].0 = 0.0;
+                missed_count += 1;
+            }
+        }
+
+        let mut missed_tracks = vec![tracks[0], harmony.as_slice()];
+        missed_tracks.extend_from_slice(&tracks[2..]);
+
+        let info = scored(1.0, batch.played_share(missed_count));
+        batch.push("missed_harmony", &missed_tracks, tempo, render, info)?;
+    }
+
+    // 6. Missed Notes (Both)
+    if selection.wants("missed_both") && tracks.len() >= 2 {
+        let mut melody = tracks[0].to_vec();
+        let mut harmony = tracks[1].to_vec();
+        let mut missed_count = 0;
+
+        // Melody misses
+        if melody.len() > 12
+            && let Some(note) = melody.get_mut(4)
+            && note.0 > 0.0
+        {
+            note.0 = 0.0;
+            missed_count += 1;
+        }
+
+        // Harmony misses
+        if harmony.len() > 1 && harmony[1].0 > 0.0 {
+            harmony[1].0 = 0.0;
+            missed_count += 1;
+        }
+
+        let mut missed_tracks = vec![melody.as_slice(), harmony.as_slice()];
+        missed_tracks.extend_from_slice(&tracks[2..]);
+
+        let info = scored(1.0, batch.played_share(missed_count));
+        batch.push("missed_both", &missed_tracks, tempo, render, info)?;
+    }
+
+    // 7. Incorrect Notes (Melody)
+    if selection.wants("incorrect_melody") && !tracks.is_empty() {
+        let mut melody = tracks[0].to_vec();
+        let mut incorrect_count = 0;
+
+        if melody.len() > 10 {
+            if let Some(note) = melody.get_mut(5)
+                && note.0 > 0.0
+            {
+                note.0 += 20.0; // Detune
+                incorrect_count += 1;
+            }
+            if let Some(note) = melody.get_mut(10)
+                && note.0 > 0.0
+            {
+                note.0 -= 20.0; // Detune
+                incorrect_count += 1;
+            }
+        }
+
+        let mut mod_tracks = vec![melody.as_slice()];
+        mod_tracks.extend_from_slice(&tracks[1..]);
+
+        let info = scored(1.0, batch.played_share(incorrect_count));
+        batch.push("incorrect_melody", &mod_tracks, tempo, render, info)?;
+    }
+
+    // 8. Incorrect Notes (Harmony)
+    if selection.wants("incorrect_harmony") && tracks.len() >= 2 {
+        let mut harmony = tracks[1].to_vec();
+        let mut incorrect_count = 0;
+
+        // Modify a couple of notes
+        let indices = [0, 2];
+        for &i in &indices {
+            if i < harmony.len() && harmony[i].0 > 0.0 {
+                harmony[i].0 += 30.0;
+                incorrect_count += 1;
+            }
+        }
+
+        let mut mod_tracks = vec![tracks[0], harmony.as_slice()];
+        mod_tracks.extend_from_slice(&tracks[2..]);
+
+        let info = scored(1.0, batch.played_share(incorrect_count));
+        batch.push("incorrect_harmony", &mod_tracks, tempo, render, info)?;
+    }
+
+    // 9. Incorrect Notes (Both)
+    if selection.wants("incorrect_both") && tracks.len() >= 2 {
+        let mut melody = tracks[0].to_vec();
+        let mut harmony = tracks[1].to_vec();
+        let mut incorrect_count = 0;
+
+        if melody.len() > 8 && melody[8].0 > 0.0 {
+            melody[8].0 += 20.0;
+            incorrect_count += 1;
+        }
+        if harmony.len() > 2 && harmony[2].0 > 0.0 {
+            harmony[2].0 += 20.0;
+            incorrect_count += 1;
+        }
+
+        let mut mod_tracks = vec![melody.as_slice(), harmony.as_slice()];
+        mod_tracks.extend_from_slice(&tracks[2..]);
+
+        let info = scored(1.0, batch.played_share(incorrect_count));
+        batch.push("incorrect_both", &mod_tracks, tempo, render, info)?;
+    }
+
+    // 10. Harmony Out of Sync (Slightly Late) / 11. (Very Late)
+    for (name, delay) in [("sync_slight_lag", 0.15), ("sync_major_lag", 0.35)] {
+        if !selection.wants(name) || tracks.len() < 2 {
+            continue;
+        }
+        let mut harmony = vec![(0.0, delay)];
+        harmony.extend_from_slice(tracks[1]);
+
+        let mut mod_tracks = vec![tracks[0], harmony.as_slice()];
+        mod_tracks.extend_from_slice(&tracks[2..]);
+
+        batch.push(name, &mod_tracks, tempo, render, scored(1.0, 1.0))?;
+    }
+
+    // 12. Extra Notes (Melody - Simultaneous/Fat Finger)
+    if selection.wants("extra_melody_simul") && !tracks.is_empty() {
+        let melody = tracks[0];
+        let mut extra_track = Vec::new();
+        let mut extra_count = 0;
+        let indices = [2, 9, 14];
+
+        for (i, &(freq, dur)) in melody.iter().enumerate() {
+            if indices.contains(&i) && freq > 0.0 {
+                // Add a "fat finger" note (semitone up) for a short duration at the start
+                let fat_freq = freq * 1.05946;
+                let fat_dur = 0.1;
+                if dur > fat_dur {
+                    extra_track.push((fat_freq, fat_dur));
+                    extra_track.push((0.0, dur - fat_dur));
+                } else {
+                    extra_track.push((fat_freq, dur));
+                }
+                extra_count += 1;
+            } else {
+                extra_track.push((0.0, dur));
+            }
+        }
+
+        let mut mixed_tracks = tracks.to_vec();
+        mixed_tracks.push(extra_track.as_slice());
+
+        let info = VariationInfo {
+            expected_extra_notes: extra_count,
+            ..scored(1.0, batch.played_share(extra_count))
+        };
+        batch.push("extra_melody_simul", &mixed_tracks, tempo, render, info)?;
+    }
+
+    // 13. Extra Notes (Melody - Before/Anticipation)
+    if selection.wants("extra_melody_before") && !tracks.is_empty() {
+        let melody = tracks[0];
+        let mut extra_track = Vec::new();
+        let mut extra_count = 0;
+        // Insert extra note before index 6 and 12.
+        // This means modifying the segment corresponding to 5 and 11.
+        let indices_before = [6, 12];
+
+        for (i, &(_, dur)) in melody.iter().enumerate() {
+            // Check if NEXT note is a target
+            if i + 1 < melody.len() && indices_before.contains(&(i + 1)) {
+                let next_freq = melody[i+1].0;
+                if next_freq > 0.0 {
+                    let extra_dur = 0.15;
+                    let extra_freq = next_freq * 0.94387; // Semitone down
+
+                    if dur > extra_dur {
+                        extra_track.push((0.0, dur - extra_dur));
+                        extra_track.push((extra_freq, extra_dur));
+                    } else {
+                        // Duration too short to anticipate, just push silence
+                        extra_track.push((0.0, dur));
+                    }
+                    extra_count += 1;
+                } else {
+                    extra_track.push((0.0, dur));
+                }
+            } else {
+                extra_track.push((0.0, dur));
+            }
+        }
+
+        let mut mixed_tracks = tracks.to_vec();
+        mixed_tracks.push(extra_track.as_slice());
+
+        let info = VariationInfo {
+            expected_extra_notes: extra_count,
+            ..scored(1.0, batch.played_share(extra_count))
+        };
+        batch.push("extra_melody_before", &mixed_tracks, tempo, render, info)?;
+    }
+
+    // 14. Extra Notes (Melody - After/Ghost)
+    if selection.wants("extra_melody_after") && !tracks.is_empty() {
+        let melody = tracks[0];
+        let mut extra_track = Vec::new();
+        let mut extra_count = 0;
+        let indices = [4, 10];
+
+        for (i, &(freq, dur)) in melody.iter().enumerate() {
+            if indices.contains(&i) && freq > 0.0 {
+                let extra_dur = 0.08;
+                let delay = 0.08;
+                let extra_freq = freq * 0.94387; // Semitone down
+
+                if dur > (delay + extra_dur) {
+                    extra_track.push((0.0, delay));
+                    extra_track.push((extra_freq, extra_dur));
+                    extra_track.push((0.0, dur - delay - extra_dur));
+                    extra_count += 1;
+                } else {
+                    extra_track.push((0.0, dur));
+                }
+            } else {
+                extra_track.push((0.0, dur));
+            }
+        }
+
+        let mut mixed_tracks = tracks.to_vec();
+        mixed_tracks.push(extra_track.as_slice());
+
+        let info = VariationInfo {
+            expected_extra_notes: extra_count,
+            ..scored(1.0, batch.played_share(extra_count))
+        };
+        batch.push("extra_melody_after", &mixed_tracks, tempo, render, info)?;
+    }
+
+    // 15. Extra Notes (Harmony - Simultaneous)
+    if selection.wants("extra_harmony_simul") && tracks.len() >= 2 {
+        let harmony = tracks[1];
+        let mut extra_track = Vec::new();
+        let mut extra_count = 0;
+        let indices = [1, 3];
+
+        for (i, &(freq, dur)) in harmony.iter().enumerate() {
+            if indices.contains(&i) && freq > 0.0 {
+                let fat_freq = freq * 1.05946;
+                let fat_dur = 0.15;
+                if dur > fat_dur {
+                    extra_track.push((fat_freq, fat_dur));
+                    extra_track.push((0.0, dur - fat_dur));
+                } else {
+                    extra_track.push((fat_freq, dur));
+                }
+                extra_count += 1;
+            } else {
+                extra_track.push((0.0, dur));
+            }
+        }
+
+        let mut mixed_tracks = tracks.to_vec();
+        mixed_tracks.push(extra_track.as_slice());
+
+        let info = VariationInfo {
+            expected_extra_notes: extra_count,
+            ..scored(1.0, batch.played_share(extra_count))
+        };
+        batch.push("extra_harmony_simul", &mixed_tracks, tempo, render, info)?;
+    }
+
+    // 16. Extra Notes (Harmony - Before)
+    if selection.wants("extra_harmony_before") && tracks.len() >= 2 {
+        let harmony = tracks[1];
+        let mut extra_track = Vec::new();
+        let mut extra_count = 0;
+        let indices_before = [2];
+
+        for (i, &(_, dur)) in harmony.iter().enumerate() {
+            if i + 1 < harmony.len() && indices_before.contains(&(i + 1)) {
+                let next_freq = harmony[i+1].0;
+                if next_freq > 0.0 {
+                    let extra_dur = 0.2;
+                    let extra_freq = next_freq * 0.94387;
+
+                    if dur > extra_dur {
+                        extra_track.push((0.0, dur - extra_dur));
+                        extra_track.push((extra_freq, extra_dur));
+                    } else {
+                        extra_track.push((0.0, dur));
+                    }
+                    extra_count += 1;
+                } else {
+                    extra_track.push((0.0, dur));
+                }
+            } else {
+                extra_track.push((0.0, dur));
+            }
+        }
+
+        let mut mixed_tracks = tracks.to_vec();
+        mixed_tracks.push(extra_track.as_slice());
+
+        let info = VariationInfo {
+            expected_extra_notes: extra_count,
+            ..scored(1.0, batch.played_share(extra_count))
+        };
+        batch.push("extra_harmony_before", &mixed_tracks, tempo, render, info)?;
+    }
+
+    // 17. Extra Notes (Harmony - After)
+    if selection.wants("extra_harmony_after") && tracks.len() >= 2 {
+        let harmony = tracks[1];
+        let mut extra_track = Vec::new();
+        let mut extra_count = 0;
+        let indices = [0, 2];
+
+        for (i, &(freq, dur)) in harmony.iter().enumerate() {
+            if indices.contains(&i) && freq > 0.0 {
+                let extra_dur = 0.08;
+                let delay = 0.08;
+                let extra_freq = freq * 0.94387;
+
+                if dur > (delay + extra_dur) {
+                    extra_track.push((0.0, delay));
+                    extra_track.push((extra_freq, extra_dur));
+                    extra_track.push((0.0, dur - delay - extra_dur));
+                    extra_count += 1;
+                } else {
+                    extra_track.push((0.0, dur));
+                }
+            } else {
+                extra_track.push((0.0, dur));
+            }
+        }
+
+        let mut mixed_tracks = tracks.to_vec();
+        mixed_tracks.push(extra_track.as_slice());
+
+        let info = VariationInfo {
+            expected_extra_notes: extra_count,
+            ..scored(1.0, batch.played_share(extra_count))
+        };
+        batch.push("extra_harmony_after", &mixed_tracks, tempo, render, info)?;
+    }
+
+    // 18. Transposed (a new reference in another key, so it is its own ideal)
+    if let Some(semitones) = song.transpose_semitones && selection.wants("transposed") {
+        let transposed: Vec<Vec<(f32, f32)>> =
+            tracks.iter().map(|track| transpose(track, semitones)).collect();
+        let transposed_tracks: Vec<&[(f32, f32)]> =
+            transposed.iter().map(|t| t.as_slice()).collect();
+
+        let direction = if semitones < 0 { "down" } else { "up" };
+        let name = format!("transposed_{}{}", direction, semitones.abs());
+        let info = batch.push(&name, &transposed_tracks, tempo, render, scored(1.0, 1.0))?;
+        info.ideal_filename = info.filename.clone();
+    }
+
+    // 19. Flat Dynamics (every note at the same, moderate volume)
+    if selection.wants("flat_dynamics") {
+        let flat: Vec<Vec<Note>> = to_notes(&tracks)
+            .into_iter()
+            .map(|track| track.into_iter().map(|n| Note { velocity: 0.6, ..n }).collect())
+            .collect();
+        let flat_tracks: Vec<&[Note]> = flat.iter().map(|t| t.as_slice()).collect();
+
+        batch.push("flat_dynamics", &flat_tracks, tempo, render, scored(1.0, 1.0))?;
+    }
+
+    // 20. Wrong Notes (Melody - right rhythm, finger slips a key or two up)
+    if selection.wants("wrong_notes") && !tracks.is_empty() {
+        let mut melody = tracks[0].to_vec();
+        let mut rng = Rng::new(sub_seed(seed, WRONG_NOTES_SEED));
+        let mut wrong_count = 0;
+
+        let playable: Vec<usize> = (0..melody.len()).filter(|&i| melody[i].0 > 0.0).collect();
+        let wanted = (playable.len() / 8).max(1);
+        let mut picked: Vec<usize> = Vec::new();
+        while picked.len() < wanted.min(playable.len()) {
+            let i = playable[rng.below(playable.len())];
+            if !picked.contains(&i) {
+                picked.push(i);
+            }
+        }
+        for &i in &picked {
+            let semitones = 1 + rng.below(2) as i32;
+            melody[i] = transpose(&melody[i..=i], semitones)[0];
+            wrong_count += 1;
+        }
+
+        let mut mod_tracks = vec![melody.as_slice()];
+        mod_tracks.extend_from_slice(&tracks[1..]);
+
+        let info = scored(1.0, batch.played_share(wrong_count));
+        batch.push("wrong_notes", &mod_tracks, tempo, render, info)?;
+    }
+
+    // 21. Extra Notes (Melody - Inserted, stealing time from the note before)
+    if selection.wants("extra_melody_inserted") && !tracks.is_empty() {
+        let melody = tracks[0];
+        let mut inserted_track = Vec::new();
+        let mut extra_count = 0;
+        // Insert a third above after index 3 and a fifth above after index 9
+        let insertions = [(3, 4), (9, 7)];
+        let extra_dur = 0.08;
+
+        for (i, &(freq, dur)) in melody.iter().enumerate() {
+            match insertions.iter().find(|(at, _)| *at == i) {
+                Some(&(_, semitones)) if freq > 0.0 && dur > 2.0 * extra_dur => {
+                    inserted_track.push((freq, dur - extra_dur));
+                    inserted_track.push(transpose(&[(freq, extra_dur)], semitones)[0]);
+                    extra_count += 1;
+                }
+                _ => inserted_track.push((freq, dur)),
+            }
+        }
+
+        let mut mod_tracks = vec![inserted_track.as_slice()];
+        mod_tracks.extend_from_slice(&tracks[1..]);
+
+        let info = VariationInfo {
+            expected_extra_notes: extra_count,
+            ..scored(1.0, batch.played_share(extra_count))
+        };
+        batch.push("extra_melody_inserted", &mod_tracks, tempo, render, info)?;
+    }
+
+    // 22. Timing Jitter (Melody - unsteady rushing and dragging)
+    if selection.wants("jitter") && !tracks.is_empty() {
+        let melody = jitter(tracks[0], JITTER_SD, sub_seed(seed, JITTER_SEED));
+        let deviation = timing_deviation(&melody, tracks[0]);
+
+        let mut mod_tracks = vec![melody.as_slice()];
+        mod_tracks.extend_from_slice(&tracks[1..]);
+
+        batch.push("jitter", &mod_tracks, tempo, render, scored(1.0 - deviation, 1.0))?;
+    }
+
+    // 23. Room Reverb (the same performance, recorded in a live room)
+    if selection.wants("reverb") {
+        let reverb = RenderOptions { reverb: Some(ROOM_REVERB), ..render };
+        batch.push("reverb", &tracks, tempo, reverb, scored(1.0, 1.0))?;
+    }
+
+    // 24. Other Instruments (melody on flute, accompaniment on organ)
+    if selection.wants("flute_organ") {
+        let profiles = [HarmonicProfile::flute(), HarmonicProfile::organ()];
+        let options = RenderOptions { profiles: &profiles, ..render };
+        batch.push("flute_organ", &tracks, tempo, options, scored(1.0, 1.0))?;
+    }
+
+    // 25. Stops Early (every part breaks off partway through the melody)
+    if selection.wants("stops_early") && !tracks.is_empty() {
+        let kept = (tracks[0].len() as f32 * STOP_EARLY_FRACTION).round() as usize;
+        let stop_at: f32 = tracks[0][..kept].iter().map(|&(_, dur)| dur).sum();
+        let truncated: Vec<Vec<(f32, f32)>> =
+            tracks.iter().map(|track| truncate(track, stop_at)).collect();
+        let mod_tracks: Vec<&[(f32, f32)]> = truncated.iter().map(|t| t.as_slice()).collect();
+
+        let played = count_playable_notes(&mod_tracks);
+        let info = scored(1.0, played as f32 / batch.playable_notes as f32);
+        batch.push("stops_early", &mod_tracks, tempo, render, info)?;
+    }
+
+    // 26. Stutter (Melody - a key struck twice in quick succession)
+    if selection.wants("stutter") && !tracks.is_empty() {
+        let mut stuttered = Vec::new();
+        let mut stutter_positions = Vec::new();
+        for (i, &(freq, dur)) in tracks[0].iter().enumerate() {
+            if STUTTER_POSITIONS.contains(&i) && freq > 0.0 {
+                // Two separate notes, so the second strike gets its own attack
+                stuttered.push((freq, dur / 2.0));
+                stuttered.push((freq, dur / 2.0));
+                stutter_positions.push(i);
+            } else {
+                stuttered.push((freq, dur));
+            }
+        }
+        let extra_count = stutter_positions.len();
+
+        let mut mod_tracks = vec![stuttered.as_slice()];
+        mod_tracks.extend_from_slice(&tracks[1..]);
+
+        let info = VariationInfo {
+            expected_extra_notes: extra_count,
+            stutter_positions,
+            ..scored(1.0, batch.played_share(extra_count))
+        };
+        batch.push("stutter", &mod_tracks, tempo, render, info)?;
+    }
+
+    // 27. Staccato / 28. Legato (every note detached, or every note slurred)
+    let articulations = [("staccato", Articulation::Staccato), ("legato", Articulation::Legato)];
+    for (name, articulation) in articulations {
+        if !selection.wants(name) {
+            continue;
+        }
+        let articulated: Vec<Vec<Note>> = to_notes(&tracks)
+            .into_iter()
+            .map(|track| track.into_iter().map(|n| Note { articulation, ..n }).collect())
+            .collect();
+        let mod_tracks: Vec<&[Note]> = articulated.iter().map(|t| t.as_slice()).collect();
+
+        batch.push(name, &mod_tracks, tempo, render, scored(1.0, 1.0))?;
+    }
+
+    // 29. Glide (each melody note slides in from the one before)
+    if selection.wants("glide") && !tracks.is_empty() {
+        let mut glided = to_notes(&tracks);
+        for note in &mut glided[0] {
+            note.glide = GLIDE_TIME;
+        }
+        let mod_tracks: Vec<&[Note]> = glided.iter().map(|t| t.as_slice()).collect();
+
+        batch.push("glide", &mod_tracks, tempo, render, scored(1.0, 1.0))?;
+    }
+
+    // 30. Detuned (the whole piano consistently out of tune)
+    if selection.wants("detuned") {
+        let offsets = detune_offsets(DETUNE_MAX_CENTS, sub_seed(seed, DETUNE_SEED));
+        let detuned: Vec<Vec<(f32, f32)>> = tracks.iter().map(|t| detune(t, &offsets)).collect();
+        let mod_tracks: Vec<&[(f32, f32)]> = detuned.iter().map(|t| t.as_slice()).collect();
+
+        // Average mistuning over the notes actually played
+        let cents: Vec<f32> = tracks
+            .iter()
+            .flat_map(|t| t.iter())
+            .filter(|&&(freq, _)| freq > 0.0)
+            .map(|&(freq, _)| detune_cents(freq, &offsets).abs())
+            .collect();
+        let mean_cents = cents.iter().sum::<f32>() / cents.len().max(1) as f32;
+
+        let info = scored(1.0, (1.0 - mean_cents / DETUNE_ZERO_ACCURACY_CENTS).max(0.0));
+        batch.push("detuned", &mod_tracks, tempo, render, info)?;
+    }
+
+    // 31. Crescendo / 32. Decrescendo (the melody swells, or fades, from
+    // start to end)
+    let (soft, loud) = HAIRPIN_RANGE;
+    for (name, from, to) in [("crescendo", soft, loud), ("decrescendo", loud, soft)] {
+        if !selection.wants(name) || tracks.is_empty() {
+            continue;
+        }
+        let mut shaped = to_notes(&tracks);
+        let last = shaped[0].len().saturating_sub(1).max(1) as f32;
+        for (i, note) in shaped[0].iter_mut().enumerate() {
+            note.velocity = from + (to - from) * i as f32 / last;
+        }
+        let dynamics = shaped[0].iter().map(|n| n.velocity).collect();
+        let mod_tracks: Vec<&[Note]> = shaped.iter().map(|t| t.as_slice()).collect();
+
+        let info = VariationInfo { dynamics, ..scored(1.0, 1.0) };
+        batch.push(name, &mod_tracks, tempo, render, info)?;
+    }
+
+    // 33. Click (the original over a metronome on every quarter note)
+    if selection.wants("click") {
+        let with_click = RenderOptions { click_bpm: Some(tempo.bpm), ..render };
+        batch.push("click", &tracks, tempo, with_click, scored(1.0, 1.0))?;
+    }
+
+    // 34. Muffled (the original through a lowpass filter)
+    if selection.wants("muffled") {
+        let muffled = RenderOptions { lowpass_hz: Some(MUFFLED_CUTOFF_HZ), ..render };
+        batch.push("muffled", &tracks, tempo, muffled, scored(1.0, 1.0))?;
+    }
+
+    // 35. Noisy (the original under background hiss)
+    if selection.wants("noisy") {
+        let noise = Noise { seed: sub_seed(seed, ROOM_NOISE.seed), ..ROOM_NOISE };
+        let noisy = RenderOptions { noise: Some(noise), ..render };
+        batch.push("noisy", &tracks, tempo, noisy, scored(1.0, 1.0))?;
+    }
+
+    // 36. Swing (eighth-note pairs played long-short in every part)
+    if selection.wants("swing") {
+        let swung: Vec<Vec<(f32, f32)>> = tracks.iter().map(|t| swing(t, SWING_RATIO)).collect();
+        let mod_tracks: Vec<&[(f32, f32)]> = swung.iter().map(|t| t.as_slice()).collect();
+        let deviation =
+            mod_tracks.first().map_or(0.0, |melody| timing_deviation(melody, tracks[0]));
+
+        batch.push("swing", &mod_tracks, tempo, render, scored(1.0 - deviation, 1.0))?;
+    }
+
+    // 37. Vibrato on the melody
+    if selection.wants("vibrato") {
+        let options = RenderOptions { vibrato: MELODY_VIBRATO, ..render };
+        batch.push("vibrato", &tracks, tempo, options, scored(1.0, 1.0))?;
+    }
+
+    // 38. Tremolo on the melody
+    if selection.wants("tremolo") {
+        let options = RenderOptions { tremolo: MELODY_TREMOLO, ..render };
+        batch.push("tremolo", &tracks, tempo, options, scored(1.0, 1.0))?;
+    }
+
+    // 39. Repeated (the song looped, a new reference that is its own ideal)
+    if selection.wants("repeated") {
+        let looped = repeat(&tracks, REPEAT_PASSES, REPEAT_GAP_SECS);
+        let looped_tracks: Vec<&[(f32, f32)]> = looped.iter().map(|t| t.as_slice()).collect();
+
+        let info = batch.push("repeated", &looped_tracks, tempo, render, scored(1.0, 1.0))?;
+        info.ideal_filename = info.filename.clone();
+    }
+
+    // 40. Humanized (Melody - slightly uneven timing and touch, a good take)
+    if selection.wants("humanized") && !tracks.is_empty() {
+        let humanize_seed = sub_seed(seed, HUMANIZE_SEED);
+        let melody = humanize(tracks[0], HUMANIZE_TIMING_SD, HUMANIZE_VELOCITY_SD, humanize_seed);
+        let played: Vec<(f32, f32)> =
+            melody.iter().map(|n| (n.pitch.to_freq(), n.duration)).collect();
+        let deviation = timing_deviation(&played, tracks[0]);
+        let dynamics = melody.iter().map(|n| n.velocity).collect();
+
+        let mut humanized = vec![melody];
+        humanized.extend(to_notes(&tracks[1..]));
+        let mod_tracks: Vec<&[Note]> = humanized.iter().map(|t| t.as_slice()).collect();
+
+        let info = VariationInfo { dynamics, ..scored(1.0 - deviation, 1.0) };
+        batch.push("humanized", &mod_tracks, tempo, render, info)?;
+    }
+
+    // 41. Ritardando (every part slows down over the last few melody notes)
+    if selection.wants("ritardando") && !tracks.is_empty() {
+        let from = tracks[0].len().saturating_sub(RITARDANDO_NOTES);
+        let start: f32 = tracks[0][..from].iter().map(|&(_, dur)| dur).sum();
+        let end = tracks.iter().map(|t| t.iter().map(|&(_, dur)| dur).sum()).fold(0.0, f32::max);
+        let slowed: Vec<Vec<(f32, f32)>> =
+            tracks.iter().map(|t| tempo_ramp(t, start, end, RITARDANDO_STRETCH)).collect();
+        let mod_tracks: Vec<&[(f32, f32)]> = slowed.iter().map(|t| t.as_slice()).collect();
+        let deviation = timing_deviation(mod_tracks[0], tracks[0]);
+
+        batch.push("ritardando", &mod_tracks, tempo, render, scored(1.0 - deviation, 1.0))?;
+    }
+
+    // 42. Accelerando (every part rushes more and more from start to end)
+    if selection.wants("accelerando") && !tracks.is_empty() {
+        let end = tracks.iter().map(|t| t.iter().map(|&(_, dur)| dur).sum()).fold(0.0, f32::max);
+        let rushed: Vec<Vec<(f32, f32)>> =
+            tracks.iter().map(|t| tempo_ramp(t, 0.0, end, ACCELERANDO_STRETCH)).collect();
+        let mod_tracks: Vec<&[(f32, f32)]> = rushed.iter().map(|t| t.as_slice()).collect();
+        let deviation = timing_deviation(mod_tracks[0], tracks[0]);
+
+        batch.push("accelerando", &mod_tracks, tempo, render, scored(1.0 - deviation, 1.0))?;
+    }
+
+    // 43. Uneven Touch (Melody - each note takes its own time to speak)
+    if selection.wants("uneven_touch") && !tracks.is_empty() {
+        let mut rng = Rng::new(sub_seed(seed, UNEVEN_TOUCH_SEED));
+        let (fastest, slowest) = UNEVEN_ATTACK;
+        let mut touched = to_notes(&tracks);
+        for note in &mut touched[0] {
+            note.attack = Some(fastest + (slowest - fastest) * rng.next_f32());
+        }
+        let mod_tracks: Vec<&[Note]> = touched.iter().map(|t| t.as_slice()).collect();
+
+        batch.push("uneven_touch", &mod_tracks, tempo, render, scored(1.0, 1.0))?;
+    }
+
+    // 44. Sharp Drift (every part slowly bent sharp, independent of the notes)
+    if selection.wants("sharp_drift") {
+        let end = tracks.iter().map(|t| t.iter().map(|&(_, dur)| dur).sum()).fold(0.0, f32::max);
+        let quarter = end / SHARP_DRIFT.len() as f32;
+        let bend: BendTrack = SHARP_DRIFT.iter().map(|&semitones| (semitones, quarter)).collect();
+
+        let options = RenderOptions { bend: &bend, ..render };
+        batch.push("sharp_drift", &tracks, tempo, options, scored(1.0, 1.0))?;
+    }
+
+    // 45. Random Phase (every note's partials start out of step, as on a real piano)
+    if selection.wants("random_phase") {
+        let scatter = PhaseScatter { seed: sub_seed(seed, PHASE_SCATTER.seed), ..PHASE_SCATTER };
+        let options = RenderOptions { phase_scatter: Some(scatter), ..render };
+        batch.push("random_phase", &tracks, tempo, options, scored(1.0, 1.0))?;
+    }
+
+    // 46. Hesitation (every part freezes for a moment mid-phrase, then carries on)
+    if selection.wants("hesitation") && tracks.first().is_some_and(|t| t.len() > 1) {
+        // Each track's note start times, to find a point where all can stop
+        let starts: Vec<Vec<f32>> = tracks
+            .iter()
+            .map(|t| {
+                let mut at = 0.0;
+                t.iter()
+                    .map(|&(_, dur)| {
+                        let start = at;
+                        at += dur;
+                        start
+                    })
+                    .collect()
+            })
+            .collect();
+        let shared = |at: f32| starts[1..].iter().all(|s| s.iter().any(|&x| (x - at).abs() < 1e-4));
+        let first = ((tracks[0].len() as f32 * HESITATION_FRACTION) as usize).max(1);
+        let before_note = (first..tracks[0].len())
+            .find(|&i| shared(starts[0][i]))
+            .unwrap_or(first.min(tracks[0].len() - 1));
+        let at_secs = starts[0][before_note];
+        let hesitated: Vec<Vec<(f32, f32)>> =
+            tracks.iter().map(|t| hesitate(t, at_secs, HESITATION_SECS)).collect();
+        let mod_tracks: Vec<&[(f32, f32)]> = hesitated.iter().map(|t| t.as_slice()).collect();
+
+        // Scored as the note before the pause being held that much longer
+        let mut held = tracks[0].to_vec();
+        held[before_note - 1].1 += HESITATION_SECS;
+        let deviation = timing_deviation(&held, tracks[0]);
+
+        let info = scored(1.0 - deviation, 1.0);
+        let info = batch.push("hesitation", &mod_tracks, tempo, render, info)?;
+        // The pause is the rest the melody gained just before `before_note`
+        let (start_sample, end_sample, _) = info.onsets_samples[0][before_note];
+        let length_samples = end_sample - start_sample;
+        info.pause = Some(PauseInfo { before_note, start_sample, length_samples });
+    }
+
+    // 47. Double Octaves (Melody - every note also struck an octave higher)
+    if selection.wants("double_octaves") && !tracks.is_empty() {
+        let mut doubled = to_notes(&tracks);
+        let mut octaves = 0;
+        for note in &mut doubled[0] {
+            let freq = note.pitch.to_freq();
+            if freq > 0.0 {
+                note.chord.push(Pitch::from(freq * 2.0));
+                octaves += 1;
+            }
+        }
+        let mod_tracks: Vec<&[Note]> = doubled.iter().map(|t| t.as_slice()).collect();
+
+        // The octaves thicken the melody rather than replace any of it
+        let info = VariationInfo { expected_extra_notes: octaves, ..scored(1.0, 1.0) };
+        batch.push("double_octaves", &mod_tracks, tempo, render, info)?;
+    }
+
+    // 48. Wrong Rhythm (Melody - right pitches in order, durations flattened)
+    if selection.wants("wrong_rhythm") && !tracks.is_empty() {
+        // Once one note is the wrong length every onset after it is late or
+        // early, so it is judged by where the notes start, as a listener would
+        let starts = |song: &[(f32, f32)]| -> Vec<f32> {
+            let mut elapsed = 0.0;
+            song.iter()
+                .filter_map(|&(freq, dur)| {
+                    let start = elapsed;
+                    elapsed += dur;
+                    (freq > 0.0).then_some(start)
+                })
+                .collect()
+        };
+        let flattened = flatten_rhythm(tracks[0]);
+        let (played, written) = (starts(&flattened), starts(tracks[0]));
+        // Skipped when only a last held note is cut short, with every note
+        // still starting on time
+        if played != written {
+            let mut mod_tracks = tracks.clone();
+            mod_tracks[0] = &flattened;
+
+            let info = scored(score::tempo_accuracy(&played, &written), 1.0);
+            batch.push("wrong_rhythm", &mod_tracks, tempo, render, info)?;
+        }
+    }
+
+    // 49. Quiet Harmony (Harmony - played well under the melody)
+    if selection.wants("quiet_harmony") && tracks.len() >= 2 {
+        let options = RenderOptions { balance: QUIET_HARMONY, ..render };
+        batch.push("quiet_harmony", &tracks, tempo, options, scored(1.0, 1.0))?;
+    }
+
+    // 50. Downbeat Accent (every part leans on the first beat of each measure)
+    if selection.wants("downbeat_accent") && !tracks.is_empty() {
+        let accent = Accent::downbeats_of(song, DOWNBEAT_ACCENT_GAIN);
+        let options = RenderOptions { accent: Some(accent), ..render };
+        let dynamics = accent.multipliers(tracks[0].iter().map(|&(_, dur)| dur));
+        let info = VariationInfo { dynamics, ..scored(1.0, 1.0) };
+        batch.push("downbeat_accent", &tracks, tempo, options, info)?;
+    }
+
+    // 51. Synth (a sawtooth lead and square-wave accompaniment in place of the piano)
+    if selection.wants("synth") {
+        let profiles = [HarmonicProfile::synth_lead(), HarmonicProfile::synth_bass()];
+        let options = RenderOptions { profiles: &profiles, ..render };
+        batch.push("synth", &tracks, tempo, options, scored(1.0, 1.0))?;
+    }
+
+    // 52. One hand late (the melody enters a moment after the accompaniment)
+    if selection.wants("one_hand_late") && tracks.len() >= 2 {
+        let mut melody = vec![(0.0, ONE_HAND_LATE_SECS)];
+        melody.extend_from_slice(tracks[0]);
+        let mut mod_tracks = tracks.clone();
+        mod_tracks[0] = &melody;
+
+        batch.push("one_hand_late", &mod_tracks, tempo, render, scored(1.0, 1.0))?;
+    }
+
+    // 53. Wrong lengths (some melody notes held too long, others cut short,
+    // every one starting on time)
+    if selection.wants("wrong_lengths") && !tracks.is_empty() {
+        let mut melody = to_notes(&tracks[..1]).remove(0);
+        // Lengthened notes move to a line of their own, so the next note
+        // can start over them
+        let mut under = Vec::new();
+        let (mut at, mut under_end) = (0.0, 0.0);
+        let mut duration_errors = Vec::new();
+        for (i, note) in melody.iter_mut().enumerate() {
+            let start = at;
+            at += note.duration;
+            let Some(&(_, factor)) = LENGTH_ERRORS.iter().find(|&&(n, _)| n == i) else {
+                continue;
+            };
+            if note.pitch.to_freq() <= 0.0 {
+                continue;
+            }
+            if factor > 1.0 {
+                under.push(Note::from((Pitch::Rest, start - under_end)));
+                under.push(Note { duration: note.duration * factor, ..note.clone() });
+                under_end = start + note.duration * factor;
+                note.pitch = Pitch::Rest;
+                note.chord.clear();
+            } else {
+                note.articulation = Articulation::Clipped(factor);
+            }
+            let change = note.duration * (factor - 1.0) * tempo.speed_mult();
+            duration_errors.push(DurationError { note: i, change_secs: change });
+        }
+
+        if !duration_errors.is_empty() {
+            let mut mod_tracks: Vec<&[Note]> = vec![&melody];
+            let rest = to_notes(&tracks[1..]);
+            mod_tracks.extend(rest.iter().map(Vec::as_slice));
+            let options = RenderOptions { under: &under, ..render };
+
+            let info = VariationInfo { duration_errors, ..scored(1.0, 1.0) };
+            let info = batch.push("wrong_lengths", &mod_tracks, tempo, options, info)?;
+            // Scored against the written notes: every onset is where it was
+            info.notes = get_notes(&tracks, tempo);
+            info.onsets_samples = get_onsets(&tracks, tempo, rate);
+        }
+    }
+
+    // 54. Sustain pedal, played on top of the original
+    if let Some(pedal) = &song.pedal
+        && selection.wants("pedal")
+    {
+        let options = RenderOptions { sustain: pedal, ..render };
+        batch.push("pedal", &tracks, tempo, options, scored(1.0, 1.0))?;
+    }
+
+    Ok(batch)
+}
+
+// Write the original of `song` into `dir`, as a mix with stems and copies
+// when it has several tracks, and the files that go with it: its score as
+// MIDI and MusicXML, and a mono copy
+fn write_original(
+    song: &Song,
+    tracks: &[&[(f32, f32)]],
+    tempo: Tempo,
+    render: RenderOptions,
+    dir: &str,
+) -> io::Result<(Vec<String>, Vec<String>, Option<String>)> {
+    let base_name = song.title.as_str();
+    let original_filename = format!("{}.wav", base_name);
+    let (mut stems, mut copies) = (Vec::new(), Vec::new());
+    if tracks.len() > 1 {
+        let speed_mult = tempo.speed_mult();
+        (stems, copies) = generate_stems(base_name, tracks.to_vec(), speed_mult, render)?;
+    } else {
+        generate(&original_filename, tracks.to_vec(), tempo, render)?;
+    }
+
+    // The score itself, for checking the fixtures in other tools
+    let midi_filename = format!("{}.mid", base_name);
+    let midi_path = format!("{}/{}", dir, midi_filename);
+    progress!("Writing {}", midi_filename);
+    // In seconds as played, so the file carries the tempo the WAVs use
+    let speed_mult = tempo.speed_mult();
+    let played: Vec<Vec<(f32, f32)>> = tracks
+        .iter()
+        .map(|track| track.iter().map(|&(freq, dur)| (freq, dur * speed_mult)).collect())
+        .collect();
+    let played: Vec<&[(f32, f32)]> = played.iter().map(Vec::as_slice).collect();
+    midi::write(&played, tempo.bpm, &midi_path).map_err(at_path(&midi_path))?;
+    let xml_filename = format!("{}.musicxml", base_name);
+    let xml_path = format!("{}/{}", dir, xml_filename);
+    progress!("Writing {}", xml_filename);
+    let bpm = song.default_bpm / speed_mult;
+    musicxml::write(song, bpm, &xml_path).map_err(at_path(&xml_path))?;
+
+    // A mono copy, for pipelines that only take one channel
+    let mono_filename = format!("{}_mono.wav", base_name);
+    let original_path = format!("{}/{}", dir, original_filename);
+    let stereo = wav::read(&original_path).map_err(at_path(&original_path))?;
+    progress!("Writing {}", mono_filename);
+    let mono_path = format!("{}/{}", dir, mono_filename);
+    wav::write_mono(&downmix(&stereo), &mono_path).map_err(at_path(&mono_path))?;
+    Ok((stems, copies, Some(mono_filename)))
+}
+
+/// Onsets of different tracks closer than this (seconds) are expected as
+/// one, since the onset detector hears a chord as a single attack.
+const CHORD_ONSET_SECS: f32 = 0.03;
+
+// Tempo and pitch accuracy of `written` as `analyze` hears it, against the
+// per-track `(start_sample, end_sample, frequency)` slots of its ideal
+fn measured_accuracy(written: &Audio<Ch16, 2>, ideal: &[Vec<(usize, usize, f32)>]) -> (f32, f32) {
+    let rate = written.sample_rate().get();
+    let mut expected: Vec<f32> = ideal
+        .iter()
+        .flatten()
+        .filter(|&&(_, _, freq)| freq > 0.0)
+        .map(|&(start, _, _)| start as f32 / rate as f32)
+        .collect();
+    expected.sort_by(f32::total_cmp);
+    expected.dedup_by(|later, earlier| *later - *earlier < CHORD_ONSET_SECS);
+    let tempo = score::tempo_accuracy(&analyze::detect_onsets(written), &expected);
+
+    let pitches = analyze::detect_pitches(written, score::PITCH_WINDOW);
+    let pitch = ideal.first().map_or(1.0, |melody| score::pitch_accuracy(&pitches, melody, rate));
+    (tempo, pitch)
+}
+
+// Cut the silent ends off a written file, its stems and copies, as `--trim` asks,
+// and move the ground truth earlier to match; slots in the cut-off tail end
+// up empty at the new end of the file
+fn trim_written(
+    info: &mut VariationInfo,
+    written: &Audio<Ch16, 2>,
+    threshold_dbfs: f32,
+    depth: BitDepth,
+    dir: &str,
+) -> io::Result<Audio<Ch16, 2>> {
+    let (trimmed, lead, trail) = fx::trim_silence(written, threshold_dbfs);
+    let len = trimmed.len();
+    if lead + trail > 0 {
+        progress!("Trimming {}", info.filename);
+        // Cut from the files themselves, so a deeper file keeps its precision;
+        // the stems and copies go to the same span as the mix, so they still
+        // line up
+        let span = |frames: usize| lead.min(frames)..(lead + len).min(frames);
+        let stereo = std::iter::once(&info.filename).chain(&info.stems).chain(&info.copies);
+        for filename in stereo {
+            let path = format!("{}/{}", dir, filename);
+            let audio = wav::read::<Ch32>(&path).map_err(at_path(&path))?;
+            let cut = audio.as_slice()[span(audio.len())].to_vec();
+            let cut = Audio::with_frames(audio.sample_rate().get(), cut);
+            wav::write(&cut, &path, depth).map_err(at_path(&path))?;
+        }
+        if let Some(filename) = &info.mono_copy {
+            let path = format!("{}/{}", dir, filename);
+            let audio = wav::read_mono::<Ch16>(&path).map_err(at_path(&path))?;
+            let cut = audio.as_slice()[span(audio.len())].to_vec();
+            let cut = Audio::with_frames(audio.sample_rate().get(), cut);
+            wav::write_mono(&cut, &path).map_err(at_path(&path))?;
+        }
+    }
+
+    let shift = |sample: usize| sample.saturating_sub(lead).min(len);
+    for slot in info.onsets_samples.iter_mut().flatten() {
+        (slot.0, slot.1) = (shift(slot.0), shift(slot.1));
+    }
+    if let Some(pause) = &mut info.pause {
+        pause.start_sample = shift(pause.start_sample);
+    }
+    let rate = written.sample_rate().get() as f32;
+    let (lead_secs, len_secs) = (lead as f32 / rate, len as f32 / rate);
+    for segment in &mut info.timeline {
+        segment.0 = (segment.0 - lead_secs).clamp(0.0, len_secs);
+        segment.1 = (segment.1 - lead_secs).clamp(0.0, len_secs);
+    }
+    info.trimmed_samples = Some((lead, trail));
+    Ok(trimmed)
+}
+
+// Catch a render gone wrong before the manifest lists it: every test keeps
+// at least some of its notes, so no file may be silent or stop short of them
+fn check_written(info: &VariationInfo, written: &Audio<Ch16, 2>, levels: Levels) -> io::Result<()> {
+    let notes_end = info.onsets_samples.iter().filter_map(|t| t.last()).map(|n| n.1).max();
+    if let Some(end) = notes_end.filter(|&end| written.len() < end) {
+        let message = format!("{} samples long, but its notes run to {}", written.len(), end);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    if levels.rms_dbfs < SILENT_RMS_DBFS {
+        let message = format!("silent, at {:.1} dBFS RMS", levels.rms_dbfs);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+    Ok(())
+}
+
+// Each track's roots, then each further chord tone (a rest where a note has
+// none), as lines of `(frequency, duration)` for `score::merge_tracks`
+fn sounding_lines(notes: &[Vec<NoteInfo>]) -> Vec<Vec<(f32, f32)>> {
+    let mut lines = Vec::new();
+    for track in notes {
+        let width = track.iter().map(|note| note.chord.len() + 1).max().unwrap_or(0);
+        for tone in 0..width {
+            lines.push(
+                track
+                    .iter()
+                    .map(|note| {
+                        let freq = match tone {
+                            0 => note.freq,
+                            _ => note.chord.get(tone - 1).copied().unwrap_or(0.0),
+                        };
+                        (freq, note.duration)
+                    })
+                    .collect(),
+            );
+        }
+    }
+    lines
+}
+
+/// Average the left and right channels, as the mono copy is written.
+pub(super) fn downmix(audio: &Audio<Ch16, 2>) -> Audio<Ch16, 1> {
+    let samples: Vec<i16> = audio
+        .iter()
+        .map(|frame| ((i16::from(frame[Left]) as i32 + i16::from(frame[Right]) as i32) / 2) as i16)
+        .collect();
+    Audio::with_i16_buffer(audio.sample_rate().get(), samples)
+}
+
+// Shift every note by `semitones` in equal temperament; rests stay silent.
+fn transpose(song: &[(f32, f32)], semitones: i32) -> Vec<(f32, f32)> {
+    let ratio = 2f32.powf(semitones as f32 / 12.0);
+    song.iter()
+        .map(|&(freq, dur)| if freq > 0.0 { (freq * ratio, dur) } else { (freq, dur) })
+        .collect()
+}
+
+// Randomly rush or drag each note by a relative standard deviation `sd`,
+// making up the difference on the following note so the song cannot drift
+// and the total length is unchanged.
+fn jitter(song: &[(f32, f32)], sd: f32, seed: u64) -> Vec<(f32, f32)> {
+    let mut rng = Rng::new(seed);
+    let mut offset = 0.0; // How far the next onset is from where it belongs
+    let mut jittered = Vec::with_capacity(song.len());
+
+    for (i, &(freq, dur)) in song.iter().enumerate() {
+        let target = if i + 1 == song.len() { dur } else { dur * (1.0 + sd * rng.gaussian()) };
+        let new_dur = (target - offset).max(dur * 0.25);
+        offset += new_dur - dur;
+        jittered.push((freq, new_dur));
+    }
+    jittered
+}
+
+// `jitter` with standard deviation `timing_sd`, plus an uneven touch: each
+// note's velocity strays from full by a standard deviation of `velocity_sd`,
+// capped at full
+fn humanize(song: &[(f32, f32)], timing_sd: f32, velocity_sd: f32, seed: u64) -> Vec<Note> {
+    // A separate stream, so the timing matches `jitter` with the same seed
+    let mut rng = Rng::new(!seed);
+    jitter(song, timing_sd, seed)
+        .into_iter()
+        .map(|(freq, dur)| Note {
+            velocity: (1.0 + velocity_sd * rng.gaussian()).clamp(0.1, 1.0),
+            ..Note::from((freq, dur))
+        })
+        .collect()
+}
+
+// Offset in cents for each pitch class (C = 0), uniform within `max_cents`
+fn detune_offsets(max_cents: f32, seed: u64) -> [f32; 12] {
+    let mut rng = Rng::new(seed);
+    std::array::from_fn(|_| (rng.next_f32() * 2.0 - 1.0) * max_cents)
+}
+
+// Offset from `offsets` for the pitch class nearest `freq`, 0.0 for a rest
+fn detune_cents(freq: f32, offsets: &[f32; 12]) -> f32 {
+    if freq <= 0.0 {
+        return 0.0;
+    }
+    offsets[(freq_to_midi(freq).round() as i32).rem_euclid(12) as usize]
+}
+
+// Mistune every note by its pitch class's offset, so each string is off
+// by the same amount wherever it is played.
+fn detune(song: &[(f32, f32)], offsets: &[f32; 12]) -> Vec<(f32, f32)> {
+    song.iter()
+        .map(|&(freq, dur)| (freq * 2f32.powf(detune_cents(freq, offsets) / 1200.0), dur))
+        .collect()
+}
+
+// Mean relative change in duration from `written` to `played`, note by note
+fn timing_deviation(played: &[(f32, f32)], written: &[(f32, f32)]) -> f32 {
+    played
+        .iter()
+        .zip(written.iter())
+        .map(|((_, new_dur), (_, dur))| (new_dur - dur).abs() / dur)
+        .sum::<f32>()
+        / played.len() as f32
+}
+
+// Swing pairs of eighth notes that start on a beat: the first is held
+// `ratio` times as long as the second, and the pair still fills the beat.
+fn swing(song: &[(f32, f32)], ratio: f32) -> Vec<(f32, f32)> {
+    let is_eighth = |dur: f32| (dur - E).abs() < 1e-4;
+    let mut swung = song.to_vec();
+    let mut elapsed = 0.0;
+    let mut i = 0;
+    while i < song.len() {
+        let beats = elapsed / Q;
+        let on_beat = (beats - beats.round()).abs() < 1e-3;
+        if on_beat && i + 1 < song.len() && is_eighth(song[i].1) && is_eighth(song[i + 1].1) {
+            let pair = song[i].1 + song[i + 1].1;
+            swung[i].1 = pair * ratio / (1.0 + ratio);
+            swung[i + 1].1 = pair - swung[i].1;
+            elapsed += pair;
+            i += 2;
+        } else {
+            elapsed += song[i].1;
+            i += 1;
+        }
+    }
+    swung
+}
+
+// Bend the tempo from `start` seconds on: each moment is stretched by a
+// factor rising (or falling) steadily from 1.0 at `start` to `end_stretch`
+// at `end`. Every track bent with the same arguments stays together.
+fn tempo_ramp(song: &[(f32, f32)], start: f32, end: f32, end_stretch: f32) -> Vec<(f32, f32)> {
+    let span = (end - start).max(1e-6);
+    // Integral of the stretch factor, taking written time to played time
+    let warp = |t: f32| {
+        let into = (t - start).clamp(0.0, span);
+        t.min(start) + into + (end_stretch - 1.0) * into * into / (2.0 * span) + (t - end).max(0.0)
+    };
+    let mut elapsed = 0.0;
+    song.iter()
+        .map(|&(freq, dur)| {
+            let played = warp(elapsed + dur) - warp(elapsed);
+            elapsed += dur;
+            (freq, played)
+        })
+        .collect()
+}
+
+// Play `tracks` `passes` times in a row, as when looping a passage to
+// practise it. Every track is padded with a rest to the longest one, plus
+// `gap_secs` between passes, so the parts stay together on each pass.
+fn repeat(tracks: &[&[(f32, f32)]], passes: usize, gap_secs: f32) -> Vec<Vec<(f32, f32)>> {
+    let length = |track: &[(f32, f32)]| track.iter().map(|&(_, dur)| dur).sum::<f32>();
+    let pass_len = tracks.iter().map(|t| length(t)).fold(0.0, f32::max);
+
+    tracks
+        .iter()
+        .map(|track| {
+            let padding = pass_len - length(track);
+            let mut looped = Vec::new();
+            for pass in 0..passes {
+                looped.extend_from_slice(track);
+                let rest = if pass + 1 < passes { padding + gap_secs } else { padding };
+                if rest > 1e-6 {
+                    looped.push((0.0, rest));
+                }
+            }
+            looped
+        })
+        .collect()
+}
+
+// Keep only what sounds before `secs`, cutting short a note still ringing then.
+fn truncate(song: &[(f32, f32)], secs: f32) -> Vec<(f32, f32)> {
+    let mut elapsed = 0.0;
+    let mut truncated = Vec::new();
+    for &(freq, dur) in song {
+        if elapsed >= secs {
+            break;
+        }
+        truncated.push((freq, dur.min(secs - elapsed)));
+        elapsed += dur;
+    }
+    truncated
+}
+
+// Stop every track for `pause_secs` at `at_secs` into the song, moving all
+// that follows later; a note sounding across that point is split around the
+// pause
+fn hesitate(song: &[(f32, f32)], at_secs: f32, pause_secs: f32) -> Vec<(f32, f32)> {
+    let mut elapsed = 0.0;
+    let mut paused = false;
+    let mut hesitated = Vec::new();
+    for &(freq, dur) in song {
+        if !paused && elapsed + dur > at_secs + 1e-4 {
+            let before = at_secs - elapsed;
+            if before > 1e-4 {
+                hesitated.push((freq, before));
+            }
+            hesitated.push((0.0, pause_secs));
+            hesitated.push((freq, dur - before.max(0.0)));
+            paused = true;
+        } else {
+            hesitated.push((freq, dur));
+        }
+        elapsed += dur;
+    }
+    hesitated
+}
+
+// Play every note and rest as a plain eighth, quarter, half or whole, as a
+// player reading the pitches but not the rhythm: dots are dropped and
+// anything shorter than an eighth is drawn out to one, so a dotted eighth
+// and sixteenth come out as two straight eighths.
+fn flatten_rhythm(song: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    song.iter()
+        .map(|&(freq, dur)| {
+            let plain = [2.0 * H, H, Q].into_iter().find(|&p| dur >= p - 1e-4).unwrap_or(E);
+            (freq, plain)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::{self, Command};
+    use crate::tests::TempDir;
+    use crate::{A4, C3, C4, D4, E4, F4, FADE_OUT_SECS, G4, SAMPLE_RATE, Song, note_timings};
+    use fon::Frame;
+
+    fn song(title: &str) -> Song {
+        Song::all().into_iter().find(|song| song.title == title).expect("A built-in song")
+    }
+
+    // The selection of just `variation` of `title`, at 8 kHz
+    fn selected(title: &str, variation: &str) -> Selection {
+        let songs = Song::all();
+        let titles: Vec<&str> = songs.iter().map(|song| song.title.as_str()).collect();
+        let args = format!("--song {} --variation {} --sample-rate 8000", title, variation);
+        let args: Vec<String> = args.split(' ').map(String::from).collect();
+        let Ok(Command::Generate(selection, _)) = cli::parse(&args, &titles, VARIATIONS) else {
+            panic!("expected a normal run");
+        };
+        selection
+    }
+
+    // `variation` of `title` as it is listed, and its mix, rendered in memory
+    // at 8 kHz
+    fn played(title: &str, variation: &str) -> (VariationInfo, Audio<Ch32, 2>) {
+        let selection = selected(title, variation);
+        let song = song(title);
+        let batch = play(&song, &selection, 0, None).unwrap();
+        batch.infos.into_iter().zip(batch.kept).next().expect("A variation for the song")
+    }
+
+    // Notes written by pitch, as `(frequency, duration)` pairs
+    fn in_hz(notes: &[(Pitch, f32)]) -> Vec<(f32, f32)> {
+        notes.iter().map(|&(pitch, duration)| (pitch.to_freq(), duration)).collect()
+    }
+
+    // Every track's (frequency, duration) pairs as an entry lists them
+    fn durations(info: &VariationInfo) -> Vec<Vec<(f32, f32)>> {
+        info.notes.iter().map(|t| t.iter().map(|n| (n.freq, n.duration)).collect()).collect()
+    }
+
+    #[test]
+    fn a_pure_tone_measures_perfectly_against_itself() {
+        let len = SAMPLE_RATE as usize;
+        let frames: Vec<Frame<Ch16, 2>> = (0..len)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                let sample = Ch16::from(0.5 * (std::f32::consts::TAU * 440.0 * t).sin());
+                Frame::<Ch16, 2>::new(sample, sample)
+            })
+            .collect();
+        let tone = Audio::with_frames(SAMPLE_RATE, frames);
+        let (tempo, pitch) = measured_accuracy(&tone, &[vec![(0, len, 440.0)]]);
+        assert_eq!(pitch, 1.0);
+        // Its one onset is heard within a few milliseconds of the start
+        assert!(tempo > 0.9, "{}", tempo);
+        // A semitone off is a wrong note
+        let (_, pitch) = measured_accuracy(&tone, &[vec![(0, len, 466.16)]]);
+        assert_eq!(pitch, 0.0);
+    }
+
+    #[test]
+    fn transposing_an_octave_doubles_every_pitch() {
+        let mut melody = song("fur_elise").tracks.swap_remove(0);
+        melody.push((0.0, Q));
+        let up = transpose(&melody, 12);
+        for (&(freq, dur), &(up_freq, up_dur)) in melody.iter().zip(&up) {
+            assert_eq!(up_dur, dur);
+            assert_eq!(up_freq, freq * 2.0, "{} Hz", freq);
+        }
+        // And back down again, rests untouched on both trips
+        let back = transpose(&up, -12);
+        assert_eq!(back, melody);
+        assert_eq!(up.last(), Some(&(0.0, Q)));
+    }
+
+    #[test]
+    fn wrong_notes_keep_the_rhythm() {
+        let (original, _) = played("greensleeves", "original");
+        let (wrong, _) = played("greensleeves", "wrong_notes");
+        let (original, wrong) = (durations(&original), durations(&wrong));
+        let pairs = original[0].iter().zip(&wrong[0]);
+        assert_eq!(original[0].len(), wrong[0].len());
+        let mut changed = 0;
+        for (&(freq, dur), &(wrong_freq, wrong_dur)) in pairs {
+            assert_eq!(wrong_dur, dur);
+            if wrong_freq != freq {
+                // A key or two up
+                let semitones = 12.0 * (wrong_freq / freq).log2();
+                let whole = (semitones - semitones.round()).abs() < 0.01;
+                assert!(whole && (1.0..=2.0).contains(&semitones.round()), "{}", semitones);
+                changed += 1;
+            }
+        }
+        assert!(changed > 0, "no note was changed");
+        assert_eq!(original[1..], wrong[1..]);
+    }
+
+    #[test]
+    fn an_inserted_note_keeps_the_length_to_the_sample() {
+        let (original, original_mix) = played("fur_elise", "original");
+        let (inserted, inserted_mix) = played("fur_elise", "extra_melody_inserted");
+        assert_eq!(inserted.expected_extra_notes, 2);
+        assert_eq!(inserted.notes[0].len(), original.notes[0].len() + 2);
+        let end = |info: &VariationInfo| info.onsets_samples[0].last().unwrap().1;
+        assert!(end(&inserted).abs_diff(end(&original)) <= 1);
+        assert!(inserted_mix.len().abs_diff(original_mix.len()) <= 1);
+    }
+
+    #[test]
+    fn jittered_notes_never_overlap() {
+        let melody = song("greensleeves").tracks.swap_remove(0);
+        for seed in 0..20 {
+            let jittered: Vec<Note> =
+                jitter(&melody, JITTER_SD * 3.0, seed).into_iter().map(Note::from).collect();
+            let slots = note_timings(&jittered, 1.0, SAMPLE_RATE);
+            for pair in slots.windows(2) {
+                let ((start, end, _), (next_start, ..)) = (pair[0], pair[1]);
+                assert!(start < end, "seed {}: an empty note at {}", seed, start);
+                assert_eq!(next_start, end, "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn stopping_early_renders_only_the_truncated_song_and_the_tail() {
+        let (original, _) = played("fur_elise", "original");
+        let (stopped, mix) = played("fur_elise", "stops_early");
+        let secs = |info: &VariationInfo| {
+            let track_secs = info.notes.iter().map(|t| t.iter().map(|n| n.duration).sum::<f32>());
+            track_secs.fold(0.0, f32::max)
+        };
+        let kept = (original.notes[0].len() as f32 * STOP_EARLY_FRACTION).round() as usize;
+        let played: f32 = original.notes[0][..kept].iter().map(|n| n.duration).sum();
+        assert!((secs(&stopped) - played).abs() < 1e-3, "{} {}", secs(&stopped), played);
+        // The piano's release is empty, so only the fade-out follows
+        let expected = (8000.0 * (secs(&stopped) + FADE_OUT_SECS)).ceil() as usize;
+        assert!(mix.len().abs_diff(expected) <= 1, "{} {}", mix.len(), expected);
+    }
+
+    #[test]
+    fn each_stutter_is_heard_as_one_extra_onset() {
+        for title in ["ode_to_joy", "greensleeves"] {
+            let (_, original) = played(title, "original");
+            let (stuttered, mix) = played(title, "stutter");
+            // The default frames are sized for 48 kHz; scale them to 8 kHz
+            let config = analyze::OnsetConfig { hop: 43, frame: 171, ..Default::default() };
+            let onsets = |audio: &Audio<Ch32, 2>| {
+                let audio = Audio::<Ch16, 2>::with_audio(8000, audio);
+                analyze::detect_onsets_with(&audio, config).len()
+            };
+            let extra = stuttered.stutter_positions.len();
+            assert!(extra > 0, "{}", title);
+            assert_eq!(onsets(&mix), onsets(&original) + extra, "{}", title);
+        }
+    }
+
+    #[test]
+    fn each_entry_names_its_song_and_tempo() {
+        let dir = TempDir::new("entry_metadata");
+        let song = song("greensleeves");
+        let selection = selected("greensleeves", "original");
+        let infos = generate_variations(&song, &selection, 0, None, dir.dir());
+        let entry = serde_json::to_value(&infos.unwrap()[0]).unwrap();
+        assert_eq!(entry["title"], "greensleeves");
+        let bpm = entry["default_bpm"].as_f64().unwrap();
+        assert!((bpm - f64::from(song.default_bpm)).abs() < 1e-3, "{}", bpm);
+    }
+
+    #[test]
+    fn every_note_of_a_pitch_class_is_detuned_alike() {
+        let score: Vec<(f32, f32)> = song("fur_elise").tracks.concat();
+        let detuned = detune(&score, &detune_offsets(DETUNE_MAX_CENTS, DETUNE_SEED));
+        let mut by_class: [Option<f32>; 12] = [None; 12];
+        for (&(freq, _), &(detuned_freq, _)) in score.iter().zip(&detuned) {
+            if freq <= 0.0 {
+                assert_eq!(detuned_freq, freq);
+                continue;
+            }
+            let cents = 1200.0 * (detuned_freq / freq).log2();
+            assert!(cents.abs() <= DETUNE_MAX_CENTS + 0.01, "{}", cents);
+            let class = (freq_to_midi(freq).round() as i32).rem_euclid(12) as usize;
+            let first = *by_class[class].get_or_insert(cents);
+            assert!((cents - first).abs() < 0.01, "{} Hz: {} then {}", freq, first, cents);
+        }
+        // The classes are not all moved together, like a transposition
+        let offsets: Vec<f32> = by_class.into_iter().flatten().collect();
+        assert!(offsets.iter().any(|&cents| (cents - offsets[0]).abs() > 1.0));
+    }
+
+    #[test]
+    fn a_crescendo_gets_louder_and_a_decrescendo_softer() {
+        for (name, rising) in [("crescendo", true), ("decrescendo", false)] {
+            let (info, mix) = played("ode_to_joy", name);
+            let samples: Vec<f32> =
+                mix.iter().map(|frame| f32::from(frame.channels()[0])).collect();
+            // RMS over the melody's slots from the `from`th to the `to`th quarter
+            let slots = &info.onsets_samples[0];
+            let rms = |from: usize, to: usize| {
+                let quarter = &slots[slots.len() * from / 4..slots.len() * to / 4];
+                let (start, end) = (quarter[0].0, quarter[quarter.len() - 1].1);
+                let power: f32 = samples[start..end].iter().map(|x| x * x).sum();
+                (power / (end - start) as f32).sqrt()
+            };
+            let (first, last) = (rms(0, 1), rms(3, 4));
+            assert_eq!(last > first, rising, "{}: {} then {}", name, first, last);
+            assert!(first.max(last) > 1.5 * first.min(last), "{}: {} then {}", name, first, last);
+        }
+    }
+
+    #[test]
+    fn swing_lengthens_each_eighth_on_the_beat() {
+        // A pair on the beat, a dotted quarter, an eighth off the beat, a
+        // pair on the beat and a lone eighth at the end
+        let song = in_hz(&[(C4, E), (D4, E), (E4, 3.0 * E), (F4, E), (G4, E), (E4, E), (D4, E)]);
+        assert_eq!(swing(&song, 1.0), song);
+
+        let swung = swing(&song, 2.0);
+        for i in [0, 4] {
+            assert!((swung[i].1 - 2.0 * swung[i + 1].1).abs() < 1e-5, "{}", i);
+            assert!((swung[i].1 + swung[i + 1].1 - 2.0 * E).abs() < 1e-5, "{}", i);
+        }
+        for i in [2, 3, 6] {
+            assert_eq!(swung[i], song[i]);
+        }
+        assert!(swung.iter().zip(&song).all(|(played, written)| played.0 == written.0));
+    }
+
+    #[test]
+    fn repeating_plays_every_note_once_a_pass() {
+        let (original, once) = played("greensleeves", "original");
+        let (repeated, looped) = played("greensleeves", "repeated");
+        let pitched = |info: &VariationInfo, track: usize| -> Vec<f32> {
+            info.notes[track].iter().map(|n| n.freq).filter(|&freq| freq > 0.0).collect()
+        };
+        for track in 0..original.notes.len() {
+            assert_eq!(pitched(&repeated, track), pitched(&original, track).repeat(REPEAT_PASSES));
+        }
+        // Each pass, with a gap between passes, and one tail at the end
+        let (passes, tail) = (REPEAT_PASSES as f32, FADE_OUT_SECS * 8000.0);
+        let gap = REPEAT_GAP_SECS * 8000.0;
+        let expected = passes * (once.len() as f32 - tail) + (passes - 1.0) * gap + tail;
+        assert!((looped.len() as f32 - expected).abs() <= passes, "{} {}", looped.len(), expected);
+    }
+
+    #[test]
+    fn humanizing_without_spread_changes_nothing_and_a_seed_repeats() {
+        let melody = song("greensleeves").tracks.swap_remove(0);
+        let plain = |notes: Vec<Note>| -> Vec<(f32, f32, f32)> {
+            notes.iter().map(|n| (n.pitch.to_freq(), n.duration, n.velocity)).collect()
+        };
+        let written = plain(melody.iter().copied().map(Note::from).collect());
+        assert_eq!(plain(humanize(&melody, 0.0, 0.0, 3)), written);
+
+        let take = |seed| plain(humanize(&melody, HUMANIZE_TIMING_SD, HUMANIZE_VELOCITY_SD, seed));
+        assert_eq!(take(3), take(3));
+        assert_ne!(take(3), take(4));
+        assert_ne!(take(3), written);
+    }
+
+    #[test]
+    fn a_ritardando_draws_out_only_the_last_notes() {
+        let (written, _) = played("ode_to_joy", "original");
+        let (slowed, _) = played("ode_to_joy", "ritardando");
+        let (written, slowed) = (durations(&written), durations(&slowed));
+        let stretch: Vec<f32> =
+            slowed[0].iter().zip(&written[0]).map(|(played, score)| played.1 / score.1).collect();
+        let from = stretch.len() - RITARDANDO_NOTES;
+        assert!(stretch[..from].iter().all(|&ratio| (ratio - 1.0).abs() < 1e-4), "{:?}", stretch);
+        // Longer than written, and more so note by note
+        assert!(stretch[from..].windows(2).all(|pair| pair[1] > pair[0]), "{:?}", stretch);
+        assert!(stretch[stretch.len() - 1] > 1.3, "{:?}", stretch);
+    }
+
+    #[test]
+    fn a_strong_accelerando_shortens_every_note() {
+        let even = in_hz(&[(A4, Q); 16]);
+        let rushed = tempo_ramp(&even, 0.0, 16.0 * Q, 0.3);
+        assert!(rushed.windows(2).all(|pair| pair[1].1 < pair[0].1), "{:?}", rushed);
+        // Still sample-contiguous once `Voice` rounds it
+        let notes: Vec<Note> = rushed.into_iter().map(Note::from).collect();
+        let slots = note_timings(&notes, 1.0, SAMPLE_RATE);
+        assert!(slots.windows(2).all(|pair| pair[1].0 == pair[0].1));
+        assert!(slots.iter().all(|&(start, end, _)| start < end));
+    }
+
+    #[test]
+    fn a_hesitation_delays_everything_after_it_by_the_pause() {
+        let (original, written) = played("fur_elise", "original");
+        let (hesitated, paused) = played("fur_elise", "hesitation");
+        let pause = hesitated.pause.expect("The pause is recorded");
+        let shift = (HESITATION_SECS * 8000.0) as usize;
+        assert!(pause.length_samples.abs_diff(shift) <= 1);
+        assert!(paused.len().abs_diff(written.len() + shift) <= 1);
+
+        // Every part's notes start where they did, or a pause later from it on
+        let at = pause.start_sample;
+        let starts = |slots: &[(usize, usize, f32)]| -> Vec<usize> {
+            slots.iter().filter(|slot| slot.2 > 0.0).map(|slot| slot.0).collect()
+        };
+        for (before, after) in original.onsets_samples.iter().zip(&hesitated.onsets_samples) {
+            let (before, after) = (starts(before), starts(after));
+            assert_eq!(before.len(), after.len());
+            for (start, moved) in before.into_iter().zip(after) {
+                let expected = if start < at { start } else { start + shift };
+                assert!(moved.abs_diff(expected) <= 1, "{} moved to {}", start, moved);
+            }
+        }
+    }
+
+    #[test]
+    fn doubled_octaves_sound_each_note_and_the_octave_above() {
+        let (_, written) = played("twinkle", "original");
+        let (doubled, mix) = played("twinkle", "double_octaves");
+        let mono = |audio: &Audio<Ch32, 2>| -> Vec<f32> {
+            audio.iter().map(|f| f32::from(f.channels()[0]) + f32::from(f.channels()[1])).collect()
+        };
+        let (written, mix) = (mono(&written), mono(&mix));
+        // Power at `hz` over one note's slot, by a plain DFT
+        let power = |samples: &[f32], hz: f32| {
+            let step = hz * std::f32::consts::TAU / 8000.0;
+            let (re, im) = samples.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, x)| {
+                (re + x * (step * i as f32).cos(), im + x * (step * i as f32).sin())
+            });
+            re * re + im * im
+        };
+        for &(start, end, freq) in doubled.onsets_samples[0].iter().filter(|slot| slot.2 > 0.0) {
+            let (plain, thick) = (&written[start..end], &mix[start..end]);
+            // The note still there, and far more above it than the piano's
+            // own second partial
+            assert!(power(thick, freq) > 0.25 * power(plain, freq), "{} Hz", freq);
+            let octave = |slot: &[f32]| power(slot, 2.0 * freq) / power(slot, freq);
+            let (plain, thick) = (octave(plain), octave(thick));
+            assert!(thick > 4.0 * plain, "{} Hz: {} against {}", freq, thick, plain);
+        }
+    }
+
+    #[test]
+    fn a_wrong_rhythm_keeps_the_pitches_and_changes_the_lengths() {
+        let (original, _) = played("greensleeves", "original");
+        let (wrong, _) = played("greensleeves", "wrong_rhythm");
+        let (original, wrong) = (durations(&original), durations(&wrong));
+        let pitches = |track: &[(f32, f32)]| -> Vec<f32> { track.iter().map(|n| n.0).collect() };
+        assert_eq!(pitches(&wrong[0]), pitches(&original[0]));
+        let changed = wrong[0].iter().zip(&original[0]).filter(|(a, b)| a.1 != b.1).count();
+        assert!(changed > 0, "every length kept");
+        // Only plain note values are left
+        for &(_, dur) in &wrong[0] {
+            assert!([E, Q, H, 2.0 * H].iter().any(|&plain| (dur - plain).abs() < 1e-4), "{}", dur);
+        }
+        assert_eq!(wrong[1..], original[1..]);
+    }
+
+    #[test]
+    fn a_downbeat_is_louder_than_the_beats_after_it() {
+        let (_, original) = played("ode_to_joy", "original");
+        let (info, accented) = played("ode_to_joy", "downbeat_accent");
+        // RMS over the first quarter of each melody slot
+        let rms = |mix: &Audio<Ch32, 2>| -> Vec<f32> {
+            let samples: Vec<f32> =
+                mix.iter().map(|frame| f32::from(frame.channels()[0])).collect();
+            let slots = info.onsets_samples[0].iter();
+            slots
+                .map(|&(start, end, _)| {
+                    let attack = &samples[start..start + (end - start) / 4];
+                    (attack.iter().map(|x| x * x).sum::<f32>() / attack.len() as f32).sqrt()
+                })
+                .collect()
+        };
+        let gains: Vec<(f32, f32)> = rms(&accented)
+            .into_iter()
+            .zip(rms(&original))
+            .zip(&info.dynamics)
+            .map(|((loud, plain), &multiplier)| (multiplier, loud / plain))
+            .collect();
+        assert!(gains.iter().any(|&(m, _)| m > 1.0), "no downbeat accented");
+        let downbeats = gains.iter().filter(|&&(m, _)| m > 1.0).map(|&(_, g)| g);
+        let others = gains.iter().filter(|&&(m, _)| m == 1.0).map(|&(_, g)| g);
+        let (quietest, loudest) = (downbeats.fold(f32::MAX, f32::min), others.fold(0.0, f32::max));
+        assert!(quietest > loudest, "downbeats from {} but others up to {}", quietest, loudest);
+    }
+
+    #[test]
+    fn one_seed_renders_the_same_bytes_and_another_does_not() {
+        let song = song("ode_to_joy");
+        let selection = selected("ode_to_joy", "jitter");
+        // The jittered file as `seed` renders it
+        let wav_bytes = |seed: u64, run: &str| {
+            let dir = TempDir::new(&format!("seed_{}", run));
+            let infos = generate_variations(&song, &selection, seed, None, dir.dir());
+            std::fs::read(dir.path(&infos.unwrap()[0].filename)).unwrap()
+        };
+        let first = wav_bytes(7, "first");
+        assert!(first == wav_bytes(7, "again"), "seed 7 rendered differently twice");
+        assert!(first != wav_bytes(8, "other"), "seeds 7 and 8 rendered alike");
+    }
+
+    #[test]
+    fn the_late_hand_enters_after_the_other_by_the_offset() {
+        let (_, original) = played("ode_to_joy_harmony", "original");
+        let (info, late) = played("ode_to_joy_harmony", "one_hand_late");
+        let first_onset = |track: &[(usize, usize, f32)]| {
+            track.iter().find(|&&(_, _, freq)| freq > 0.0).expect("A sounding note").0
+        };
+        let lag = (ONE_HAND_LATE_SECS * 8000.0).round() as usize;
+        assert_eq!(first_onset(&info.onsets_samples[1]), 0);
+        assert_eq!(first_onset(&info.onsets_samples[0]), lag);
+
+        // The melody's opening E4 against the bass's C3 before the melody is due
+        let e4_over_c3 = |audio: &Audio<Ch32, 2>| {
+            let opening: Vec<f32> =
+                audio.iter().take(lag).map(|f| f32::from(f.channels()[0])).collect();
+            let power = |hz: f32| {
+                let step = hz * std::f32::consts::TAU / 8000.0;
+                let (re, im) = opening.iter().enumerate().fold((0.0, 0.0), |(re, im), (i, x)| {
+                    (re + x * (step * i as f32).cos(), im + x * (step * i as f32).sin())
+                });
+                re * re + im * im
+            };
+            power(E4.to_freq()) / power(C3.to_freq())
+        };
+        let (together, late) = (e4_over_c3(&original), e4_over_c3(&late));
+        assert!(late < 0.1 * together, "{} against {}", late, together);
+    }
+
+    #[test]
+    fn a_wrong_length_changes_how_long_a_note_sounds_but_not_its_onset() {
+        let (original, written) = played("ode_to_joy", "original");
+        let (info, wrong) = played("ode_to_joy", "wrong_lengths");
+        assert_eq!(info.onsets_samples, original.onsets_samples);
+        assert!(!info.duration_errors.is_empty());
+        let mono = |audio: &Audio<Ch32, 2>| -> Vec<f32> {
+            audio.iter().map(|f| f32::from(f.channels()[0])).collect()
+        };
+        let (written, wrong) = (mono(&written), mono(&wrong));
+        // RMS of the change over `from..to`, against the written note's RMS
+        let change = |from: usize, to: usize| {
+            let difference: f32 = (from..to).map(|i| (wrong[i] - written[i]).powi(2)).sum();
+            let note: f32 = written[from..to].iter().map(|x| x * x).sum();
+            (difference / note).sqrt()
+        };
+        for error in &info.duration_errors {
+            let (start, end, _) = info.onsets_samples[0][error.note];
+            let len = end - start;
+            // Struck as written, then silent for the second half of a note cut
+            // short, or ringing under the next note after one held over
+            let attack = change(start, start + len / 8);
+            assert!(attack < 0.05, "note {}: attack changed by {}", error.note, attack);
+            let (after, least) = if error.change_secs < 0.0 {
+                (change(start + len / 2, end), 0.5)
+            } else {
+                (change(end, end + len / 2), 0.1)
+            };
+            assert!(after > least, "note {}: changed by only {}", error.note, after);
+        }
+    }
+}
@@ -1,62 +1,443 @@
-//! Minimal WAV file writer.
+//! Minimal WAV file reader and writer.
 //!
 //! http://www-mmsp.ece.mcgill.ca/Documents/AudioFormats/WAVE/WAVE.html
 
-use fon::Audio;
-use fon::chan::Ch16;
-use fon::pos::{Left, Right};
+use fon::chan::{Ch16, Ch24, Ch32, Channel};
+use fon::{Audio, Frame};
 use std::convert::TryInto;
-use std::{fs, io, mem::size_of};
+use std::{fs, io};
 
-/// Write a 16-bit PCM WAV file
-pub(super) fn write(audio: Audio<Ch16, 2>, filename: &str) -> io::Result<()> {
+/// Sample encoding of a WAV file.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(super) enum BitDepth {
+    #[default]
+    Int16,
+    Int24,
+    Float32,
+}
+
+impl BitDepth {
+    /// The depth `--bit-depth` and the build plan call `name`: "16", "24" or
+    /// "float".
+    pub(super) fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "16" => Some(BitDepth::Int16),
+            "24" => Some(BitDepth::Int24),
+            "float" => Some(BitDepth::Float32),
+            _ => None,
+        }
+    }
+
+    /// The inverse of [`BitDepth::from_name`], for the manifest.
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            BitDepth::Int16 => "16",
+            BitDepth::Int24 => "24",
+            BitDepth::Float32 => "float",
+        }
+    }
+
+    // fmt chunk AudioFormat: 1 = integer PCM, 3 = IEEE float
+    fn format_tag(self) -> u16 {
+        match self {
+            BitDepth::Int16 | BitDepth::Int24 => 0x0001,
+            BitDepth::Float32 => 0x0003,
+        }
+    }
+
+    fn bytes(self) -> usize {
+        match self {
+            BitDepth::Int16 => 2,
+            BitDepth::Int24 => 3,
+            BitDepth::Float32 => 4,
+        }
+    }
+
+    // Non-PCM formats carry a cbSize field, and a fact chunk after the fmt one
+    fn is_float(self) -> bool {
+        self == BitDepth::Float32
+    }
+
+    // fmt chunk size: the 16 bytes every format has, plus cbSize for float
+    fn fmt_size(self) -> u32 {
+        if self.is_float() { 18 } else { 16 }
+    }
+}
+
+/// Write a stereo WAV file
+pub(super) fn write<Chan>(audio: &Audio<Chan, 2>, filename: &str, depth: BitDepth) -> io::Result<()>
+where
+    Chan: Channel,
+    Ch16: From<Chan>,
+    Ch24: From<Chan>,
+{
+    write_pcm(audio, filename, depth)
+}
+
+/// Write a 16-bit PCM mono WAV file
+pub(super) fn write_mono(audio: &Audio<Ch16, 1>, filename: &str) -> io::Result<()> {
+    write_pcm(audio, filename, BitDepth::Int16)
+}
+
+fn write_pcm<Chan, const CH: usize>(
+    audio: &Audio<Chan, CH>,
+    filename: &str,
+    depth: BitDepth,
+) -> io::Result<()>
+where
+    Chan: Channel,
+    Ch16: From<Chan>,
+    Ch24: From<Chan>,
+{
+    fs::write(filename, encode(audio, depth)?)
+}
+
+/// The bytes of a WAV file of `audio`: the RIFF header, then the fmt, fact
+/// (float only) and data chunks. Fails with `InvalidInput` if the file would
+/// be too big for the header's 32-bit sizes, at about 4 GiB.
+pub(super) fn encode<Chan, const CH: usize>(
+    audio: &Audio<Chan, CH>,
+    depth: BitDepth,
+) -> io::Result<Vec<u8>>
+where
+    Chan: Channel,
+    Ch16: From<Chan>,
+    Ch24: From<Chan>,
+{
+    let (data, frames) = sizes(depth, CH, audio.len())?;
     let mut buf = vec![];
-    write_header(&mut buf, &audio);
-    write_fmt_header(&mut buf, &audio);
-    write_audio_data(&mut buf, &audio);
-    fs::write(filename, buf)
+    write_header(&mut buf, data, depth);
+    write_fmt_header(&mut buf, audio, depth);
+    if depth.is_float() {
+        write_fact(&mut buf, frames);
+    }
+    write_audio_data(&mut buf, audio, data, depth);
+    Ok(buf)
+}
+
+// Size in bytes of everything in the RIFF chunk but the data: "WAVE", then
+// "fmt " and "fact" with their 8-byte headers, and the data's header
+fn riff_overhead(depth: BitDepth) -> u32 {
+    let fact = if depth.is_float() { 8 + 4 } else { 0 };
+    4 + (8 + depth.fmt_size()) + fact + 8
+}
+
+// The data chunk's size in bytes and the frame count, each as the header
+// writes it, if the whole file's size fits in the RIFF chunk's 32 bits
+fn sizes(depth: BitDepth, channels: usize, frames: usize) -> io::Result<(u32, u32)> {
+    let data = channels.checked_mul(depth.bytes()).and_then(|size| size.checked_mul(frames));
+    let data = data.and_then(|data| u32::try_from(data).ok());
+    // The data's pad byte if it is odd
+    let fits = |&data: &u32| data.checked_add((data & 1) + riff_overhead(depth)).is_some();
+    match (data.filter(fits), u32::try_from(frames)) {
+        (Some(data), Ok(frames)) => Ok((data, frames)),
+        _ => {
+            let message = format!("{} frames are too many for a WAV file", frames);
+            Err(io::Error::new(io::ErrorKind::InvalidInput, message))
+        }
+    }
 }
 
-fn write_header(buf: &mut Vec<u8>, audio: &Audio<Ch16, 2>) {
-    // Predict size of WAV subchunks.
-    let n: u32 = audio.len().try_into().unwrap();
+fn write_header(buf: &mut Vec<u8>, data: u32, depth: BitDepth) {
     // RIFF Chunk: ckID
     buf.extend(b"RIFF");
     // RIFF Chunk: cksize
-    buf.extend(&(36u32 + n).to_le_bytes());
+    buf.extend(&(riff_overhead(depth) + data + (data & 1)).to_le_bytes());
     // RIFF Chunk: WAVEID
     buf.extend(b"WAVE");
 }
 
-fn write_fmt_header(buf: &mut Vec<u8>, audio: &Audio<Ch16, 2>) {
+fn write_fmt_header<Chan: Channel, const CH: usize>(
+    buf: &mut Vec<u8>,
+    audio: &Audio<Chan, CH>,
+    depth: BitDepth,
+) {
+    let block_align = (depth.bytes() * CH) as u16;
     // RIFF Subchunk: "fmt "
     buf.extend(b"fmt ");
     // Chunk size: 16, 18 or 40
-    buf.extend(&(16u32).to_le_bytes());
-    // 0: WAVE_FORMAT_PCM
-    buf.extend(&(0x0001u16).to_le_bytes());
-    // 2: Stereo
-    buf.extend(&(2u16).to_le_bytes());
+    buf.extend(&depth.fmt_size().to_le_bytes());
+    // 0: WAVE_FORMAT_PCM or WAVE_FORMAT_IEEE_FLOAT
+    buf.extend(&depth.format_tag().to_le_bytes());
+    // 2: Channels
+    buf.extend(&(CH as u16).to_le_bytes());
     // 4: Sampling Rate
     buf.extend(&u32::from(audio.sample_rate()).to_le_bytes());
-    // 8: Bytes per second (i16 * 2 * sample rate)
-    buf.extend(&(4 * u32::from(audio.sample_rate())).to_le_bytes());
-    // 12. Data block size (bytes: i16 * 2)
-    buf.extend(&(size_of::<u16>() as u16 * 2u16).to_le_bytes());
+    // 8: Bytes per second (sample size * channels * sample rate)
+    buf.extend(&(block_align as u32 * u32::from(audio.sample_rate())).to_le_bytes());
+    // 12. Data block size (bytes: sample size * channels)
+    buf.extend(&block_align.to_le_bytes());
     // 14. Bits per sample
-    buf.extend(&(16u16).to_le_bytes());
+    buf.extend(&(8 * depth.bytes() as u16).to_le_bytes());
+    if depth.is_float() {
+        // 16. cbSize: no extension follows
+        buf.extend(&0u16.to_le_bytes());
+    }
+}
+
+fn write_fact(buf: &mut Vec<u8>, frames: u32) {
+    // RIFF Subchunk: "fact"
+    buf.extend(b"fact");
+    // cksize: 4
+    buf.extend(&4u32.to_le_bytes());
+    // dwSampleLength: frames per channel
+    buf.extend(&frames.to_le_bytes());
 }
 
-fn write_audio_data(buf: &mut Vec<u8>, audio: &Audio<Ch16, 2>) {
+fn write_audio_data<Chan, const CH: usize>(
+    buf: &mut Vec<u8>,
+    audio: &Audio<Chan, CH>,
+    data: u32,
+    depth: BitDepth,
+) where
+    Chan: Channel,
+    Ch16: From<Chan>,
+    Ch24: From<Chan>,
+{
     // RIFF Subchunk: "data"
     buf.extend(b"data");
-    // cksize (Bytes): Stereo (2) * i16 (2) * Frame Length
-    buf.extend(&(4 * audio.len() as u32).to_le_bytes());
-    // Sampled data
+    // cksize (Bytes): Channels * sample size * Frame Length
+    buf.extend(&data.to_le_bytes());
+    // Sampled data, channels interleaved (left then right for stereo)
     for frame in audio.iter() {
-        // Add left channel
-        buf.extend(&i16::from(frame[Left]).to_le_bytes());
-        // Add right channel
-        buf.extend(&i16::from(frame[Right]).to_le_bytes());
+        for &sample in frame.channels() {
+            match depth {
+                BitDepth::Int16 => buf.extend(&i16::from(Ch16::from(sample)).to_le_bytes()),
+                // Low three bytes of the little-endian i32
+                BitDepth::Int24 => buf.extend(&i32::from(Ch24::from(sample)).to_le_bytes()[..3]),
+                BitDepth::Float32 => buf.extend(&sample.to_f32().to_le_bytes()),
+            }
+        }
+    }
+    // Chunks are word-aligned, so odd-sized 24-bit mono data is padded
+    if data & 1 == 1 {
+        buf.push(0);
+    }
+}
+
+/// Read a stereo WAV file of any supported [`BitDepth`]
+pub(super) fn read<Chan: Channel>(filename: &str) -> io::Result<Audio<Chan, 2>> {
+    read_pcm(filename)
+}
+
+/// Read a mono WAV file of any supported [`BitDepth`]
+pub(super) fn read_mono<Chan: Channel>(filename: &str) -> io::Result<Audio<Chan, 1>> {
+    read_pcm(filename)
+}
+
+fn read_pcm<Chan: Channel, const CH: usize>(filename: &str) -> io::Result<Audio<Chan, CH>> {
+    let buf = fs::read(filename)?;
+    if buf.len() < 12 || &buf[0..4] != b"RIFF" || &buf[8..12] != b"WAVE" {
+        return Err(invalid("not a RIFF/WAVE file"));
+    }
+
+    let mut format = None;
+    let mut pos = 12;
+    // Walk the subchunks; anything other than "fmt " and "data" is skipped.
+    while pos + 8 <= buf.len() {
+        let id = &buf[pos..pos + 4];
+        let size = u32::from_le_bytes(buf[pos + 4..pos + 8].try_into().unwrap()) as usize;
+        let body = buf
+            .get(pos + 8..pos + 8 + size)
+            .ok_or_else(|| invalid("truncated chunk"))?;
+        match id {
+            b"fmt " => format = Some(read_fmt_header(body, CH as u16)?),
+            b"data" => {
+                let (hz, depth) = format.ok_or_else(|| invalid("data chunk before fmt chunk"))?;
+                return read_audio_data(body, hz, depth);
+            }
+            _ => {}
+        }
+        // Chunks are word-aligned: odd sizes are followed by a pad byte.
+        pos += 8 + size + (size & 1);
+    }
+    Err(invalid("missing data chunk"))
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Validate the format and return the sample rate and encoding
+fn read_fmt_header(body: &[u8], expected_channels: u16) -> io::Result<(u32, BitDepth)> {
+    if body.len() < 16 {
+        return Err(invalid("fmt chunk too short"));
+    }
+    let field16 = |at: usize| u16::from_le_bytes([body[at], body[at + 1]]);
+    let format = field16(0);
+    let channels = field16(2);
+    let sample_rate = u32::from_le_bytes(body[4..8].try_into().unwrap());
+    let bits = field16(14);
+    let depth = match (format, bits) {
+        (0x0001, 16) => BitDepth::Int16,
+        (0x0001, 24) => BitDepth::Int24,
+        (0x0003, 32) => BitDepth::Float32,
+        _ => return Err(invalid("only 16/24-bit PCM and 32-bit float are supported")),
+    };
+    if channels != expected_channels {
+        return Err(invalid(if expected_channels == 1 {
+            "expected a mono file"
+        } else {
+            "expected a stereo file"
+        }));
+    }
+    if sample_rate == 0 {
+        return Err(invalid("sample rate is zero"));
+    }
+    Ok((sample_rate, depth))
+}
+
+fn read_audio_data<Chan: Channel, const CH: usize>(
+    body: &[u8],
+    sample_rate: u32,
+    depth: BitDepth,
+) -> io::Result<Audio<Chan, CH>> {
+    let frame_bytes = CH * depth.bytes();
+    if !body.len().is_multiple_of(frame_bytes) {
+        return Err(invalid("data chunk is not a whole number of frames"));
+    }
+    let frames: Vec<Frame<Chan, CH>> = body
+        .chunks_exact(frame_bytes)
+        .map(|block| {
+            let mut frame = Frame::default();
+            let samples = block.chunks_exact(depth.bytes());
+            for (chan, b) in frame.channels_mut().iter_mut().zip(samples) {
+                *chan = match depth {
+                    BitDepth::Int16 => Ch16::new(i16::from_le_bytes([b[0], b[1]])).into(),
+                    // Shift up then back down to sign-extend the top byte
+                    BitDepth::Int24 => {
+                        Ch24::new(i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8).into()
+                    }
+                    BitDepth::Float32 => {
+                        Ch32::new(f32::from_le_bytes([b[0], b[1], b[2], b[3]])).into()
+                    }
+                };
+            }
+            frame
+        })
+        .collect();
+    Ok(Audio::with_frames(sample_rate, frames))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TempDir;
+    use crate::variations::downmix;
+
+    fn le32(bytes: &[u8]) -> usize {
+        u32::from_le_bytes(bytes.try_into().unwrap()) as usize
+    }
+
+    // Each chunk's id and size, checking they tile the file exactly
+    fn chunks(buf: &[u8]) -> Vec<(&[u8], usize)> {
+        assert_eq!(&buf[0..4], b"RIFF");
+        assert_eq!(le32(&buf[4..8]), buf.len() - 8);
+        let mut chunks = Vec::new();
+        let mut pos = 12;
+        while pos < buf.len() {
+            let size = le32(&buf[pos + 4..pos + 8]);
+            chunks.push((&buf[pos..pos + 4], size));
+            pos += 8 + size + (size & 1);
+        }
+        assert_eq!(pos, buf.len());
+        chunks
+    }
+
+    // A short stereo ramp, so every sample differs
+    fn ramp(frames: usize) -> Audio<Ch32, 2> {
+        let frames: Vec<_> = (0..frames)
+            .map(|i| {
+                let x = i as f32 / frames as f32 * 1.6 - 0.8;
+                Frame::<Ch32, 2>::new(Ch32::new(x), Ch32::new(-x / 2.0))
+            })
+            .collect();
+        Audio::with_frames(44_100, frames)
+    }
+
+    #[test]
+    fn chunk_sizes_add_up_to_the_file() {
+        let audio = Audio::<Ch16, 2>::with_silence(48_000, 1_000);
+        let buf = encode(&audio, BitDepth::Int16).unwrap();
+        let chunks = chunks(&buf);
+        assert_eq!(chunks, [(&b"fmt "[..], 16), (&b"data"[..], 1_000 * 2 * 2)]);
+    }
+
+    #[test]
+    fn float_files_have_a_cb_size_and_a_fact_chunk() {
+        let buf = encode(&ramp(100), BitDepth::Float32).unwrap();
+        let chunks = chunks(&buf);
+        assert_eq!(chunks, [(&b"fmt "[..], 18), (&b"fact"[..], 4), (&b"data"[..], 100 * 2 * 4)]);
+        // cbSize, then the fact chunk's frame count
+        assert_eq!(&buf[36..38], [0, 0]);
+        assert_eq!(le32(&buf[46..50]), 100);
+    }
+
+    #[test]
+    fn odd_sized_data_is_padded() {
+        let audio = Audio::<Ch24, 1>::with_silence(8_000, 3);
+        let buf = encode(&audio, BitDepth::Int24).unwrap();
+        assert_eq!(chunks(&buf), [(&b"fmt "[..], 16), (&b"data"[..], 9)]);
+    }
+
+    #[test]
+    fn every_depth_reads_back() {
+        let audio = ramp(500);
+        // One step of each integer depth, and exact for float
+        for (depth, tolerance) in [
+            (BitDepth::Int16, 1.0 / 32_767.0),
+            (BitDepth::Int24, 1.0 / 8_388_607.0),
+            (BitDepth::Float32, 0.0),
+        ] {
+            let path = std::env::temp_dir().join(format!("wav_test_{}.wav", depth.name()));
+            let path = path.to_str().unwrap();
+            write(&audio, path, depth).unwrap();
+            let back = read::<Ch32>(path).unwrap();
+            fs::remove_file(path).unwrap();
+            assert_eq!(back.sample_rate().get(), 44_100);
+            assert_eq!(back.len(), audio.len());
+            for (a, b) in audio.iter().zip(back.iter()) {
+                for (x, y) in a.channels().iter().zip(b.channels()) {
+                    let (x, y) = (x.to_f32(), y.to_f32());
+                    assert!((x - y).abs() <= tolerance, "{:?}: {} read back as {}", depth, x, y);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn the_mono_copy_is_one_channel_of_the_average() {
+        let frames: Vec<_> = ramp(501).iter().map(|&frame| frame.to()).collect();
+        let stereo = Audio::<Ch16, 2>::with_frames(44_100, frames);
+        let dir = TempDir::new("wav_mono");
+        let path = &dir.path("mono.wav");
+        write_mono(&downmix(&stereo), path).unwrap();
+        let buf = fs::read(path).unwrap();
+
+        assert_eq!(chunks(&buf), [(&b"fmt "[..], 16), (&b"data"[..], 501 * 2)]);
+        // NumChannels, then byte rate and block align for 2-byte samples
+        assert_eq!(u16::from_le_bytes([buf[22], buf[23]]), 1);
+        assert_eq!(le32(&buf[28..32]), 44_100 * 2);
+        assert_eq!(u16::from_le_bytes([buf[32], buf[33]]), 2);
+        for (frame, sample) in stereo.iter().zip(buf[44..].chunks(2)) {
+            let (left, right) = (i16::from(frame.channels()[0]), i16::from(frame.channels()[1]));
+            let average = ((i32::from(left) + i32::from(right)) / 2) as i16;
+            assert_eq!(i16::from_le_bytes([sample[0], sample[1]]), average);
+        }
+    }
+
+    #[test]
+    fn files_past_the_32_bit_sizes_are_refused() {
+        // The largest 16-bit stereo data whose RIFF size still fits
+        let most = (u32::MAX - riff_overhead(BitDepth::Int16)) as usize / 4;
+        assert_eq!(sizes(BitDepth::Int16, 2, most).unwrap(), (most as u32 * 4, most as u32));
+        for (depth, channels, frames) in [
+            (BitDepth::Int16, 2, most + 1),
+            (BitDepth::Float32, 2, 1 << 30),
+            (BitDepth::Int24, 1, usize::MAX),
+        ] {
+            let err = sizes(depth, channels, frames).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{:?} {}", depth, frames);
+        }
     }
 }
@@ -30,7 +30,12 @@ def get_available_tests():
         raise FileNotFoundError(f"Could not find test configuration at {json_path}")
 
     with open(json_path, 'r') as f:
-        return json.load(f)
+        manifest = json.load(f)
+
+    # Schema version 1 was a bare list; later versions wrap it with metadata
+    if isinstance(manifest, list):
+        return manifest
+    return manifest["tests"]
 
 def extract_note_arrays(test_case):
     """